@@ -2,27 +2,10 @@ use std::collections::HashMap;
 use std::env;
 use std::process;
 
-use anyhow::{Error, anyhow};
 use cranelift_codegen::ir::Block;
 use cranelift_codegen::ir::Function;
 use cranelift_codegen::ir::Inst;
-use cranelift_codegen::isa::{CallConv, TargetFrontendConfig};
-use cranelift_wasm::translate_module;
-use cranelift_wasm::DummyEnvironment;
-use target_lexicon::PointerWidth;
-
-/// Translates single instruction WASM module into CLIR (Cranelift IR)
-fn translate_wasm_module(data: &[u8]) -> Result<Vec<Function>, Error> {
-    let mut dummy_environment = DummyEnvironment::new(TargetFrontendConfig {
-        default_call_conv: CallConv::SystemV,
-        pointer_width: PointerWidth::U64,
-    });
-
-    match translate_module(data, &mut dummy_environment) {
-        Ok(_) => Ok(dummy_environment.info.function_bodies.into_iter().map(|(_, f)| f).collect()),
-        Err(error) => Err(anyhow!("translate_data failed with {:?}", error)),
-    }
-}
+use extractor::wasm_env::{translate_module_functions, TranslateEnv};
 
 fn postprocess_block(func: &Function, block: &Block) -> Vec<Inst> {
     let mut insts: Vec<Inst> = Vec::new();
@@ -37,15 +20,19 @@ fn postprocess_block(func: &Function, block: &Block) -> Vec<Inst> {
 
 fn main() {
     let args: Vec<_> = env::args().collect();
-    if args.len() != 2 {
-        println!("[*] {} <filename>", args[0]);
-        process::exit(1);
-    }
+    let env = match (args.len(), args.get(2).map(|x| x.as_str())) {
+        (2, None) => TranslateEnv::default(),
+        (3, Some("--dummy-env")) => TranslateEnv::Dummy,
+        _ => {
+            println!("[*] {} <filename> [--dummy-env]", args[0]);
+            process::exit(1);
+        },
+    };
 
     // file read
     let filename = &args[1];
     let data = std::fs::read(filename).unwrap();
-    let ir_funcs = translate_wasm_module(data.as_slice()).unwrap();
+    let ir_funcs = translate_module_functions(data.as_slice(), &env).unwrap();
 
     // count IR expressions
     let mut count_map = HashMap::new();
@@ -117,6 +117,13 @@ CONTROL_OPCODES = (
     'call_indirect'
 )
 
+# branches whose conditions (or indices) come from extracted rules
+BRANCH_OPCODES = (
+    'if',
+    'br_if',
+    'br_table',
+)
+
 TABLE_OPCODES = ( # not exhaustive
     'table.get',
     'table.set',
@@ -188,6 +195,8 @@ class InstrNode():
         self.ret_types = ret_types
         self.instrs = instrs # list of Instr
         self.conds = {} # dict of rule conditions
+        self.weight = 1.0 # lower for rules using less preferred instructions
+        self.wrap = None # 'block' or 'call' for multi-value rules
     
     @classmethod
     def from_single_instr(cls, instr: Instr): # from single instr
@@ -209,6 +218,12 @@ class RuleProvider():
     def get_rule(self, ty, rng):
         pass
 
+    def get_branch_rule(self, opcode, rng): # rule computing the i32 operand of the branch
+        return None
+
+    def get_multi_rule(self, stack, allow_float, rng): # rule computing the values at the top of the stack
+        return None
+
 class ExtRuleProvider(RuleProvider):
     def __init__(self):
         super().__init__()
@@ -220,7 +235,7 @@ class ExtRuleProvider(RuleProvider):
         extractor_cwd = os.path.join(os.path.dirname(root_dir), "extractor")
         extractor_path = os.path.join(extractor_cwd, "target", "release", "extractor")
         args = [extractor_path]
-        args.append(config.codegen_extractor_option)
+        args.extend(config.codegen_extractor_option.split()) # per-ISA modes may carry flags
         subp = subprocess.Popen(
             args, cwd=extractor_cwd, shell=False,
             stdout=subprocess.PIPE, stderr=subprocess.PIPE
@@ -283,16 +298,36 @@ class ExtRuleProvider(RuleProvider):
     def get_rule(self, ty, rng):
         if ty in self.extracted_rules and not rng.get_choice_prob(config.codegen_prob_use_typing): # extracted rules
             opcode = rng.get_choice_arr(list(self.extracted_rules[ty].keys()))
-            rule = rng.get_choice_arr(self.extracted_rules[ty][opcode])
+            rules = self.extracted_rules[ty][opcode]
+            rule = rng.get_choice_weighted(rules, [x.weight for x in rules])
         else: # typing rules
             assert ty in self.typing_rules
             opcode = rng.get_choice_arr(list(self.typing_rules[ty].keys()))
             rule = rng.get_choice_arr(self.typing_rules[ty][opcode])
         return rule
-            
+
+    @override
+    def get_branch_rule(self, opcode, rng):
+        if not 'branch' in self.extracted_rules or not opcode in self.extracted_rules['branch']:
+            return None
+        if rng.get_choice_prob(config.codegen_prob_use_typing): # plain branch
+            return None
+        rules = self.extracted_rules['branch'][opcode]
+        return rng.get_choice_weighted(rules, [x.weight for x in rules])
+    
+    @override
+    def get_multi_rule(self, stack, allow_float, rng):
+        if not 'multi' in self.extracted_rules:
+            return None
+        rets_candidates = [rets for rets in self.extracted_rules['multi'].keys() if
+            len(rets) <= len(stack) and list(rets) == stack[-len(rets):] and
+            (allow_float or not any(ty in ('f32', 'f64', 'v128') for ty in rets))]
+        if len(rets_candidates) == 0:
+            return None
+        rules = self.extracted_rules['multi'][rng.get_choice_arr(rets_candidates)]
+        return rng.get_choice_weighted(rules, [x.weight for x in rules])
     
     def add_raw_rule(raw_rule, rule_dict):
-        assert len(raw_rule[1]) <= 1 # for now
         param_types = raw_rule[0]
         if len(raw_rule[1]) == 0:
             ret_types = ()
@@ -305,11 +340,31 @@ class ExtRuleProvider(RuleProvider):
         for oparg_idx, oparg_conds in oparg_conds_raw:
             conds[oparg_idx] = ExtRuleProvider.postprocess_conds(oparg_conds)
         
-        ExtRuleProvider.add_rule(param_types, ret_types, raw_rule[2], rule_dict)
+        weight = raw_rule[6] if len(raw_rule) > 6 else 1.0 # 5 is sources
+        wrap = raw_rule[7] if len(raw_rule) > 7 else None # only for multi-value rules
+        # entities of blacklisted types cannot be allocated
+        if any(len(instr.operands) == 1 and type(instr.operands[0]) is tuple and instr.operands[0][0] == "index" and
+               not instr.operands[0][1] in ALLOWED_TYPES for instr in raw_rule[2]):
+            return
+        ExtRuleProvider.add_rule(param_types, ret_types, raw_rule[2], rule_dict, weight, wrap)
     
-    def add_rule(param_types, ret_types, instrs, rule_dict):
+    def add_rule(param_types, ret_types, instrs, rule_dict, weight=1.0, wrap=None):
         if all(map(lambda x: x in ALLOWED_TYPES, param_types)) and all(map(lambda x: x in ALLOWED_TYPES, ret_types)):
-            if len(ret_types) > 0:
+            if len(ret_types) > 1: # multi-value rules, keyed by their result types
+                assert wrap in ('block', 'call')
+                node = InstrNode(param_types, ret_types, instrs)
+                node.conds = {}
+                node.weight = weight
+                node.wrap = wrap
+                rets = tuple(ret_types)
+                if not 'multi' in rule_dict:
+                    rule_dict['multi'] = {rets:[node]}
+                elif not rets in rule_dict['multi']:
+                    rule_dict['multi'][rets] = [node]
+                else:
+                    rule_dict['multi'][rets].append(node)
+                return
+            elif len(ret_types) > 0:
                 node = InstrNode(param_types, ret_types, instrs)
                 node.conds = {}
                 ty = ret_types[0]
@@ -317,8 +372,12 @@ class ExtRuleProvider(RuleProvider):
                 node = InstrNode(param_types, ret_types, instrs)
                 node.conds = {}
                 ty = 'noout'
+            node.weight = weight
             
             last_opcode = instrs[-1].name
+            if last_opcode in BRANCH_OPCODES: # labels and block types are chosen in generation
+                ty = 'branch'
+            
             if last_opcode == 'arg': # ignore this case: unnecessary
                 pass
             elif not ty in rule_dict:
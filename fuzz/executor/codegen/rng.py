@@ -31,6 +31,10 @@ class Rng():
         assert len(arr) > 0
         return arr[0]
     
+    def get_choice_weighted(self, arr, weights):
+        assert len(arr) > 0 and len(arr) == len(weights)
+        return arr[0]
+    
     def get_float(self):
         return 0.0
 
@@ -66,6 +70,11 @@ class RandomRng(Rng):
         assert len(arr) > 0
         return self.rand.choice(arr)
     
+    @override
+    def get_choice_weighted(self, arr, weights):
+        assert len(arr) > 0 and len(arr) == len(weights)
+        return self.rand.choices(arr, weights)[0]
+    
     @override
     def get_float(self):
         val = struct.unpack('f', self.rand.randbytes(4))[0] # float range
@@ -118,6 +127,16 @@ class ConsumeRng(Rng):
         assert len(arr) > 0
         return arr[self.get_choice(len(arr))] # when seed is depleted, first element
     
+    @override
+    def get_choice_weighted(self, arr, weights):
+        assert len(arr) > 0 and len(arr) == len(weights)
+        point = self.get_int(23) / (2**23) * sum(weights)
+        for elem, weight in zip(arr, weights):
+            if point < weight:
+                return elem
+            point -= weight
+        return arr[-1] # rounding error
+    
     @override
     def get_float(self):
         val = struct.unpack('f', self.consume_seed(4))[0] # float range
@@ -180,8 +180,7 @@ class StackGenBlockContext(GenContext):
                         self.records_rev.append(StackRecord(start_stack, InstrNode.from_single_instr(Instr('end', ()))))
                         self.records_rev += if_ctx.records_rev
                         self.stack += if_ctx.params
-                        self.stack.append('i32')
-                        self.records_rev.append(StackRecord(self.stack, InstrNode.from_single_instr(Instr('if', (blocktype,)))))
+                        self.push_branch(rule_provider, Instr('if', (blocktype,)))
                     else:
                         else_ctx = StackGenBlockContext(self.rng, self.globalctx, self.ctx_stack)
                         else_ctx.init_stack(struct_rets)
@@ -194,8 +193,7 @@ class StackGenBlockContext(GenContext):
                         self.records_rev.append(StackRecord(start_stack, InstrNode.from_single_instr(Instr('else', ()))))
                         self.records_rev += if_ctx.records_rev
                         self.stack += if_ctx.params
-                        self.stack.append('i32')
-                        self.records_rev.append(StackRecord(self.stack, InstrNode.from_single_instr(Instr('if', (blocktype,)))))
+                        self.push_branch(rule_provider, Instr('if', (blocktype,)))
                 
                 elif struct_choice == 'unreachable':
                     struct_rets = self.get_struct_stack()
@@ -250,10 +248,10 @@ class StackGenBlockContext(GenContext):
 
                 if len(exit_candidates) > 0 and self.rng.get_choice_prob(config.codegen_stackgen_prob_br_if):
                     exit_idx, exit_ctx = self.rng.get_choice_arr(exit_candidates)
-                    self.gen_br_if(exit_idx, exit_ctx)
+                    self.gen_br_if(exit_idx, exit_ctx, rule_provider)
 
                 elif len(exit_candidates) > 0 and self.rng.get_choice_prob(config.codegen_stackgen_prob_br):
-                    self.gen_br(exit_candidates)
+                    self.gen_br(exit_candidates, rule_provider)
                 else:
                     self.extend_type(rule_provider)
         
@@ -264,6 +262,12 @@ class StackGenBlockContext(GenContext):
     
     def extend_type(self, rule_provider: RuleProvider):
         assert self.init_done and not self.gen_done
+        if len(self.stack) > 1 and self.rng.get_choice_prob(config.codegen_stackgen_prob_multi_value):
+            multi_rule = rule_provider.get_multi_rule(self.stack, not self.canonicalize_nans, self.rng)
+            if multi_rule != None:
+                self.extend_multi_value(multi_rule)
+                return
+
         if len(self.stack) == 0:
             ty = 'noout'
         elif self.rng.get_choice_prob(config.codegen_stackgen_prob_stack_pop):
@@ -275,79 +279,7 @@ class StackGenBlockContext(GenContext):
             chosen_rule = rule_provider.get_rule(ty, self.rng)
             assert (len(chosen_rule.ret_types) == 0 and ty == 'noout') or (len(chosen_rule.ret_types) == 1 and chosen_rule.ret_types[0] == ty)
 
-            # scan through the rule, count number of arg uses
-            args_use_cnt = [0] * len(chosen_rule.param_types)
-            for instr in chosen_rule.instrs:
-                if instr.name == 'arg':
-                    assert len(instr.operands) == 1
-                    arg_idx = instr.operands[0]
-                    assert arg_idx < len(chosen_rule.param_types)
-                    args_use_cnt[arg_idx] += 1
-            
-            # optimize local generation: skip gen if redundant
-            opted_instrs = []
-            local_sets = list(range(len(chosen_rule.param_types) - 1, -1, -1))
-            for idx, instr in enumerate(chosen_rule.instrs):
-                if instr.name == "arg":
-                    assert len(instr.operands) == 1
-                    arg_idx = instr.operands[0]
-                    assert arg_idx < len(chosen_rule.param_types)
-
-                    if args_use_cnt[arg_idx] == 1 and local_sets[-1] == arg_idx:
-                        local_sets.pop()
-                        continue
-                    else:
-                        assert args_use_cnt[arg_idx] > 0
-                        opted_instrs = chosen_rule.instrs[idx:]
-                        break
-                else:
-                    opted_instrs = chosen_rule.instrs[idx:]
-                    break
-            
-            # generate local sets
-            new_instrs = [] # may be empty (there is one case that this is empty)
-            args_store = {} # stores generated args
-            for param_idx in local_sets:
-                param_ty = chosen_rule.param_types[param_idx]
-                local_instrs_tuple = self.ctx_stack[0].alloc_local(param_ty)
-                new_instrs.append(local_instrs_tuple[0])
-                args_store[param_idx] = local_instrs_tuple[1]
-
-            # gen args and operands
-            oparg_store = {} # stores generated opargs
-            for instr in opted_instrs:
-                if instr.name == "arg":
-                    assert len(instr.operands) == 1
-                    arg_idx = instr.operands[0]
-                    assert arg_idx < len(chosen_rule.param_types)
-
-                    new_instrs.append(args_store[arg_idx])
-
-                elif len(instr.operands) > 0 and \
-                        any(oper[0] == "oparg" for oper in instr.operands if type(oper) is tuple):
-                    new_operands = []
-                    for oper_idx, oper in enumerate(instr.operands):
-                        if type(oper) is tuple and oper[0] == "oparg":
-                            assert len(oper) == 2
-                            oparg_idx = oper[1]
-                            if oparg_idx in oparg_store:
-                                new_operands.append(oparg_store[oparg_idx])
-                            else:
-                                instr_info = INSTRUCTIONS[instr.name]
-                                assert oper_idx < len(instr_info.operands)
-                                new_operand = self.gen_operand(instr.name, instr_info.operands[oper_idx], oper_idx, oparg_store, chosen_rule.conds)
-                                # oparg_store[oparg_idx] = new_operand
-                                new_operands.append(new_operand)
-                        else:
-                            instr_info = INSTRUCTIONS[instr.name]
-                            assert oper_idx < len(instr_info.operands)
-                            new_operand = self.perturb_operand(instr.name, instr_info.operands[oper_idx], oper)
-                            new_operands.append(new_operand)
-                    assert len(new_operands) > 0
-                    new_instrs.append(Instr(instr.name, tuple(new_operands)))
-
-                else:
-                    new_instrs.append(instr)
+            new_instrs = self.materialize_rule(chosen_rule)
 
             # canonicalize nans
             if len(new_instrs) > 0 and self.canonicalize_nans and new_instrs[-1].name in FLOAT_CANON_OPCODES:
@@ -362,6 +294,128 @@ class StackGenBlockContext(GenContext):
         else:
             self.gen_argconst(ty)
     
+    # multi-value rule run in a block or a helper function, so that its results are not simply pushed
+    def extend_multi_value(self, multi_rule):
+        ret_cnt = len(multi_rule.ret_types)
+        assert list(multi_rule.ret_types) == self.stack[-ret_cnt:]
+        self.stack = self.stack[:-ret_cnt]
+
+        if multi_rule.wrap == 'block': # args are block params
+            blocktype = self.globalctx.alloc_type_section(list(multi_rule.param_types), list(multi_rule.ret_types))
+            new_instrs = [Instr('block', (blocktype,))] + self.materialize_rule(multi_rule) + [Instr('end', ())]
+        else: # args are function args
+            assert multi_rule.wrap == 'call'
+            new_instrs = [Instr('call', (self.globalctx.generate_rule_func(multi_rule),))]
+
+        self.records_rev.append(StackRecord(self.stack, InstrNode(multi_rule.param_types, multi_rule.ret_types, new_instrs)))
+        self.stack += multi_rule.param_types
+
+    # locals for the args of the rule, and args and operands in its instrs
+    def materialize_rule(self, chosen_rule):
+        # scan through the rule, count number of arg uses
+        args_use_cnt = [0] * len(chosen_rule.param_types)
+        for instr in chosen_rule.instrs:
+            if instr.name == 'arg':
+                assert len(instr.operands) == 1
+                arg_idx = instr.operands[0]
+                assert arg_idx < len(chosen_rule.param_types)
+                args_use_cnt[arg_idx] += 1
+
+        # optimize local generation: skip gen if redundant
+        opted_instrs = []
+        local_sets = list(range(len(chosen_rule.param_types) - 1, -1, -1))
+        for idx, instr in enumerate(chosen_rule.instrs):
+            if instr.name == "arg":
+                assert len(instr.operands) == 1
+                arg_idx = instr.operands[0]
+                assert arg_idx < len(chosen_rule.param_types)
+
+                if args_use_cnt[arg_idx] == 1 and local_sets[-1] == arg_idx:
+                    local_sets.pop()
+                    continue
+                else:
+                    assert args_use_cnt[arg_idx] > 0
+                    opted_instrs = chosen_rule.instrs[idx:]
+                    break
+            else:
+                opted_instrs = chosen_rule.instrs[idx:]
+                break
+
+        # generate local sets
+        new_instrs = [] # may be empty (there is one case that this is empty)
+        args_store = {} # stores generated args
+        for param_idx in local_sets:
+            param_ty = chosen_rule.param_types[param_idx]
+            local_instrs_tuple = self.ctx_stack[0].alloc_local(param_ty)
+            new_instrs.append(local_instrs_tuple[0])
+            args_store[param_idx] = local_instrs_tuple[1]
+
+        # gen args and operands
+        oparg_store = {} # stores generated opargs
+        for instr in opted_instrs:
+            if instr.name == "arg":
+                assert len(instr.operands) == 1
+                arg_idx = instr.operands[0]
+                assert arg_idx < len(chosen_rule.param_types)
+
+                new_instrs.append(args_store[arg_idx])
+
+            elif len(instr.operands) == 1 and type(instr.operands[0]) is tuple and instr.operands[0][0] == "index":
+                # entity of the type (local, global, table, function) chosen here
+                assert len(instr.operands[0]) == 2
+                new_instrs.append(self.alloc_entity_instr(instr.name, instr.operands[0][1]))
+
+            elif len(instr.operands) > 0 and \
+                    any(oper[0] == "oparg" for oper in instr.operands if type(oper) is tuple):
+                new_operands = []
+                for oper_idx, oper in enumerate(instr.operands):
+                    if type(oper) is tuple and oper[0] == "oparg":
+                        assert len(oper) == 2
+                        oparg_idx = oper[1]
+                        if oparg_idx in oparg_store:
+                            new_operands.append(oparg_store[oparg_idx])
+                        else:
+                            instr_info = INSTRUCTIONS[instr.name]
+                            assert oper_idx < len(instr_info.operands)
+                            new_operand = self.gen_operand(instr.name, instr_info.operands[oper_idx], oper_idx, oparg_store, chosen_rule.conds)
+                            # oparg_store[oparg_idx] = new_operand
+                            new_operands.append(new_operand)
+                    else:
+                        instr_info = INSTRUCTIONS[instr.name]
+                        assert oper_idx < len(instr_info.operands)
+                        new_operand = self.perturb_operand(instr.name, instr_info.operands[oper_idx], oper)
+                        new_operands.append(new_operand)
+                assert len(new_operands) > 0
+                new_instrs.append(Instr(instr.name, tuple(new_operands)))
+
+            else:
+                new_instrs.append(instr)
+
+        return new_instrs
+
+    def alloc_entity_instr(self, opcode, ty):
+        if opcode == 'global.get':
+            return self.globalctx.alloc_global(ty)[1]
+        elif opcode == 'global.set':
+            return self.globalctx.alloc_global(ty)[0]
+        elif opcode == 'local.tee':
+            return self.ctx_stack[0].alloc_local(ty)[2]
+        else: # table and ref.func use the only table and any function
+            instr_info = INSTRUCTIONS[opcode]
+            assert len(instr_info.operands) == 1
+            return Instr(opcode, (self.gen_operand(opcode, instr_info.operands[0], 0, {}, {}),))
+
+    # branch whose i32 condition (or index) is computed by an extracted branch rule, if any
+    def push_branch(self, rule_provider, branch_instr):
+        branch_rule = rule_provider.get_branch_rule(branch_instr.name, self.rng)
+        if branch_rule == None:
+            self.stack.append('i32')
+            self.records_rev.append(StackRecord(self.stack, InstrNode.from_single_instr(branch_instr)))
+        else:
+            cond_instrs = self.materialize_rule(branch_rule)[:-1] # last one is the branch itself
+            self.records_rev.append(StackRecord(self.stack, InstrNode(branch_rule.param_types, (), cond_instrs + [branch_instr])))
+            self.stack += branch_rule.param_types
+
     # global or local
     def gen_var(self, ty): # ty being already popped
         assert self.init_done
@@ -458,15 +512,14 @@ class StackGenBlockContext(GenContext):
             # move local value to memory or table at the end of the block
             self.records_rev.insert(0, StackRecord(self.rets, InstrNode((), (), pre_instrs + [get_local] + post_instrs)))
 
-    def gen_br_if(self, exit_idx, exit_ctx):
+    def gen_br_if(self, exit_idx, exit_ctx, rule_provider):
         if exit_ctx.get_stack_goal() == None: # loop needs to match with the params
             exit_ctx.set_target_params(self.stack)
         
         labelidx = len(self.ctx_stack) - 1 - exit_idx
-        self.stack.append('i32')
-        self.records_rev.append(StackRecord(self.stack, InstrNode.from_single_instr(Instr('br_if', (labelidx,)))))
+        self.push_branch(rule_provider, Instr('br_if', (labelidx,)))
 
-    def gen_br(self, exit_candidates):
+    def gen_br(self, exit_candidates, rule_provider):
         exit_opcodes = ['br', 'br_table']
         if 0 in [x[0] for x in exit_candidates]:
             exit_opcodes.append('return')
@@ -502,9 +555,7 @@ class StackGenBlockContext(GenContext):
                 exitidx_list.append(exit_idx)
             labelidx_list = [len(self.ctx_stack) - 1 - x for x in exitidx_list]
 
-            self.stack.append('i32')
-            self.records_rev.append(StackRecord(self.stack, InstrNode.from_single_instr(
-                Instr('br_table', (labelidx_list[:-1], labelidx_list[-1])))))
+            self.push_branch(rule_provider, Instr('br_table', (labelidx_list[:-1], labelidx_list[-1])))
         else: # return
             self.records_rev.append(StackRecord(self.stack, InstrNode.from_single_instr(Instr('return', ()))))
     
@@ -756,6 +807,24 @@ class StackGenContext(GenContext):
         new_func.generate(rule_provider)
         assert new_func.gen_done
         return new_func_idx
+
+    # function consisting of the rule only, taking the args of the rule as its args
+    def generate_rule_func(self, rule):
+        new_func = StackGenFuncContext(self.rng, self)
+        new_func.init_stack(list(rule.ret_types))
+        new_func.set_target_params([])
+        self.funcs.append(new_func)
+        new_func_idx = len(self.funcs) - 1
+
+        new_instrs = []
+        for ty in rule.param_types:
+            new_instrs += new_func.alloc_arg(ty, allow_dup=False)
+        new_instrs += new_func.materialize_rule(rule)
+        new_func.records_rev.append(StackRecord([], InstrNode([], rule.ret_types, new_instrs)))
+        new_func.stack = []
+        new_func.params = []
+        new_func.gen_done = True
+        return new_func_idx
     
     def alloc_global(self, ty, allow_dup=True):
         assert ty != 'noout' and ty in ALLOWED_TYPES
@@ -27,7 +27,7 @@ parser.add_argument(
     dest='extractor_opt', 
     type=str, 
     default='all', 
-    help='extractor option (all, opt, lower, typing, optlower, opttyping, lowertyping)'
+    help='extractor option (all, opt, lower, typing, optlower, opttyping, lowertyping, or a per-ISA mode such as "s390x --with-opt --with-typing")'
 )
 parser.add_argument(
     '--generator-opt', 
@@ -132,7 +132,7 @@ wasmedge_additional_configs = []
 codegen_seed_len = 4 # length condition of seed accepted by the generator
 codegen_is_random = args.generator_seed_random # is random generation or consumption generation
 codegen_seed_enum_start_from = 0 # when enumerative
-codegen_extractor_option = args.extractor_opt # extractor option (all, opt, lower, typing, optlower, opttyping, lowertyping)
+codegen_extractor_option = args.extractor_opt # extractor option (all, opt, lower, typing, optlower, opttyping, lowertyping, x64, aarch64, s390x, riscv64)
 codegen_generator_option = args.generator_opt # generator option (stackgen, wasm-smith)
 
 codegen_memory_max = 1 # in num of pages (for each, 64kiB)
@@ -208,6 +208,7 @@ codegen_stackgen_prob_struct_exit = 0.1 # probability for structure exit
 codegen_stackgen_prob_struct_ret = 0.5 # probability of using the ret on stack
 codegen_stackgen_prob_struct_skipelse = 0.8 # probability of skipping the else
 codegen_stackgen_prob_stack_pop = 0.9 # probability of using the type in the stack
+codegen_stackgen_prob_multi_value = 0.1 # probability of multi-value rules for the types in the stack
 codegen_stackgen_prob_reuse_local = 0.2 # probability of reusing the locals
 codegen_stackgen_prob_reuse_global = 0.5 # probability of reusing the globals
 codegen_stackgen_prob_reuse_func = 0.9 # probability of reusing the functions
@@ -0,0 +1,13 @@
+#!/bin/bash
+# Builds the extractor with the verify feature (see README.md). The ISLE compiler of the fuzzed
+# wasmtime stays untouched: a copy patched with patches/isle-emit-logging.patch replaces it in
+# this build only, through a Cargo path override.
+set -e
+
+cd "$(dirname "$0")"
+ISLE_DIR=$PWD/target/verify-isle
+rm -rf $ISLE_DIR
+mkdir -p target
+cp -r ../../targets/wasmtime/wasmtime/cranelift/isle $ISLE_DIR
+patch -s -p1 -d $ISLE_DIR/isle < patches/isle-emit-logging.patch
+cargo build --release --features verify --config "paths = [\"$ISLE_DIR/isle\"]" "$@"
@@ -0,0 +1,259 @@
+// CLIF filetest exporter: writes the LHS of simplify/lower rules as `test run` files
+// Each rule is instantiated as in the soundness checker (src/isle_sound.rs), with its LHS only, and
+// the expected results of the run lines come from the Cranelift interpreter on boundary and random
+// inputs satisfying the rule conditions. Inputs that trap or give NaN (whose payload differs between
+// ISAs) are left out, so every run line should hold on every backend that lowers the function.
+// Simplify rules are also written as `test optimize` files, whose check lines expect the root of the
+// RHS (built from the rule, not by Cranelift) in the optimized LHS.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, Error};
+use cranelift_codegen::{ir::{types, Function, Type, ValueDef}, settings, verify_function};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    diagnostics::Diagnostics,
+    isle_sound::{build_function, get_conds, get_domains, get_instances, get_rule_shape, has_nan, run_function, sample_args, sample_imms, Rng, RunResult},
+    norm::{RuleSource, UnifiedRule},
+    rule_match::{get_clir_rules, MatchOption},
+};
+
+const SCALAR_TARGETS: [&str; 4] = ["aarch64", "s390x", "x86_64", "riscv64"];
+const VECTOR_TARGETS: [&str; 4] = ["aarch64", "s390x", "x86_64 sse42", "riscv64 has_v"];
+const OPTIMIZE_TARGET: &str = "x86_64"; // as the egraph filetests
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClifOption {
+    pub runs: usize, // run lines per function
+    pub seed: u64,
+}
+
+impl Default for ClifOption {
+    fn default() -> Self {
+        Self { runs: 8, seed: 0 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClifFile {
+    pub source: RuleSource,
+    pub name: String, // file name, e.g., opts_arithmetic_12.clif or opts_arithmetic_12_optimize.clif
+    pub text: String,
+}
+
+type ClifFunc = (Function, Vec<String>); // with its run or check lines
+
+// e.g., opts/arithmetic.isle:12 -> opts_arithmetic_12
+fn get_file_stem(source: &RuleSource) -> String {
+    format!("{}_{}", source.file.trim_end_matches(".isle").replace(['/', '.'], "_"), source.line)
+}
+
+fn get_value_types(func: &Function) -> Vec<Type> {
+    let mut value_types = func.signature.params.iter().chain(&func.signature.returns).map(|x| x.value_type).collect::<Vec<_>>();
+    for block in func.layout.blocks() {
+        for inst in func.layout.block_insts(block) {
+            value_types.extend(func.dfg.inst_results(inst).iter().map(|x| func.dfg.value_type(*x)));
+        }
+    }
+    value_types
+}
+
+// a function with its run lines for each type instantiation; cnt numbers the functions of the file
+fn export_rule(rule: &UnifiedRule, source: &RuleSource, option: &ClifOption, rng: &mut Rng, cnt: &mut usize) -> Vec<ClifFunc> {
+    let shape = match get_rule_shape(rule, false) {
+        Ok(x) => x,
+        Err(_) => return Vec::new(),
+    };
+    let flags = settings::Flags::new(settings::builder());
+
+    let mut funcs = Vec::new();
+    for types in get_instances(rule, &shape) {
+        let (imm_domains, value_domains) = get_domains(rule, &shape, &types);
+        let imms = match sample_imms(rule, &imm_domains, rng, true) {
+            Some(x) => x,
+            None => continue,
+        };
+        let name = format!("{}_{}_{}", source.name, source.line, cnt);
+        let func = match build_function(&name, rule, &shape, &types, &imms, shape.lhs_root) {
+            Ok(Some(x)) if verify_function(&x, &flags).is_ok() => x,
+            _ => continue,
+        };
+        let ret_type = types.types[&shape.lhs_root];
+
+        let mut run_lines = Vec::new();
+        for input_idx in 0..option.runs {
+            let args = match sample_args(rule, &shape, &types, (&imm_domains, &value_domains), &imms, rng, input_idx < option.runs / 2) {
+                Ok(Some(x)) => x,
+                Ok(None) => continue,
+                Err(_) => break, // conditions cannot be evaluated
+            };
+            let result = match run_function(&func, &args) {
+                Ok(RunResult::Value(x)) if !has_nan(&x, ret_type) => x,
+                _ => continue,
+            };
+            let args = args.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+            let run_line = format!("; run: {}({}) == {}", func.name, args.join(", "), result);
+            if !run_lines.contains(&run_line) {
+                run_lines.push(run_line);
+            }
+        }
+        if !run_lines.is_empty() {
+            *cnt += 1;
+            funcs.push((func, run_lines));
+        }
+    }
+    funcs
+}
+
+// the check line for the root of the RHS: the param it returns, or its opcode (e.g., `= iconst.i32`)
+fn get_check_line(rhs: &Function) -> Option<String> {
+    let block = rhs.layout.entry_block()?;
+    let ret = rhs.layout.last_inst(block)?;
+    let ret_value = *rhs.dfg.inst_args(ret).first()?;
+    match rhs.dfg.value_def(ret_value) {
+        ValueDef::Param(_, idx) => Some(format!("; check: return v{}", idx)),
+        ValueDef::Result(inst, _) => {
+            let text = rhs.dfg.display_inst(inst).to_string();
+            let opcode = text.split(" = ").nth(1)?.split_whitespace().next()?.to_string();
+            Some(format!("; check: = {}", opcode))
+        },
+        _ => None,
+    }
+}
+
+// the LHS with the check line of the RHS for each type instantiation of a simplify rule; rules with
+// conditions on values are left out, since the optimizer cannot tell whether they hold
+fn optimize_rule(rule: &UnifiedRule, source: &RuleSource, rng: &mut Rng, cnt: &mut usize) -> Vec<ClifFunc> {
+    if rule.is_lower {
+        return Vec::new();
+    }
+    let shape = match get_rule_shape(rule, true) {
+        Ok(x) => x,
+        Err(_) => return Vec::new(),
+    };
+    if shape.value_vars.iter().any(|x| !get_conds(rule, *x).is_empty()) {
+        return Vec::new();
+    }
+    let flags = settings::Flags::new(settings::builder());
+
+    let mut funcs = Vec::new();
+    for types in get_instances(rule, &shape) {
+        let (imm_domains, _) = get_domains(rule, &shape, &types);
+        let imms = match sample_imms(rule, &imm_domains, rng, true) {
+            Some(x) => x,
+            None => continue,
+        };
+        let name = format!("{}_{}_{}", source.name, source.line, cnt);
+        let (lhs, rhs) = match (
+            build_function(&name, rule, &shape, &types, &imms, shape.lhs_root),
+            build_function(&name, rule, &shape, &types, &imms, shape.rhs_root),
+        ) {
+            (Ok(Some(x)), Ok(Some(y))) if verify_function(&x, &flags).is_ok() && verify_function(&y, &flags).is_ok() => (x, y),
+            _ => continue,
+        };
+        if let Some(check_line) = get_check_line(&rhs) {
+            *cnt += 1;
+            funcs.push((lhs, vec![check_line]));
+        }
+    }
+    funcs
+}
+
+fn get_file_text(source: &RuleSource, funcs: &[ClifFunc], optimize: bool) -> String {
+    let value_types = funcs.iter().flat_map(|(x, _)| get_value_types(x)).collect::<Vec<_>>();
+    let mut text = format!("; {}:{} ({}, prio {})\n", source.file, source.line, source.name, source.prio);
+    if optimize {
+        text.push_str(&format!("test optimize\nset opt_level=speed\ntarget {}\n", OPTIMIZE_TARGET));
+    }
+    else {
+        text.push_str("test interpret\ntest run\n");
+        if value_types.contains(&types::I128) {
+            text.push_str("set enable_llvm_abi_extensions=true\n"); // i128 params on x86_64
+        }
+        let targets = if value_types.iter().any(|x| x.is_vector()) { VECTOR_TARGETS } else { SCALAR_TARGETS };
+        for target in targets {
+            text.push_str(&format!("target {}\n", target));
+        }
+    }
+    for (func, lines) in funcs {
+        text.push_str(&format!("\n{}{}\n", func.display(), lines.join("\n")));
+    }
+    text
+}
+
+// one file for each rule (and one more for each simplify rule), with the variants of the rule (from
+// inlining and substitution) together
+pub fn export_rules(rules: &[UnifiedRule], option: &ClifOption) -> Vec<ClifFile> {
+    let mut rng = Rng::new(option.seed);
+    let mut rule_funcs: BTreeMap<RuleSource, (Vec<ClifFunc>, usize)> = BTreeMap::new();
+    let mut rule_opt_funcs: BTreeMap<RuleSource, (Vec<ClifFunc>, usize)> = BTreeMap::new();
+    for rule in rules {
+        let source = match Diagnostics::get_target(&rule.sources) {
+            Some(x) => x,
+            None => continue,
+        };
+        let (funcs, cnt) = rule_funcs.entry(source.clone()).or_default();
+        funcs.append(&mut export_rule(rule, source, option, &mut rng, cnt));
+        let (opt_funcs, opt_cnt) = rule_opt_funcs.entry(source.clone()).or_default();
+        opt_funcs.append(&mut optimize_rule(rule, source, &mut rng, opt_cnt));
+    }
+
+    let mut files = Vec::new();
+    for (optimize, rule_funcs) in [(false, rule_funcs), (true, rule_opt_funcs)] {
+        files.extend(rule_funcs.into_iter().filter(|(_, (funcs, _))| !funcs.is_empty()).map(|(source, (funcs, _))| ClifFile {
+            name: format!("{}{}.clif", get_file_stem(&source), if optimize { "_optimize" } else { "" }),
+            text: get_file_text(&source, &funcs, optimize),
+            source,
+        }));
+    }
+    files
+}
+
+pub fn export_clif(match_option: MatchOption, src_root: &Path, option: &ClifOption) -> Vec<ClifFile> {
+    let (mut rules, mut lower_rules) = get_clir_rules(match_option, src_root);
+    rules.append(&mut lower_rules);
+    export_rules(&rules, option)
+}
+
+pub fn write_clif_files(files: &[ClifFile], dir: &Path) -> Result<(), Error> {
+    fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    for file in files {
+        let path = dir.join(&file.name);
+        fs::write(&path, &file.text).with_context(|| format!("Cannot write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use cranelift_codegen::ir::types;
+
+    use crate::norm::{LinExpr, LinExprIdx, LinVec};
+
+    use super::*;
+
+    #[test]
+    fn test_export_rules() {
+        // (iadd ty x (iconst ty 0))
+        let mut lhs = LinVec::new();
+        let ty = LinExprIdx::LHS(lhs.push(LinExpr::TypeVar(vec![types::I32, types::F32])));
+        let x = LinExprIdx::LHS(lhs.push(LinExpr::Var(Vec::new())));
+        let zero = LinExprIdx::LHS(lhs.push(LinExpr::Const(0)));
+        let y = LinExprIdx::LHS(lhs.push(LinExpr::Expr { name: String::from("iconst"), params: vec![ty.clone(), zero] }));
+        lhs.push(LinExpr::Expr { name: String::from("iadd"), params: vec![ty, x.clone(), y] });
+        let mut rhs = LinVec::new();
+        rhs.push(LinExpr::Ident(x));
+        let rule = UnifiedRule::new(lhs, rhs, false).with_sources(vec![RuleSource::new_target("opts/arithmetic.isle", 10)]);
+
+        let files = export_rules(&[rule], &ClifOption::default());
+        println!("{}\n{}", files[0].text, files[1].text);
+        assert_eq!(files[0].name, "opts_arithmetic_10.clif");
+        assert!(files[0].text.contains("test run\ntarget aarch64\n"));
+        assert!(files[0].text.contains("; run: %simplify_10_0("));
+        assert!(!files[0].text.contains("%simplify_10_1")); // iconst has no f32 instantiation
+        assert_eq!(files[1].name, "opts_arithmetic_10_optimize.clif");
+        assert!(files[1].text.contains("test optimize\nset opt_level=speed\ntarget x86_64\n"));
+        assert!(files[1].text.contains("; check: return v0"));
+    }
+}
@@ -0,0 +1,175 @@
+// Extraction configuration for library users
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Error};
+
+use crate::{
+    diagnostics::Diagnostics,
+    isle::{default_cranelift_src, resolve_cranelift_src},
+    prod::{canonicalize_rules, ProdRule},
+    prod_cache::{default_cache_dir, learn_prods_cached},
+    prod_extract::{learn_prods, learn_prods_diag},
+    prod_multi::get_multi_value_prods,
+    prod_typing::get_all_typing_rule_prods,
+    prod_verify::{verify_prods, ProdVerdict},
+    rule_match::MatchOption,
+    wasm_env::TranslateEnv,
+    wasm_map::WasmMapOption,
+};
+
+/// What to extract and from where. Build with [`ExtractConfig::builder`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtractConfig {
+    cranelift_src: PathBuf,
+    match_option: MatchOption,
+    with_typing: bool,
+    multi_value: bool,
+    map_option: WasmMapOption,
+    cache_dir: Option<PathBuf>,
+}
+
+impl ExtractConfig {
+    pub fn builder() -> ExtractConfigBuilder {
+        ExtractConfigBuilder::new()
+    }
+
+    /// `cranelift/codegen/src` that the ISLE rules are read from
+    pub fn cranelift_src(&self) -> &Path {
+        &self.cranelift_src
+    }
+
+    pub fn match_option(&self) -> MatchOption {
+        self.match_option
+    }
+
+    /// Learned production rules, plus the multi-value and typing rules if requested,
+    /// in canonical order without duplicates.
+    pub fn extract(&self) -> Result<Vec<ProdRule>, Error> {
+        let mut rules = canonicalize_rules(match &self.cache_dir {
+            Some(cache_dir) => learn_prods_cached(self.match_option, &self.map_option, &self.cranelift_src, cache_dir)?,
+            None => learn_prods(self.match_option, &self.map_option, &self.cranelift_src),
+        });
+        if self.multi_value {
+            rules.append(&mut get_multi_value_prods(&rules));
+        }
+        if self.with_typing {
+            rules.append(&mut get_all_typing_rule_prods());
+        }
+        Ok(canonicalize_rules(rules))
+    }
+
+    /// Runs the pipeline without the cache and reports, for each
+    /// `simplify`/`lower` rule, its productions or why it was dropped.
+    pub fn report(&self) -> Diagnostics {
+        let mut diag = Diagnostics::new();
+        learn_prods_diag(self.match_option, &self.map_option, &self.cranelift_src, &mut diag);
+        diag
+    }
+
+    /// Compiles the rules as the engine would, and tells for each whether it fires its
+    /// `simplify`/`lower` rule (see `prod_verify`; needs the `verify` feature).
+    pub fn verify(&self, rules: &[ProdRule]) -> Result<Vec<Option<ProdVerdict>>, Error> {
+        verify_prods(rules, &self.map_option)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtractConfigBuilder {
+    cranelift_src: Option<PathBuf>,
+    match_option: MatchOption,
+    with_typing: bool,
+    multi_value: bool,
+    map_option: WasmMapOption,
+    cache_dir: Option<PathBuf>,
+}
+
+impl ExtractConfigBuilder {
+    /// Defaults: wasmtime of this repository, all rules, no typing rules, no multi-value rules,
+    /// weighted alternative instructions, NaN canonicalization, no cache.
+    pub fn new() -> Self {
+        Self {
+            cranelift_src: None,
+            match_option: MatchOption::All,
+            with_typing: false,
+            multi_value: false,
+            map_option: WasmMapOption::default(),
+            cache_dir: None,
+        }
+    }
+
+    /// A wasmtime checkout, its cranelift directory, or `cranelift/codegen/src`
+    pub fn cranelift_src(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cranelift_src = Some(path.into());
+        self
+    }
+
+    pub fn match_option(mut self, match_option: MatchOption) -> Self {
+        self.match_option = match_option;
+        self
+    }
+
+    pub fn with_typing(mut self, with_typing: bool) -> Self {
+        self.with_typing = with_typing;
+        self
+    }
+
+    /// Also pair the learned rules into rules with two results (see `prod_multi`)
+    pub fn multi_value(mut self, multi_value: bool) -> Self {
+        self.multi_value = multi_value;
+        self
+    }
+
+    /// Only use the WebAssembly instructions with the smallest translation for each IR,
+    /// instead of keeping the others with lower weights
+    pub fn strict_candidates(mut self, strict_candidates: bool) -> Self {
+        self.map_option.strict_candidates = strict_candidates;
+        self
+    }
+
+    /// Translate WebAssembly instructions with NaN canonicalization, as the engine does
+    pub fn nan_canonicalization(mut self, nan_canonicalization: bool) -> Self {
+        self.map_option.nan_canonicalization = nan_canonicalization;
+        self
+    }
+
+    /// Environment to translate WebAssembly instructions with (wasmtime-like by default)
+    pub fn translate_env(mut self, translate_env: TranslateEnv) -> Self {
+        self.map_option.env = translate_env;
+        self
+    }
+
+    /// Cache learned rules in this directory (see `prod_cache`)
+    pub fn cache_dir(mut self, cache_dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// Cache learned rules in `target/rule-cache` of this crate
+    pub fn default_cache(self) -> Self {
+        self.cache_dir(default_cache_dir())
+    }
+
+    pub fn build(self) -> Result<ExtractConfig, Error> {
+        let cranelift_src = match self.cranelift_src {
+            Some(path) => resolve_cranelift_src(&path)
+                .ok_or(anyhow!("Cannot find cranelift/codegen/src (prelude.isle) under {}", path.display()))?,
+            None => default_cranelift_src(),
+        };
+        Ok(ExtractConfig {
+            cranelift_src,
+            match_option: self.match_option,
+            with_typing: self.with_typing,
+            multi_value: self.multi_value,
+            // NaNs are canonicalized for the backend of the rules (see WasmMapOption::target)
+            map_option: WasmMapOption { target: self.match_option.target_arch(), ..self.map_option },
+            cache_dir: self.cache_dir,
+        })
+    }
+}
+
+impl Default for ExtractConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
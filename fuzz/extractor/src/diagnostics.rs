@@ -0,0 +1,228 @@
+// Diagnostics: why each simplify/lower rule did or did not yield productions
+
+use std::collections::BTreeMap;
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::norm::{RuleRole, RuleSource};
+
+// pipeline stages in which a rule can drop out
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagStage {
+    Norm, // isle_norm::process_rule (extractor macro expansion)
+    Inline, // isle_inl::process_internals
+    Subst, // isle_subst::subst_result
+    Cond, // isle_cond::process_conds
+    Match, // rule_match::match_rule
+    Extract, // prod_extract::extract_prod_rule
+    Solve, // prod_solve::solve_prod
+}
+
+impl DiagStage {
+    pub const ALL: [DiagStage; 7] = [DiagStage::Norm, DiagStage::Inline, DiagStage::Subst, DiagStage::Cond, DiagStage::Match, DiagStage::Extract, DiagStage::Solve];
+
+    fn default_reason(&self) -> &'static str {
+        match self {
+            DiagStage::Norm => "rule could not be normalized",
+            DiagStage::Inline => "type intersection came out empty",
+            DiagStage::Subst => "no applicable substitution",
+            DiagStage::Cond => "unsatisfiable condition",
+            DiagStage::Match => "no type-compatible match",
+            DiagStage::Extract => "no production could be extracted",
+            DiagStage::Solve => "unsatisfiable production condition",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct RuleDiag {
+    survivors: BTreeMap<DiagStage, usize>, // stage -> number of variants that passed
+    reasons: BTreeMap<DiagStage, String>, // stage -> first drop reason
+    productions: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RuleDrop {
+    pub stage: DiagStage,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RuleOutcome {
+    pub source: RuleSource,
+    pub productions: usize,
+    pub dropped: Option<RuleDrop>,
+}
+
+// collects per-rule outcomes while the pipeline runs; a disabled collector records nothing
+#[derive(Clone, Debug, Default)]
+pub struct Diagnostics {
+    enabled: bool,
+    rules: BTreeMap<RuleSource, RuleDiag>,
+}
+
+// left-aligned columns (right-aligned for the given ones), the last column unpadded
+pub(crate) fn format_table<const N: usize>(header: [&str; N], rows: &[[String; N]], right_aligned: &[usize]) -> String {
+    let mut widths = header.map(|x| x.len());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let header = header.map(String::from);
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let mut line = String::new();
+        for (idx, cell) in row.iter().enumerate() {
+            if idx == N - 1 {
+                line.push_str(cell);
+            }
+            else if right_aligned.contains(&idx) {
+                line.push_str(&format!("{:>width$}  ", cell, width = widths[idx]));
+            }
+            else {
+                line.push_str(&format!("{:<width$}  ", cell, width = widths[idx]));
+            }
+        }
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+pub fn is_target_source(source: &RuleSource) -> bool {
+    source.role == RuleRole::Target && ["simplify", "lower", "lower_branch"].contains(&source.name.as_str())
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self { enabled: true, rules: BTreeMap::new() }
+    }
+
+    pub fn disabled() -> Self {
+        Self { enabled: false, rules: BTreeMap::new() }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // the target rule of a pipeline value, if it is a simplify/lower rule
+    pub fn get_target(sources: &[RuleSource]) -> Option<&RuleSource> {
+        sources.first().filter(|x| is_target_source(x))
+    }
+
+    // all simplify/lower rules of a production, e.g., of merged or paired productions, without duplicates
+    pub fn get_targets(sources: &[RuleSource]) -> Vec<&RuleSource> {
+        let mut targets: Vec<&RuleSource> = Vec::new();
+        for source in sources.iter().filter(|x| is_target_source(x)) {
+            if !targets.contains(&source) {
+                targets.push(source);
+            }
+        }
+        targets
+    }
+
+    pub fn add_rule(&mut self, source: &RuleSource) {
+        if self.enabled && is_target_source(source) {
+            self.rules.entry(source.clone()).or_default();
+        }
+    }
+
+    pub fn add_survivors(&mut self, source: &RuleSource, stage: DiagStage, cnt: usize) {
+        if self.enabled && is_target_source(source) {
+            *self.rules.entry(source.clone()).or_default().survivors.entry(stage).or_default() += cnt;
+        }
+    }
+
+    pub fn add_drop(&mut self, source: &RuleSource, stage: DiagStage, reason: String) {
+        if self.enabled && is_target_source(source) {
+            self.rules.entry(source.clone()).or_default().reasons.entry(stage).or_insert(reason);
+        }
+    }
+
+    pub fn add_productions(&mut self, source: &RuleSource, cnt: usize) {
+        if self.enabled && is_target_source(source) {
+            self.rules.entry(source.clone()).or_default().productions += cnt;
+        }
+    }
+
+    // sorted by file, line
+    pub fn report(&self) -> Vec<RuleOutcome> {
+        self.rules.iter().map(|(source, diag)| {
+            let dropped = if diag.productions > 0 {
+                None
+            }
+            else {
+                // first stage that no variant survived
+                let stage = DiagStage::ALL.into_iter()
+                    .find(|x| diag.survivors.get(x).copied().unwrap_or(0) == 0)
+                    .unwrap_or(DiagStage::Extract);
+                let reason = diag.reasons.get(&stage).cloned().unwrap_or(String::from(stage.default_reason()));
+                Some(RuleDrop { stage, reason })
+            };
+            RuleOutcome { source: source.clone(), productions: diag.productions, dropped }
+        }).collect()
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string_pretty(&self.report())?)
+    }
+
+    pub fn to_table(&self) -> String {
+        let report = self.report();
+        let rows = report.iter().map(|outcome| {
+            let location = format!("{}:{}", outcome.source.file, outcome.source.line);
+            let (status, reason) = match &outcome.dropped {
+                None => (format!("{} prods", outcome.productions), String::new()),
+                Some(x) => (format!("{:?}", x.stage).to_lowercase(), x.reason.clone()),
+            };
+            [location, outcome.source.name.clone(), outcome.source.prio.to_string(), status, reason]
+        }).collect::<Vec<_>>();
+
+        let mut table = format_table(["rule", "term", "prio", "outcome", "reason"], &rows, &[2]);
+
+        // summary
+        let produced = report.iter().filter(|x| x.dropped.is_none()).count();
+        table.push_str(&format!("\n{} / {} rules yield productions\n", produced, report.len()));
+        for stage in DiagStage::ALL {
+            let dropped = report.iter().filter(|x| x.dropped.as_ref().map(|y| y.stage) == Some(stage)).count();
+            table.push_str(&format!("dropped at {:<8} {}\n", format!("{:?}:", stage).to_lowercase(), dropped));
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diagnostics_report() {
+        let mut diag = Diagnostics::new();
+        let (ok_source, drop_source) = (RuleSource::new_target("opts/arithmetic.isle", 10), RuleSource::new_target("opts/arithmetic.isle", 20));
+        for source in [&ok_source, &drop_source] {
+            diag.add_rule(source);
+            diag.add_survivors(source, DiagStage::Norm, 1);
+            diag.add_survivors(source, DiagStage::Inline, 1);
+            diag.add_survivors(source, DiagStage::Subst, 1);
+        }
+        diag.add_survivors(&ok_source, DiagStage::Cond, 1);
+        diag.add_survivors(&ok_source, DiagStage::Match, 1);
+        diag.add_productions(&ok_source, 2);
+        diag.add_drop(&drop_source, DiagStage::Cond, String::from("unsatisfiable condition"));
+
+        let report = diag.report();
+        println!("{}", diag.to_table());
+        assert_eq!(report[0].productions, 2);
+        assert_eq!(report[0].dropped, None);
+        assert_eq!(report[1].dropped.as_ref().unwrap().stage, DiagStage::Cond);
+
+        let mut disabled = Diagnostics::disabled();
+        disabled.add_rule(&ok_source);
+        assert!(disabled.report().is_empty());
+    }
+}
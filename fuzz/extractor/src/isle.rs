@@ -2,7 +2,6 @@
 
 use std::{
     fs,
-    env,
     path::Path, path::PathBuf
 };
 
@@ -20,8 +19,10 @@ fn parse_files<P: AsRef<Path>>(
 
 fn find_isle_files_rec(dir: &Path, isle_files: &mut Vec<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
     assert!(dir.is_dir());
-    for dir_entry in fs::read_dir(dir)? {
-        let dir_entry_path = dir_entry?.path();
+    // sorted, as read_dir order is platform-dependent and rule order affects learning
+    let mut dir_entry_paths = fs::read_dir(dir)?.map(|x| x.map(|y| y.path())).collect::<Result<Vec<_>, _>>()?;
+    dir_entry_paths.sort();
+    for dir_entry_path in dir_entry_paths {
         if dir_entry_path.is_dir() {
             let _ = find_isle_files_rec(&dir_entry_path, isle_files);
         }
@@ -66,18 +67,32 @@ impl ISLEParseOptions {
 //     cranelift_codegen_meta::generate(isas, &out_dir_str, &out_dir_str)
 // }
 
-pub fn run_parse_opt(opt: ISLEParseOptions) -> Result<ast::Defs, Errors> {
-    let cwd = env::current_dir().unwrap().to_string_lossy().to_string();
+// cranelift/codegen/src of the wasmtime checkout in this repository
+pub fn default_cranelift_src() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join("..")
+        .join("targets")
+        .join("wasmtime")
+        .join("wasmtime")
+        .join("cranelift")
+        .join("codegen")
+        .join("src")
+}
+
+// accepts a wasmtime checkout, its cranelift directory, or cranelift/codegen/src itself
+pub fn resolve_cranelift_src(path: &Path) -> Option<PathBuf> {
+    let candidates = [
+        path.join("cranelift").join("codegen").join("src"),
+        path.join("codegen").join("src"),
+        path.to_path_buf(),
+    ];
+    candidates.into_iter().find(|x| x.join("prelude.isle").is_file())
+}
+
+// ISLE files to parse for the given option
+pub fn get_isle_files(opt: ISLEParseOptions, src_root: &Path) -> Vec<PathBuf> {
     // let src_out = Path::new(&cwd).join("meta");
-    let src_root = Path::new(&cwd)
-                    .parent().expect("Crate in wrong directory - cannot find wasmtime")
-                    .parent().expect("Crate in wrong directory - cannot find wasmtime")
-                    .join("targets")
-                    .join("wasmtime")
-                    .join("wasmtime")
-                    .join("cranelift")
-                    .join("codegen")
-                    .join("src");
     let src_opts = src_root.join("opts");
     let src_x64 = src_root.join("isa").join("x64");
     let src_arm64 = src_root.join("isa").join("aarch64");
@@ -96,7 +111,6 @@ pub fn run_parse_opt(opt: ISLEParseOptions) -> Result<ast::Defs, Errors> {
             isle_files.push(prelude_opt_isle);
             // isle_files.push(clif_opt_isle);
             let _ = find_isle_files_rec(src_opts.as_path(), &mut isle_files);
-            parse_files(isle_files)
         },
         ISLEParseOptions::Lower => {
             isle_files.push(prelude_lower_isle);
@@ -105,38 +119,37 @@ pub fn run_parse_opt(opt: ISLEParseOptions) -> Result<ast::Defs, Errors> {
             let _ = find_isle_files_rec(src_arm64.as_path(), &mut isle_files);
             let _ = find_isle_files_rec(src_s390x.as_path(), &mut isle_files);
             let _ = find_isle_files_rec(src_riscv64.as_path(), &mut isle_files);
-            parse_files(isle_files)
         },
         ISLEParseOptions::X64 => {
             isle_files.push(prelude_lower_isle);
             // isle_files.push(clif_lower_isle);
             let _ = find_isle_files_rec(src_x64.as_path(), &mut isle_files);
-            parse_files(isle_files)
         },
         ISLEParseOptions::ARM64 => {
             isle_files.push(prelude_lower_isle);
             // isle_files.push(clif_lower_isle);
             let _ = find_isle_files_rec(src_arm64.as_path(), &mut isle_files);
-            parse_files(isle_files)
         },
         ISLEParseOptions::S390X => {
             isle_files.push(prelude_lower_isle);
             // isle_files.push(clif_lower_isle);
             let _ = find_isle_files_rec(src_s390x.as_path(), &mut isle_files);
-            parse_files(isle_files)
         },
         ISLEParseOptions::RISCV64 => {
             isle_files.push(prelude_lower_isle);
             // isle_files.push(clif_lower_isle);
             let _ = find_isle_files_rec(src_riscv64.as_path(), &mut isle_files);
-            parse_files(isle_files)
         },
         ISLEParseOptions::TestOpt | ISLEParseOptions::TestLower => {
-            let src_test = Path::new(&cwd).join("test");
+            let src_test = Path::new(env!("CARGO_MANIFEST_DIR")).join("test");
             let _ = find_isle_files_rec(src_test.as_path(), &mut isle_files);
-            parse_files(isle_files)
         }
     }
+    isle_files
+}
+
+pub fn run_parse_opt(opt: ISLEParseOptions, src_root: &Path) -> Result<ast::Defs, Errors> {
+    parse_files(get_isle_files(opt, src_root))
 }
 
 #[cfg(test)]
@@ -146,14 +159,14 @@ mod test {
     #[test]
     fn test_run_parse() {
         // generate_meta().unwrap();
-        let parsed = run_parse_opt(ISLEParseOptions::Lower).unwrap();
+        let parsed = run_parse_opt(ISLEParseOptions::Lower, &default_cranelift_src()).unwrap();
         println!("{:#?}", parsed.defs);
     }
 
     #[test]
     fn test_run_parse_rules() {
         // generate_meta().unwrap();
-        let parsed = run_parse_opt(ISLEParseOptions::Opt).unwrap();
+        let parsed = run_parse_opt(ISLEParseOptions::Opt, &default_cranelift_src()).unwrap();
         for def in parsed.defs {
             match def {
                 ast::Def::Rule(rule) => println!("{:#?}", rule),
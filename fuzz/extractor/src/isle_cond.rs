@@ -308,13 +308,13 @@ fn copy_expr_to_var(lin_result: &mut LinResult, var_idx: &LinExprIdx, expr_idx:
 
 #[cfg(test)]
 mod test {
-    use crate::{isle::ISLEParseOptions, isle_inl::process_internals, isle_lin::linearize_rules_opt};
+    use crate::{isle::{default_cranelift_src, ISLEParseOptions}, isle_inl::process_internals, isle_lin::linearize_rules_opt};
 
     use super::*;
 
     #[test]
     fn test_process_conds() {
-        let results = linearize_rules_opt(ISLEParseOptions::Lower);
+        let results = linearize_rules_opt(ISLEParseOptions::Lower, &default_cranelift_src());
         let inl_results: Vec<_> = results.into_iter().flat_map(process_internals).collect();
         println!("{:#?}", inl_results.into_iter().flat_map(process_conds).collect::<Vec<_>>());
     }
@@ -0,0 +1,145 @@
+// Unknown-directive detector: extern terms of simplify/lower rules without a handler in isle_inl
+
+use std::{collections::{BTreeMap, BTreeSet}, path::Path, sync::Arc};
+
+use anyhow::{anyhow, Error};
+use cranelift_isle::ast::{Def, Extern};
+use serde::{Deserialize, Serialize};
+
+use crate::{diagnostics::{format_table, Diagnostics}, isle::run_parse_opt, isle_inl::get_directive, isle_type::type_rules_opt, norm::{LinExpr, LinExprIdx, LinResult, RuleSource}, rule_match::MatchOption};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExternKind {
+    Constructor,
+    Extractor,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UnknownDirective {
+    pub name: String,
+    pub kinds: Vec<ExternKind>,
+    pub signature: Option<String>, // (decl name (args..) ret)
+    pub decl: Option<String>, // file:line of the decl
+    pub blocked_rules: Vec<RuleSource>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct ExternInfo {
+    kinds: BTreeSet<ExternKind>,
+    signature: Option<String>,
+    decl: Option<String>,
+}
+
+// extern constructors and extractors of the parsed ISLE files
+fn get_externs(defs: &[Def], filenames: &[Arc<str>], src_root: &Path) -> BTreeMap<String, ExternInfo> {
+    let mut externs: BTreeMap<String, ExternInfo> = BTreeMap::new();
+    for def in defs {
+        match def {
+            Def::Extern(Extern::Constructor { term, .. }) => {
+                externs.entry(term.0.clone()).or_default().kinds.insert(ExternKind::Constructor);
+            },
+            Def::Extern(Extern::Extractor { term, .. }) => {
+                externs.entry(term.0.clone()).or_default().kinds.insert(ExternKind::Extractor);
+            },
+            _ => (),
+        }
+    }
+
+    for def in defs {
+        match def {
+            Def::Decl(decl) if externs.contains_key(&decl.term.0) => {
+                let info = externs.get_mut(&decl.term.0).unwrap();
+                let arg_tys = decl.arg_tys.iter().map(|x| x.0.as_str()).collect::<Vec<_>>().join(" ");
+                info.signature = Some(format!("(decl {} ({}) {})", decl.term.0, arg_tys, decl.ret_ty.0));
+                let filename = Path::new(&*filenames[decl.pos.file]);
+                let filename = filename.strip_prefix(src_root).unwrap_or(filename);
+                info.decl = Some(format!("{}:{}", filename.display(), decl.pos.line));
+            },
+            _ => (),
+        }
+    }
+    externs
+}
+
+// expression names left in a typed rule; lowering rules only match on LHS
+fn get_expr_names(lin_result: &LinResult) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    for idx in lin_result.get_idx_list() {
+        if lin_result.rule.is_lower && matches!(idx, LinExprIdx::RHS(_)) {
+            continue;
+        }
+        match lin_result.get(&idx) {
+            Some(LinExpr::Expr { name, .. }) => { names.insert(name.clone()); },
+            _ => (),
+        }
+    }
+    names
+}
+
+// extern terms that still appear in simplify/lower rules after process_internals and are not in
+// isle_inl::DIRECTIVES, i.e., directives that need a handler (most blocking first)
+pub fn find_unknown_directives(match_option: MatchOption, src_root: &Path) -> Result<Vec<UnknownDirective>, Error> {
+    let mut externs = BTreeMap::new();
+    let mut blocked: BTreeMap<String, BTreeSet<RuleSource>> = BTreeMap::new();
+    for parse_option in match_option.parse_options() {
+        let parsed = run_parse_opt(parse_option, src_root).map_err(|e| anyhow!("ISLE parsing failed with {:?}", e))?;
+        externs.append(&mut get_externs(&parsed.defs, &parsed.filenames, src_root));
+
+        for lin_result in type_rules_opt(parse_option, src_root) {
+            let target = match Diagnostics::get_target(&lin_result.rule.sources) {
+                Some(x) => x.clone(),
+                None => continue,
+            };
+            for name in get_expr_names(&lin_result) {
+                if externs.contains_key(&name) && get_directive(&name).is_none() {
+                    blocked.entry(name).or_default().insert(target.clone());
+                }
+            }
+        }
+    }
+
+    let mut directives = blocked.into_iter().map(|(name, sources)| {
+        let info = externs.remove(&name).unwrap_or_default();
+        UnknownDirective {
+            name,
+            kinds: info.kinds.into_iter().collect(),
+            signature: info.signature,
+            decl: info.decl,
+            blocked_rules: sources.into_iter().collect(),
+        }
+    }).collect::<Vec<_>>();
+    directives.sort_by(|x, y| y.blocked_rules.len().cmp(&x.blocked_rules.len()).then(x.name.cmp(&y.name)));
+    Ok(directives)
+}
+
+pub fn directives_to_table(directives: &[UnknownDirective]) -> String {
+    let rows = directives.iter().map(|x| [
+        x.name.clone(),
+        x.kinds.iter().map(|y| format!("{:?}", y).to_lowercase()).collect::<Vec<_>>().join("/"),
+        x.blocked_rules.len().to_string(),
+        x.decl.clone().unwrap_or_default(),
+        x.signature.clone().unwrap_or_default(),
+    ]).collect::<Vec<_>>();
+
+    let mut table = format_table(["directive", "kind", "rules", "decl", "signature"], &rows, &[2]);
+    let blocked_cnt = directives.iter().flat_map(|x| x.blocked_rules.iter()).collect::<BTreeSet<_>>().len();
+    table.push_str(&format!("\n{} unknown directives blocking {} rules\n", directives.len(), blocked_cnt));
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use crate::isle::default_cranelift_src;
+
+    use super::*;
+
+    #[test]
+    fn test_find_unknown_directives() {
+        let directives = find_unknown_directives(MatchOption::Opt, &default_cranelift_src()).unwrap();
+        println!("{}", directives_to_table(&directives));
+        for directive in directives {
+            assert!(get_directive(&directive.name).is_none());
+        }
+    }
+}
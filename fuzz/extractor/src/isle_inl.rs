@@ -1,6 +1,6 @@
 // Step 1.4.1: Process compiler internals of linearized ISLE rules
 
-use std::collections::{HashMap, HashSet};
+use std::{collections::{HashMap, HashSet}, sync::OnceLock};
 
 use cranelift_codegen::ir::{dynamic_to_fixed, types, Type};
 
@@ -9,56 +9,334 @@ use crate::norm::{get_all_types, get_imm128_types, get_imm32_types, get_imm64_ty
 // Special names for custom identifiers
 const VP_NAME: &str = "VP"; // value passing expression
 
-// expression names that needs transformation of typevars back to vars
-const TYPEVAR_REVERT_LIST: &'static [&'static str] = &[
-    "i64_neg",
-    "u64_add",
-    "u64_sub",
-    "u64_mul",
-    "u64_sdiv",
-    "u64_udiv",
-    "u64_and",
-    "u64_or",
-    "u64_xor",
-    "u64_shl",
-    "u64_not",
-    "u64_eq",
-    "u64_le",
-    "u64_lt",
-    "u64_is_zero",
-    "u64_is_odd",
-    "u64_from_bool",
-    "trap_code_division_by_zero",
-    "trap_code_integer_overflow",
-    "trap_code_bad_conversion_to_integer",
-    "u32_add",
-    "u32_sub",
-    "u32_and",
-    "s32_add_falliable",
-    "u32_lteq",
-    "u8_lteq",
-    "u8_lt",
-    "u8_and",
-    "u8_shl",
-    "u8_shr",
-    "ty_equal", // ignored
-    "range", // ignored
-    "range_view", // ignored
-    "mem_flags_trusted",
-    "intcc_unsigned",
-    "signed_cond_code", // ignored
-    "intcc_reverse", // ignored
-    "intcc_inverse", // ignored
-    "floatcc_reverse", // ignored
-    "floatcc_inverse", // ignored
-    "floatcc_unordered", // ignored
-    "imm12_and",
-    "imm12_const_add",
-    "offset32_add",
-    "uimm32shifted",
-    "uimm16shifted",
-    "i64_nonequal",
-];
+// How a compiler directive is processed
+#[derive(Clone, Copy)]
+pub(crate) enum DirectiveKind {
+    TypePred(fn(&Type) -> bool), // specialize typevar to the types satisfying the predicate (inl_ty_intersect)
+    Imm(fn(&Type) -> bool), // typecast integer, specialize typevar of predec. const (inl_imm)
+    Identity, // value passing (inl_identity)
+    Identity2, // value passing with a type variable (inl_identity_2)
+    TypeSep(fn(&Type) -> Option<i128>), // group typevars by the value, change expr to the const (inl_ty_sep)
+    NoVarCond(&'static str), // condition without vars (inl_novarcond)
+    NoVarCondImm(&'static str, fn(&Type) -> bool), // condition without vars, specialize predec. const (inl_novarcond_imm)
+    VarCond1(&'static str), // condition on the param (inl_varcond_1)
+    Revert, // transform typevars back to vars (inl_revert_typevar)
+    SinkLoad(&'static str, fn(&Type) -> bool), // value defined by the load opcode of the types (inl_sink_load)
+    Custom(fn(&LinResult, &LinExprIdx) -> Option<Vec<LinResult>>), // hand-written handler
+}
+
+pub(crate) struct Directive {
+    pub name: &'static str,
+    pub kind: DirectiveKind,
+}
+
+macro_rules! directives {
+    ($($name:literal => $kind:expr,)*) => {
+        &[$(Directive { name: $name, kind: $kind },)*]
+    };
+}
+
+fn is_imm32(ty: &Type) -> bool { get_imm32_types().contains(ty) }
+fn is_imm64(ty: &Type) -> bool { get_imm64_types().contains(ty) }
+fn is_imm128(ty: &Type) -> bool { get_imm128_types().contains(ty) }
+
+// dynamic vector types handled by ty_dyn_vec64 and ty_dyn_vec128
+fn is_dyn_vec(ty: &Type) -> bool {
+    [
+        types::I8X8XN, types::I8X16XN, types::I16X4XN, types::I16X8XN, types::I32X2XN,
+        types::I32X4XN, types::I64X2XN, types::F32X4XN, types::F64X2XN,
+    ].contains(ty)
+}
+
+fn get_half_width(ty: &Type) -> Option<Type> {
+    match ty.lane_type() {
+        types::I16 => types::I8.by(ty.lane_count()),
+        types::I32 => types::I16.by(ty.lane_count()),
+        types::I64 => types::I32.by(ty.lane_count()),
+        types::F64 => types::F32.by(ty.lane_count()),
+        _ => None,
+    }
+}
+
+// max. value of the type with the given shift of u64 (ty_mask, ty_smin, ...)
+fn ty_shifted_mask(bits: u32, val: u64) -> Option<i128> {
+    if bits > 64 || bits == 0 { None }
+    else { Some((val >> (64 - bits)) as i128) }
+}
+
+// Compiler directives (extern terms of ISLE) and how to process them.
+// For a new directive, add an entry here; write a Custom handler only if no kind fits.
+use DirectiveKind::*;
+pub(crate) static DIRECTIVES: &[Directive] = directives! {
+    // type predicates
+    "fits_in_64" => TypePred(|ty| ty.bits() <= 64 && !ty.is_dynamic_vector()),
+    "fits_in_32" => TypePred(|ty| ty.bits() <= 32 && !ty.is_dynamic_vector()),
+    "fits_in_16" => TypePred(|ty| ty.bits() <= 16 && !ty.is_dynamic_vector()),
+    "lane_fits_in_32" => TypePred(|ty| (ty.is_vector() || ty.is_dynamic_vector()) && ty.lane_type().bits() <= 32),
+    "ty_int_ref_scalar_64" => TypePred(|ty| ty.bits() <= 64 && !ty.is_float() && !ty.is_vector()),
+    "ty_int_ref_scalar_64_extract" => TypePred(|ty| ty.bits() <= 64 && !ty.is_float() && !ty.is_vector()),
+    "ty_32" => TypePred(|ty| ty.bits() == 32),
+    "ty_64" => TypePred(|ty| ty.bits() == 64),
+    "ty_32_or_64" => TypePred(|ty| ty.bits() == 32 || ty.bits() == 64),
+    "ty_8_or_16" => TypePred(|ty| ty.bits() == 8 || ty.bits() == 16),
+    "int_fits_in_32" => TypePred(|ty| [types::I8, types::I16, types::I32].contains(ty)),
+    "ty_int_ref_64" => TypePred(|ty| [types::I64, types::R64].contains(ty)),
+    "ty_int_ref_16_to_64" => TypePred(|ty| [types::I16, types::I32, types::I64, types::R64].contains(ty)),
+    "ty_int" => TypePred(|ty| ty.is_int()),
+    "ty_scalar" => TypePred(|ty| ty.lane_count() == 1),
+    "ty_scalar_float" => TypePred(|ty| [types::F32, types::F64].contains(ty)),
+    "ty_float_or_vec" => TypePred(|ty| [types::F32, types::F64].contains(ty) || ty.is_vector()),
+    "ty_vector_float" => TypePred(|ty| ty.is_vector() && ty.lane_type().is_float()),
+    "ty_vector_not_float" => TypePred(|ty| ty.is_vector() && !ty.lane_type().is_float()),
+    "ty_vec64" => TypePred(|ty| ty.is_vector() && ty.bits() == 64),
+    "ty_vec64_ctor" => TypePred(|ty| ty.is_vector() && ty.bits() == 64),
+    "ty_vec128" => TypePred(|ty| ty.is_vector() && ty.bits() == 128),
+    "ty_dyn_vec64" => TypePred(|ty| is_dyn_vec(ty) && dynamic_to_fixed(*ty).bits() == 64),
+    "ty_dyn_vec128" => TypePred(|ty| is_dyn_vec(ty) && dynamic_to_fixed(*ty).bits() == 128),
+    "ty_vec64_int" => TypePred(|ty| ty.is_vector() && ty.bits() == 64 && ty.lane_type().is_int()),
+    "ty_vec128_int" => TypePred(|ty| ty.is_vector() && ty.bits() == 128 && ty.lane_type().is_int()),
+    "ty_addr64" => TypePred(|ty| [types::I64, types::R64].contains(ty)),
+    "ty_dyn64_int" => TypePred(|ty| ty.is_dynamic_vector() && ty.min_bits() == 64 && ty.lane_type().is_int()),
+    "ty_dyn128_int" => TypePred(|ty| ty.is_dynamic_vector() && ty.min_bits() == 128 && ty.lane_type().is_int()),
+    "not_vec32x2" => TypePred(|ty| !(ty.lane_bits() == 32 && ty.lane_count() == 2)),
+    "not_i64x2" => Custom(inl_not_i64x2),
+    "lane_type" => Custom(inl_lane_type),
+    "ty_half_lanes" => Custom(inl_ty_half_lanes),
+    "ty_half_width" => Custom(inl_ty_half_width),
+
+    // immediates
+    "u64_from_imm64" => Imm(is_imm64),
+    "u128_from_immediate" => Imm(is_imm128),
+    "imm64" => Imm(is_imm64),
+    "imm64_masked" => Custom(inl_imm64_masked),
+    "simm32" => Imm(is_imm32),
+    "simm32_from_value" => Imm(is_imm32),
+    "simm32_from_imm64" => Imm(is_imm32),
+    "uimm8" => Imm(|ty| *ty == types::I8),
+    "offset32" => Imm(is_imm32),
+    "offset32_to_u32" => Imm(is_imm32),
+    "u32_to_offset32" => Imm(is_imm32),
+    "u32_from_ieee32" => Imm(|ty| *ty == types::F32), // float from bit repr.
+    "u64_from_ieee64" => Imm(|ty| *ty == types::F64),
+    "multi_lane" => Custom(inl_multi_lane),
+    "dynamic_lane" => Custom(inl_dynamic_lane),
+    "i64_sextend_imm64" => Custom(inl_i64_sextend_imm64),
+    "i64_sextend_u64" => Custom(inl_i64_sextend_imm64),
+    "u64_uextend_imm64" => Custom(inl_u64_uextend_imm64),
+    "i64_from_iconst" => Custom(inl_u64_from_iconst),
+    "u64_from_iconst" => Custom(inl_u64_from_iconst),
+
+    // value passing
+    "emit_u64_le_const" => Identity,
+    "emit_u128_le_const" => Identity,
+    "subsume" => Identity,
+    "remat" => Identity,
+    "u8_as_u32" => Identity,
+    "u8_as_u64" => Identity,
+    "u16_as_u64" => Identity,
+    "u32_as_u64" => Identity,
+    "i64_as_u64" => Identity,
+    "u64_as_i32" => Identity,
+    "u128_as_u64" => Identity,
+    "u64_as_u32" => Identity,
+    "u64_as_i16" => Identity,
+    "imm12_from_u64" => Identity,
+    "imm12_from_i64" => Identity,
+    "imm5_from_u64" => Identity,
+    "imm5_from_i8" => Identity,
+    "uimm5_bitcast_to_imm5" => Identity,
+    "uimm5_from_u8" => Identity,
+    "uimm5_from_u64" => Identity,
+    "imm_shift_from_u8" => Identity,
+    "imm_from_bits" => Identity,
+    "imm_from_neg_bits" => Identity,
+    "imm_shift_from_imm64" => Identity2,
+    "u64_into_imm_logic" => Identity2,
+    "u8_from_uimm8" => Identity,
+    "vconst_from_immediate" => Identity,
+    "vec_mask_from_immediate" => Identity,
+    "u64_from_constant" => Identity,
+    "u128_from_constant" => Identity,
+    "const_to_vconst" => Identity,
+    "u8_into_uimm5" => Identity,
+    "u8_into_imm12" => Identity,
+    "i32_from_u64" => Identity,
+    "i16_from_u64" => Identity,
+    "i16_from_u32" => Identity,
+    "uimm32shifted_from_u64" => Identity,
+    "uimm16shifted_from_u64" => Identity,
+    "shuffle_mask_from_u128" => Identity,
+    "u64_from_value" => Identity,
+    "u32_from_value" => Identity,
+    "u8_from_value" => Identity,
+    "u8_from_signed_value" => Identity,
+    "i64_from_value" => Identity,
+    "i32_from_value" => Identity,
+    "i16_from_value" => Identity,
+    "uimm16shifted_from_value" => Identity,
+    "uimm32shifted_from_value" => Identity,
+    "i64_from_offset" => Identity,
+    "shift_amount_masked" => Identity2,
+    "imm12_const" => Identity,
+    "offset32_imm" => Identity,
+    "u64_truncate_to_u32" => Identity,
+    "shift_masked_imm" => Identity2,
+    "maybe_uextend" => Identity, // i8 compares widened to i32 are allowed when matching icmp/fcmp
+    "value_type" => Custom(inl_value_type),
+    "has_type" => Custom(inl_has_type),
+
+    // conditions
+    "splat64" => VarCond1("_splat64"), // v | v << 64
+    "imm64_power_of_two" => Custom(inl_imm64_power_of_two),
+    "u64_nonzero" => NoVarCondImm("_nonzero", is_imm64),
+    "i64_nonzero" => NoVarCondImm("_nonzero", is_imm64),
+    "nonzero_u64_from_imm64" => NoVarCondImm("_nonzero", is_imm64),
+    "u64_nonzero_hipart" => NoVarCondImm("_nonzero_hipart", is_imm64),
+    "u64_nonzero_lopart" => NoVarCondImm("_nonzero_lopart", is_imm64),
+    "nonzero_u64_fits_in_u32" => Custom(inl_nonzero_u64_fits_in_u32),
+    "vconst_all_ones_or_all_zeros" => Custom(inl_vconst_all_ones_or_all_zeros),
+    "pshufd_lhs_imm" => NoVarCond("_pshufd_lhs_imm"),
+    "pshufd_rhs_imm" => NoVarCond("_pshufd_rhs_imm"),
+    "shufps_imm" => NoVarCond("_shufps_imm"),
+    "shufps_rev_imm" => NoVarCond("_shufps_rev_imm"),
+    "pshuflw_lhs_imm" => NoVarCond("_pshuflw_lhs_imm"),
+    "pshuflw_rhs_imm" => NoVarCond("_pshuflw_rhs_imm"),
+    "pshufhw_lhs_imm" => NoVarCond("_pshufhw_lhs_imm"),
+    "pshufhw_rhs_imm" => NoVarCond("_pshufhw_rhs_imm"),
+    "palignr_imm_from_immediate" => NoVarCond("_palignr_imm_from_immediate"),
+    "pblendw_imm" => NoVarCond("_pblendw_imm"),
+    "u32_nonnegative" => NoVarCondImm("_nonnegative", is_imm32),
+    "sse_interps_lane_imm" => VarCond1("_sse_interps_lane_imm"), // 0 | lane << 4
+    "u64_from_inverted_value" => VarCond1("_inverted"),
+    "uimm16shifted_from_inverted_value" => VarCond1("_inverted"),
+    "uimm32shifted_from_inverted_value" => VarCond1("_inverted"),
+    "i16_from_swapped_value" => VarCond1("_swapped"),
+    "i64_from_negated_value" => VarCond1("_negate"),
+    "i32_from_negated_value" => VarCond1("_negate"),
+    "i16_from_negated_value" => VarCond1("_negate"),
+    "len_minus_one" => VarCond1("_minusone"),
+    "shuffle64_from_imm" => Custom(inl_shuffle64_from_imm),
+    "shuffle32_from_imm" => Custom(inl_shuffle32_from_imm),
+    "shuffle16_from_imm" => Custom(inl_shuffle16_from_imm),
+    "shuffle_dup8_from_imm" => VarCond1("_shuffle_dup8_from_imm"),
+    "shuffle_dup16_from_imm" => VarCond1("_shuffle_dup16_from_imm"),
+    "shuffle_dup32_from_imm" => VarCond1("_shuffle_dup32_from_imm"),
+    "shuffle_dup64_from_imm" => VarCond1("_shuffle_dup64_from_imm"),
+    "u64_low32_bits_unset" => VarCond1("_u64_low32_bits_unset"),
+    "u128_replicated_u64" => VarCond1("_u128_replicated_u64"),
+    "u64_replicated_u32" => VarCond1("_u64_replicated_u32"),
+    "u32_replicated_u16" => VarCond1("_u32_replicated_u16"),
+    "u16_replicated_u8" => VarCond1("_u16_replicated_u8"),
+    "safe_divisor_from_imm64" => Custom(inl_safe_divisor_from_imm64),
+
+    // type-dependent constants
+    "ty_bits" => TypeSep(|ty| Some(ty.bits() as i128)),
+    "ty_bits_u16" => TypeSep(|ty| Some(ty.bits() as i128)),
+    "ty_bits_u64" => TypeSep(|ty| Some(ty.bits() as i128)),
+    "ty_bytes" => TypeSep(|ty| Some(ty.bytes() as i128)),
+    "ty_mask" => TypeSep(|ty| ty_shifted_mask(ty.bits(), u64::MAX)),
+    "ty_lane_mask" => TypeSep(|ty| ty_shifted_mask(ty.lane_count(), u64::MAX)),
+    "ty_lane_count" => TypeSep(|ty| Some(ty.lane_count() as i128)),
+    "ty_umin" => Custom(inl_ty_umin),
+    "ty_umax" => TypeSep(|ty| ty_shifted_mask(ty.bits(), u64::MAX)),
+    "ty_smin" => TypeSep(|ty| ty_shifted_mask(ty.bits(), i64::MIN as u64)),
+    "ty_smax" => TypeSep(|ty| ty_shifted_mask(ty.bits(), i64::MAX as u64)),
+    "shift_mask" => TypeSep(|ty| Some((ty.lane_bits().checked_sub(1)?) as i128)),
+    "zero_offset" => Custom(inl_zero_offset),
+    "fcvt_to_uint_ub32" => Custom(inl_fcvt_to_uint_ub32),
+    "fcvt_to_uint_lb32" => Custom(inl_fcvt_to_uint_lb32),
+    "fcvt_to_uint_ub64" => Custom(inl_fcvt_to_uint_ub64),
+    "fcvt_to_uint_lb64" => Custom(inl_fcvt_to_uint_lb64),
+    "fcvt_to_sint_ub32" => Custom(inl_fcvt_to_sint_ub32),
+    "fcvt_to_sint_lb32" => Custom(inl_fcvt_to_sint_lb32),
+    "fcvt_to_sint_ub64" => Custom(inl_fcvt_to_sint_ub64),
+    "fcvt_to_sint_lb64" => Custom(inl_fcvt_to_sint_lb64),
+
+    // loads merged into their users
+    "sinkable_load" => SinkLoad("load", |ty| ty.bits() >= 32),
+    "sinkable_load_exact" => SinkLoad("load", |_| true),
+    "sinkable_load_32_64" => SinkLoad("load", |ty| ty.bits() == 32 || ty.bits() == 64),
+    "sinkable_load_16" => SinkLoad("load", |ty| ty.bits() == 16),
+    "sinkable_load_little" => SinkLoad("load", |_| true),
+    "sinkable_sload16" => SinkLoad("sload16", |ty| [types::I32, types::I64].contains(ty)),
+    "sinkable_uload16" => SinkLoad("uload16", |ty| [types::I32, types::I64].contains(ty)),
+    "sinkable_sload32" => SinkLoad("sload32", |ty| *ty == types::I64),
+    "sinkable_uload32" => SinkLoad("uload32", |ty| *ty == types::I64),
+
+    // typevars back to vars
+    "i64_neg" => Revert,
+    "u64_add" => Revert,
+    "u64_sub" => Revert,
+    "u64_mul" => Revert,
+    "u64_sdiv" => Revert,
+    "u64_udiv" => Revert,
+    "u64_and" => Revert,
+    "u64_or" => Revert,
+    "u64_xor" => Revert,
+    "u64_shl" => Revert,
+    "u64_not" => Revert,
+    "u64_eq" => Revert,
+    "u64_le" => Revert,
+    "u64_lt" => Revert,
+    "u64_is_zero" => Revert,
+    "u64_is_odd" => Revert,
+    "u64_from_bool" => Revert,
+    "trap_code_division_by_zero" => Revert,
+    "trap_code_integer_overflow" => Revert,
+    "trap_code_bad_conversion_to_integer" => Revert,
+    "u32_add" => Revert,
+    "u32_sub" => Revert,
+    "u32_and" => Revert,
+    "s32_add_falliable" => Revert,
+    "u32_lteq" => Revert,
+    "u8_lteq" => Revert,
+    "u8_lt" => Revert,
+    "u8_and" => Revert,
+    "u8_shl" => Revert,
+    "u8_shr" => Revert,
+    "ty_equal" => Revert, // ignored
+    "range" => Revert, // ignored
+    "range_view" => Revert, // ignored
+    "mem_flags_trusted" => Revert,
+    "intcc_unsigned" => Revert,
+    "signed_cond_code" => Revert, // ignored
+    "intcc_reverse" => Revert, // ignored
+    "intcc_inverse" => Revert, // ignored
+    "floatcc_reverse" => Revert, // ignored
+    "floatcc_inverse" => Revert, // ignored
+    "floatcc_unordered" => Revert, // ignored
+    "imm12_and" => Revert,
+    "imm12_const_add" => Revert,
+    "offset32_add" => Revert,
+    "uimm32shifted" => Revert,
+    "uimm16shifted" => Revert,
+    "i64_nonequal" => Revert,
+};
+
+pub(crate) fn get_directive(name: &str) -> Option<&'static Directive> {
+    static DIRECTIVE_MAP: OnceLock<HashMap<&'static str, &'static Directive>> = OnceLock::new();
+    let directive_map = DIRECTIVE_MAP.get_or_init(|| DIRECTIVES.iter().map(|x| (x.name, x)).collect());
+    directive_map.get(name).copied()
+}
+
+impl Directive {
+    fn apply(&self, lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
+        let get_types = |pred: fn(&Type) -> bool| get_all_types().into_iter().filter(|x| pred(x)).collect::<Vec<_>>();
+        match self.kind {
+            TypePred(pred) => inl_ty_intersect(self.name, &get_types(pred), lin_result, cur_idx),
+            Imm(pred) => inl_imm(self.name, &get_types(pred), lin_result, cur_idx),
+            Identity => inl_identity(self.name, lin_result, cur_idx),
+            Identity2 => inl_identity_2(self.name, lin_result, cur_idx),
+            TypeSep(func) => inl_ty_sep(self.name, func, lin_result, cur_idx),
+            NoVarCond(cond_name) => inl_novarcond(self.name, cond_name, lin_result, cur_idx),
+            NoVarCondImm(cond_name, pred) => inl_novarcond_imm(self.name, cond_name, &get_types(pred), lin_result, cur_idx),
+            VarCond1(cond_name) => inl_varcond_1(self.name, cond_name, lin_result, cur_idx),
+            Revert => inl_revert_typevar(lin_result, cur_idx),
+            SinkLoad(load_name, pred) => inl_sink_load(self.name, load_name, &get_types(pred), lin_result, cur_idx),
+            Custom(handler) => handler(lin_result, cur_idx),
+        }
+    }
+}
 
 pub fn process_internals(lin_result: LinResult) -> Vec<LinResult> {
     let mut results = vec![lin_result.deep_clone()];
@@ -160,129 +438,15 @@ fn process_internals_all(lin_result: &LinResult) -> Option<Vec<LinResult>> {
 }
 
 fn process_internals_one(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_constprim_type(lin_result, cur_idx)
-        .or_else(|| inl_fits_in_64(lin_result, cur_idx))
-        .or_else(|| inl_fits_in_32(lin_result, cur_idx))
-        .or_else(|| inl_fits_in_16(lin_result, cur_idx))
-        .or_else(|| inl_lane_fits_in_32(lin_result, cur_idx))
-        .or_else(|| inl_ty_int_ref_scalar_64(lin_result, cur_idx))
-        .or_else(|| inl_ty_32(lin_result, cur_idx))
-        .or_else(|| inl_ty_64(lin_result, cur_idx))
-        .or_else(|| inl_ty_32_or_64(lin_result, cur_idx))
-        .or_else(|| inl_ty_8_or_16(lin_result, cur_idx))
-        .or_else(|| inl_int_fits_in_32(lin_result, cur_idx))
-        .or_else(|| inl_ty_int_ref_64(lin_result, cur_idx))
-        .or_else(|| inl_ty_int_ref_16_to_64(lin_result, cur_idx))
-        .or_else(|| inl_ty_int(lin_result, cur_idx))
-        .or_else(|| inl_ty_scalar(lin_result, cur_idx))
-        .or_else(|| inl_ty_scalar_float(lin_result, cur_idx))
-        .or_else(|| inl_ty_float_or_vec(lin_result, cur_idx))
-        .or_else(|| inl_ty_vector_float(lin_result, cur_idx))
-        .or_else(|| inl_ty_vector_not_float(lin_result, cur_idx))
-        .or_else(|| inl_ty_vec64(lin_result, cur_idx))
-        .or_else(|| inl_ty_vec128(lin_result, cur_idx))
-        .or_else(|| inl_ty_dyn_vec64(lin_result, cur_idx))
-        .or_else(|| inl_ty_dyn_vec128(lin_result, cur_idx))
-        .or_else(|| inl_ty_vec64_int(lin_result, cur_idx))
-        .or_else(|| inl_ty_vec128_int(lin_result, cur_idx))
-        .or_else(|| inl_ty_addr64(lin_result, cur_idx))
-        .or_else(|| inl_ty_dyn64_int(lin_result, cur_idx))
-        .or_else(|| inl_ty_dyn128_int(lin_result, cur_idx))
-        .or_else(|| inl_not_vec32x2(lin_result, cur_idx))
-        .or_else(|| inl_not_i64x2(lin_result, cur_idx))
-        .or_else(|| inl_lane_type(lin_result, cur_idx))
-        .or_else(|| inl_ty_half_lanes(lin_result, cur_idx))
-        .or_else(|| inl_ty_half_width(lin_result, cur_idx))
-        .or_else(|| inl_u64_from_imm64(lin_result, cur_idx))
-        .or_else(|| inl_u128_from_immediate(lin_result, cur_idx))
-        .or_else(|| inl_imm64(lin_result, cur_idx))
-        .or_else(|| inl_imm64_masked(lin_result, cur_idx))
-        .or_else(|| inl_simm32(lin_result, cur_idx))
-        .or_else(|| inl_simm32_from_value(lin_result, cur_idx))
-        .or_else(|| inl_simm32_from_imm64(lin_result, cur_idx))
-        .or_else(|| inl_uimm8(lin_result, cur_idx))
-        .or_else(|| inl_offset32(lin_result, cur_idx))
-        .or_else(|| inl_offset32_to_u32(lin_result, cur_idx))
-        .or_else(|| inl_u32_to_offset32(lin_result, cur_idx))
-        .or_else(|| inl_u32_from_ieee32(lin_result, cur_idx))
-        .or_else(|| inl_u64_from_ieee64(lin_result, cur_idx))
-        .or_else(|| inl_emit_u64_le_const(lin_result, cur_idx))
-        .or_else(|| inl_emit_u128_le_const(lin_result, cur_idx))
-        .or_else(|| inl_multi_lane(lin_result, cur_idx))
-        .or_else(|| inl_dynamic_lane(lin_result, cur_idx))
-        .or_else(|| inl_i64_sextend_imm64(lin_result, cur_idx))
-        .or_else(|| inl_u64_uextend_imm64(lin_result, cur_idx))
-        .or_else(|| inl_subsume(lin_result, cur_idx))
-        .or_else(|| inl_remat(lin_result, cur_idx))
-        .or_else(|| inl_splat64(lin_result, cur_idx))
-        .or_else(|| inl_ty_as_ty(lin_result, cur_idx))
-        .or_else(|| inl_ty_from_ty_identity(lin_result, cur_idx))
-        .or_else(|| inl_shift_amount_masked(lin_result, cur_idx))
-        .or_else(|| inl_imm12_const(lin_result, cur_idx))
-        .or_else(|| inl_offset32_imm(lin_result, cur_idx))
-        .or_else(|| inl_u64_truncate_to_u32(lin_result, cur_idx))
-        .or_else(|| inl_shift_masked_imm(lin_result, cur_idx))
-        .or_else(|| inl_value_type(lin_result, cur_idx))
-        .or_else(|| inl_imm64_power_of_two(lin_result, cur_idx))
-        .or_else(|| inl_u64_nonzero(lin_result, cur_idx))
-        .or_else(|| inl_nonzero_u64_fits_in_u32(lin_result, cur_idx))
-        .or_else(|| inl_vconst_all_ones_or_all_zeros(lin_result, cur_idx))
-        .or_else(|| inl_pshufd_lhs_imm(lin_result, cur_idx))
-        .or_else(|| inl_pshufd_rhs_imm(lin_result, cur_idx))
-        .or_else(|| inl_shufps_imm(lin_result, cur_idx))
-        .or_else(|| inl_shufps_rev_imm(lin_result, cur_idx))
-        .or_else(|| inl_pshuflw_lhs_imm(lin_result, cur_idx))
-        .or_else(|| inl_pshuflw_rhs_imm(lin_result, cur_idx))
-        .or_else(|| inl_pshufhw_lhs_imm(lin_result, cur_idx))
-        .or_else(|| inl_pshufhw_rhs_imm(lin_result, cur_idx))
-        .or_else(|| inl_palignr_imm_from_immediate(lin_result, cur_idx))
-        .or_else(|| inl_pblendw_imm(lin_result, cur_idx))
-        .or_else(|| inl_u32_nonnegative(lin_result, cur_idx))
-        .or_else(|| inl_sse_interps_lane_imm(lin_result, cur_idx))
-        .or_else(|| inl_u64_from_inverted_value(lin_result, cur_idx))
-        .or_else(|| inl_uimm16shifted_from_inverted_value(lin_result, cur_idx))
-        .or_else(|| inl_uimm32shifted_from_inverted_value(lin_result, cur_idx))
-        .or_else(|| inl_i16_from_swapped_value(lin_result, cur_idx))
-        .or_else(|| inl_i64_from_negated_value(lin_result, cur_idx))
-        .or_else(|| inl_i32_from_negated_value(lin_result, cur_idx))
-        .or_else(|| inl_i16_from_negated_value(lin_result, cur_idx))
-        .or_else(|| inl_len_minus_one(lin_result, cur_idx))
-        .or_else(|| inl_shuffle64_from_imm(lin_result, cur_idx))
-        .or_else(|| inl_shuffle32_from_imm(lin_result, cur_idx))
-        .or_else(|| inl_shuffle16_from_imm(lin_result, cur_idx))
-        .or_else(|| inl_shuffle_dup8_from_imm(lin_result, cur_idx))
-        .or_else(|| inl_shuffle_dup16_from_imm(lin_result, cur_idx))
-        .or_else(|| inl_shuffle_dup32_from_imm(lin_result, cur_idx))
-        .or_else(|| inl_shuffle_dup64_from_imm(lin_result, cur_idx))
-        .or_else(|| inl_u64_low32_bits_unset(lin_result, cur_idx))
-        .or_else(|| inl_u128_replicated_u64(lin_result, cur_idx))
-        .or_else(|| inl_u64_replicated_u32(lin_result, cur_idx))
-        .or_else(|| inl_u32_replicated_u16(lin_result, cur_idx))
-        .or_else(|| inl_u16_replicated_u8(lin_result, cur_idx))
-        .or_else(|| inl_neg_imm12(lin_result, cur_idx))
-        .or_else(|| inl_safe_divisor_from_imm64(lin_result, cur_idx))
-        .or_else(|| inl_has_type(lin_result, cur_idx))
-        .or_else(|| inl_ty_bits(lin_result, cur_idx))
-        .or_else(|| inl_ty_bytes(lin_result, cur_idx))
-        .or_else(|| inl_ty_mask(lin_result, cur_idx))
-        .or_else(|| inl_ty_lane_mask(lin_result, cur_idx))
-        .or_else(|| inl_ty_lane_count(lin_result, cur_idx))
-        .or_else(|| inl_ty_umin(lin_result, cur_idx))
-        .or_else(|| inl_ty_umax(lin_result, cur_idx))
-        .or_else(|| inl_ty_smin(lin_result, cur_idx))
-        .or_else(|| inl_ty_smax(lin_result, cur_idx))
-        .or_else(|| inl_shift_mask(lin_result, cur_idx))
-        .or_else(|| inl_zero_offset(lin_result, cur_idx))
-        .or_else(|| inl_fcvt_to_uint_ub32(lin_result, cur_idx))
-        .or_else(|| inl_fcvt_to_uint_lb32(lin_result, cur_idx))
-        .or_else(|| inl_fcvt_to_uint_ub64(lin_result, cur_idx))
-        .or_else(|| inl_fcvt_to_uint_lb64(lin_result, cur_idx))
-        .or_else(|| inl_fcvt_to_sint_ub32(lin_result, cur_idx))
-        .or_else(|| inl_fcvt_to_sint_lb32(lin_result, cur_idx))
-        .or_else(|| inl_fcvt_to_sint_ub64(lin_result, cur_idx))
-        .or_else(|| inl_fcvt_to_sint_lb64(lin_result, cur_idx))
-        .or_else(|| inl_u64_from_iconst(lin_result, cur_idx))
-        .or_else(|| inl_revert_typevar(lin_result, cur_idx))
+    match lin_result.get(cur_idx)? {
+        LinExpr::ConstPrim(_) => inl_constprim_type(lin_result, cur_idx),
+        LinExpr::Expr { name, .. } => match get_directive(name) {
+            Some(directive) => directive.apply(lin_result, cur_idx),
+            None if name.starts_with("IntCC.") || name.starts_with("FloatCC.") => inl_revert_typevar(lin_result, cur_idx),
+            None => None,
+        },
+        _ => None,
+    }
 }
 
 // $XXX: ConstPrim to types
@@ -304,278 +468,6 @@ fn inl_constprim_type(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Ve
     }
 }
 
-// fits_in_64: specialize typevar
-fn inl_fits_in_64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(
-        |x| x.bits() <= 64 && !x.is_dynamic_vector()
-    ).collect();
-    inl_ty_intersect("fits_in_64", &intersect_types, lin_result, cur_idx)
-}
-
-// fits_in_32: specialize typevar
-fn inl_fits_in_32(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(
-        |x| x.bits() <= 32 && !x.is_dynamic_vector()
-    ).collect();
-    inl_ty_intersect("fits_in_32", &intersect_types, lin_result, cur_idx)
-}
-
-// fits_in_16: specialize typevar
-fn inl_fits_in_16(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(
-        |x| x.bits() <= 16 && !x.is_dynamic_vector()
-    ).collect();
-    inl_ty_intersect("fits_in_16", &intersect_types, lin_result, cur_idx)
-}
-
-// lane_fits_in_32: specialize typevar
-fn inl_lane_fits_in_32(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| 
-        if !ty.is_vector() && !ty.is_dynamic_vector() {
-            false
-        } else if ty.lane_type().bits() <= 32 {
-            true
-        } else {
-            false
-        }
-    ).collect();
-    inl_ty_intersect("lane_fits_in_32", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_int_ref_scalar_64: specialize typevar
-fn inl_ty_int_ref_scalar_64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| 
-        if ty.bits() <= 64 && !ty.is_float() && !ty.is_vector() {
-            true
-        } else {
-            false
-        }
-    ).collect();
-    inl_ty_intersect("ty_int_ref_scalar_64", &intersect_types, lin_result, cur_idx)
-        .or_else(|| inl_ty_intersect("ty_int_ref_scalar_64_extract", &intersect_types, lin_result, cur_idx))
-}
-
-// ty_32: specialize typevar
-fn inl_ty_32(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| ty.bits() == 32).collect();
-    inl_ty_intersect("ty_32", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_64: specialize typevar
-fn inl_ty_64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| ty.bits() == 64).collect();
-    inl_ty_intersect("ty_64", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_32_or_64: specialize typevar
-fn inl_ty_32_or_64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| ty.bits() == 32 || ty.bits() == 64).collect();
-    inl_ty_intersect("ty_32_or_64", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_8_or_16: specialize typevar
-fn inl_ty_8_or_16(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| ty.bits() == 8 || ty.bits() == 16).collect();
-    inl_ty_intersect("ty_8_or_16", &intersect_types, lin_result, cur_idx)
-}
-
-// int_fits_in_32: specialize typevar
-fn inl_int_fits_in_32(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = vec![types::I8, types::I16, types::I32];
-    inl_ty_intersect("int_fits_in_32", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_int_ref_64: specialize typevar
-fn inl_ty_int_ref_64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = vec![types::I64, types::R64];
-    inl_ty_intersect("ty_int_ref_64", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_int_ref_16_to_64: specialize typevar
-fn inl_ty_int_ref_16_to_64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = vec![types::I16, types::I32, types::I64, types::R64];
-    inl_ty_intersect("ty_int_ref_16_to_64", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_int: specialize typevar
-fn inl_ty_int(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| ty.is_int()).collect();
-    inl_ty_intersect("ty_int", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_scalar: specialize typevar
-fn inl_ty_scalar(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| ty.lane_count() == 1).collect();
-    inl_ty_intersect("ty_scalar", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_scalar_float: specialize typevar
-fn inl_ty_scalar_float(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = vec![types::F32, types::F64];
-    inl_ty_intersect("ty_scalar_float", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_float_or_vec: specialize typevar
-fn inl_ty_float_or_vec(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| {
-        match ty {
-            &types::F32 | &types::F64 => true,
-            ty if ty.is_vector() => true,
-            _ => false,
-        }
-    }).collect();
-    inl_ty_intersect("ty_float_or_vec", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_vector_float: specialize typevar
-fn inl_ty_vector_float(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| {
-        if ty.is_vector() && ty.lane_type().is_float() {
-            true
-        } else {
-            false
-        }
-    }).collect();
-    inl_ty_intersect("ty_vector_float", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_vector_not_float: specialize typevar
-fn inl_ty_vector_not_float(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| {
-        if ty.is_vector() && !ty.lane_type().is_float() {
-            true
-        } else {
-            false
-        }
-    }).collect();
-    inl_ty_intersect("ty_vector_not_float", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_vec64: specialize typevar
-fn inl_ty_vec64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| {
-        if ty.is_vector() && ty.bits() == 64 {
-            true
-        } else {
-            false
-        }
-    }).collect();
-    inl_ty_intersect("ty_vec64", &intersect_types, lin_result, cur_idx)
-        .or_else(|| inl_ty_intersect("ty_vec64_ctor", &intersect_types, lin_result, cur_idx))
-}
-
-// ty_vec128: specialize typevar
-fn inl_ty_vec128(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| {
-        if ty.is_vector() && ty.bits() == 128 {
-            true
-        } else {
-            false
-        }
-    }).collect();
-    inl_ty_intersect("ty_vec128", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_dyn_vec64: specialize typevar
-fn inl_ty_dyn_vec64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| {
-        match ty {
-            &types::I8X8XN | &types::I8X16XN | &types::I16X4XN | &types::I16X8XN | &types::I32X2XN | 
-            &types::I32X4XN | &types::I64X2XN | &types::F32X4XN | &types::F64X2XN => (),
-            _ => { return false; },
-        }
-        if dynamic_to_fixed(ty.clone()).bits() == 64 {
-            true
-        } else {
-            false
-        }
-    }).collect();
-    inl_ty_intersect("ty_dyn_vec64", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_dyn_vec128: specialize typevar
-fn inl_ty_dyn_vec128(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| {
-        match ty {
-            &types::I8X8XN | &types::I8X16XN | &types::I16X4XN | &types::I16X8XN | &types::I32X2XN | 
-            &types::I32X4XN | &types::I64X2XN | &types::F32X4XN | &types::F64X2XN => (),
-            _ => { return false; },
-        }
-        if dynamic_to_fixed(ty.clone()).bits() == 128 {
-            true
-        } else {
-            false
-        }
-    }).collect();
-    inl_ty_intersect("ty_dyn_vec128", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_vec64_int: specialize typevar
-fn inl_ty_vec64_int(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| {
-        if ty.is_vector() && ty.bits() == 64 && ty.lane_type().is_int() {
-            true
-        } else {
-            false
-        }
-    }).collect();
-    inl_ty_intersect("ty_vec64_int", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_vec128_int: specialize typevar
-fn inl_ty_vec128_int(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| {
-        if ty.is_vector() && ty.bits() == 128 && ty.lane_type().is_int() {
-            true
-        } else {
-            false
-        }
-    }).collect();
-    inl_ty_intersect("ty_vec128_int", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_addr64: specialize typevar
-fn inl_ty_addr64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = vec![types::I64, types::R64];
-    inl_ty_intersect("ty_addr64", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_dyn64_int: specialize typevar
-fn inl_ty_dyn64_int(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| {
-        if ty.is_dynamic_vector() && ty.min_bits() == 64 && ty.lane_type().is_int() {
-            true
-        } else {
-            false
-        }
-    }).collect();
-    inl_ty_intersect("ty_dyn64_int", &intersect_types, lin_result, cur_idx)
-}
-
-// ty_dyn128_int: specialize typevar
-fn inl_ty_dyn128_int(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| {
-        if ty.is_dynamic_vector() && ty.min_bits() == 128 && ty.lane_type().is_int() {
-            true
-        } else {
-            false
-        }
-    }).collect();
-    inl_ty_intersect("ty_dyn128_int", &intersect_types, lin_result, cur_idx)
-}
-
-// not_vec32x2: specialize typevar
-fn inl_not_vec32x2(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let intersect_types = get_all_types().into_iter().filter(|ty| {
-        if ty.lane_bits() == 32 && ty.lane_count() == 2 {
-            false
-        } else {
-            true
-        }
-    }).collect();
-    inl_ty_intersect("not_vec32x2", &intersect_types, lin_result, cur_idx)
-}
-
 // not_i64x2: specialize typevar, but with the automatically added one
 fn inl_not_i64x2(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
     if !lin_result.rule.is_lower {
@@ -612,64 +504,6 @@ fn inl_not_i64x2(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<Lin
     }
 }
 
-// lane_type: specialize typevar
-fn inl_lane_type(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let mut intersect_types = HashSet::new();
-    for ty in get_all_types() {
-        intersect_types.insert(ty.lane_type());
-    }
-    inl_ty_intersect("lane_type", &intersect_types.into_iter().collect(), lin_result, cur_idx)
-}
-
-// ty_half_lanes: specialize typevar
-fn inl_ty_half_lanes(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let mut intersect_types = HashSet::new();
-    for ty in get_all_types().into_iter().filter(|ty| ty.lane_count() != 1) {
-        match ty.lane_type().by(ty.lane_count() / 2) {
-            Some(ty_res) => { intersect_types.insert(ty_res); },
-            None => (),
-        }
-    }
-    inl_ty_intersect("ty_half_lanes", &intersect_types.into_iter().collect(), lin_result, cur_idx)
-}
-
-// ty_half_width: specialize typevar
-fn inl_ty_half_width(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let mut intersect_types = HashSet::new();
-    for ty in get_all_types() {
-        let ty_res = match ty.lane_type() {
-            types::I16 => types::I8.by(ty.lane_count()),
-            types::I32 => types::I16.by(ty.lane_count()),
-            types::I64 => types::I32.by(ty.lane_count()),
-            types::F64 => types::F32.by(ty.lane_count()),
-            _ => None,
-        };
-        match ty_res {
-            Some(x) => { intersect_types.insert(x); },
-            None => (),
-        }
-    }
-    inl_ty_intersect("ty_half_lanes", &intersect_types.into_iter().collect(), lin_result, cur_idx)
-}
-
-// u64_from_imm64: typecast integer (nop in the expr), specialize typevar of predec. iconst to imm64
-fn inl_u64_from_imm64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let types = get_imm64_types().to_vec();
-    inl_imm("u64_from_imm64", &types, lin_result, cur_idx)
-}
-
-// u128_from_immediate: typecast integer (nop in the expr), specialize typevar of predec. iconst to imm128
-fn inl_u128_from_immediate(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let types = get_imm128_types().to_vec();
-    inl_imm("u128_from_immediate", &types, lin_result, cur_idx)
-}
-
-// imm64: typecast integer (nop in the expr), specialize typevar of predec. iconst to imm64
-fn inl_imm64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let types = get_imm64_types().to_vec();
-    inl_imm("imm64", &types, lin_result, cur_idx)
-}
-
 // imm64_masked: typecast integer (nop in the expr), specialize typevar of predec. iconst to imm64 (ignore masking for simplicity)
 fn inl_imm64_masked(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
     let stmt = lin_result.get(&cur_idx)?;
@@ -693,70 +527,6 @@ fn inl_imm64_masked(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<
     }
 }
 
-// simm32: typecast integer (nop in the expr), specialize typevar of predec. iconst to imm32
-fn inl_simm32(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let types = get_imm32_types().to_vec();
-    inl_imm("simm32", &types, lin_result, cur_idx)
-}
-
-// simm32_from_value: typecast integer (nop in the expr), specialize typevar of predec. iconst to imm32
-fn inl_simm32_from_value(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let types = get_imm32_types().to_vec();
-    inl_imm("simm32_from_value", &types, lin_result, cur_idx)
-}
-
-// simm32_from_imm64: typecast integer (nop in the expr), specialize typevar of predec. iconst to imm32
-fn inl_simm32_from_imm64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let types = get_imm32_types().to_vec();
-    inl_imm("simm32_from_imm64", &types, lin_result, cur_idx)
-}
-
-// uimm8: typecast integer (nop in the expr), specialize typevar of predec. iconst to imm8
-fn inl_uimm8(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let types = vec![types::I8];
-    inl_imm("uimm8", &types, lin_result, cur_idx)
-}
-
-// offset32: typecast integer (nop in the expr), specialize typevar of predec. iconst to imm32
-fn inl_offset32(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let types = get_imm32_types().to_vec();
-    inl_imm("offset32", &types, lin_result, cur_idx)
-}
-
-// offset32_to_u32: typecast integer (nop in the expr), specialize typevar of predec. iconst to imm32
-fn inl_offset32_to_u32(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let types = get_imm32_types().to_vec();
-    inl_imm("offset32_to_u32", &types, lin_result, cur_idx)
-}
-
-// u32_to_offset32: typecast integer (nop in the expr), specialize typevar of predec. iconst to imm32
-fn inl_u32_to_offset32(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let types = get_imm32_types().to_vec();
-    inl_imm("u32_to_offset32", &types, lin_result, cur_idx)
-}
-
-// u32_from_ieee32: float from bit repr. (nop in the expr), specialize typevar of predec. const to F32
-fn inl_u32_from_ieee32(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let types = vec![types::F32];
-    inl_imm("u32_from_ieee32", &types, lin_result, cur_idx)
-}
-
-// u64_from_ieee64: float from bit repr. (nop in the expr), specialize typevar of predec. const to F64
-fn inl_u64_from_ieee64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let types = vec![types::F64];
-    inl_imm("u64_from_ieee64", &types, lin_result, cur_idx)
-}
-
-// emit_u64_le_const: nop
-fn inl_emit_u64_le_const(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_identity("emit_u64_le_const", lin_result, cur_idx)
-}
-
-// emit_u128_le_const: nop
-fn inl_emit_u128_le_const(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_identity("emit_u128_le_const", lin_result, cur_idx)
-}
-
 // dynamic_lane: mix two consts together as a dynamic vector
 fn inl_dynamic_lane(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
     let stmt = lin_result.get(&cur_idx)?;
@@ -900,6 +670,39 @@ fn inl_i64_sextend_imm64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option
     }
 }
 
+// lane_type: specialize typevar
+fn inl_lane_type(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
+    let mut intersect_types = HashSet::new();
+    for ty in get_all_types() {
+        intersect_types.insert(ty.lane_type());
+    }
+    inl_ty_intersect("lane_type", &intersect_types.into_iter().collect(), lin_result, cur_idx)
+}
+
+// ty_half_lanes: specialize typevar
+fn inl_ty_half_lanes(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
+    let mut intersect_types = HashSet::new();
+    for ty in get_all_types().into_iter().filter(|ty| ty.lane_count() != 1) {
+        match ty.lane_type().by(ty.lane_count() / 2) {
+            Some(ty_res) => { intersect_types.insert(ty_res); },
+            None => (),
+        }
+    }
+    inl_ty_intersect("ty_half_lanes", &intersect_types.into_iter().collect(), lin_result, cur_idx)
+}
+
+// ty_half_width: specialize typevar
+fn inl_ty_half_width(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
+    let mut intersect_types = HashSet::new();
+    for ty in get_all_types() {
+        match get_half_width(&ty) {
+            Some(x) => { intersect_types.insert(x); },
+            None => (),
+        }
+    }
+    inl_ty_intersect("ty_half_width", &intersect_types.into_iter().collect(), lin_result, cur_idx)
+}
+
 // u64_uextend_imm64: specialize typevar to imm64
 fn inl_u64_uextend_imm64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
     let stmt = lin_result.get(&cur_idx)?;
@@ -929,99 +732,6 @@ fn inl_u64_uextend_imm64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option
     }
 }
 
-// subsume: nop
-fn inl_subsume(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_identity("subsume", lin_result, cur_idx)
-}
-
-// remat: nop
-fn inl_remat(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_identity("remat", lin_result, cur_idx)
-}
-
-// splat64: splat64 v => v | v << 64
-fn inl_splat64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("splat64", "_splat64", lin_result, cur_idx)
-}
-
-// ty_as_ty: nop
-fn inl_ty_as_ty(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_identity("u8_as_u32", lin_result, cur_idx)
-        .or_else(|| inl_identity("u8_as_u64", lin_result, cur_idx))
-        .or_else(|| inl_identity("u16_as_u64", lin_result, cur_idx))
-        .or_else(|| inl_identity("u32_as_u64", lin_result, cur_idx))
-        .or_else(|| inl_identity("i64_as_u64", lin_result, cur_idx))
-        .or_else(|| inl_identity("u64_as_i32", lin_result, cur_idx))
-        .or_else(|| inl_identity("u128_as_u64", lin_result, cur_idx))
-        .or_else(|| inl_identity("u64_as_u32", lin_result, cur_idx))
-        .or_else(|| inl_identity("u64_as_i16", lin_result, cur_idx))
-}
-
-// ty_from_ty with nop
-fn inl_ty_from_ty_identity(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_identity("imm12_from_u64", lin_result, cur_idx)
-        .or_else(|| inl_identity("imm12_from_i64", lin_result, cur_idx))
-        .or_else(|| inl_identity("imm5_from_u64", lin_result, cur_idx))
-        .or_else(|| inl_identity("imm5_from_i8", lin_result, cur_idx))
-        .or_else(|| inl_identity("uimm5_bitcast_to_imm5", lin_result, cur_idx))
-        .or_else(|| inl_identity("uimm5_from_u8", lin_result, cur_idx))
-        .or_else(|| inl_identity("uimm5_from_u64", lin_result, cur_idx))
-        .or_else(|| inl_identity("imm_shift_from_u8", lin_result, cur_idx))
-        .or_else(|| inl_identity("imm_from_bits", lin_result, cur_idx))
-        .or_else(|| inl_identity("imm_from_neg_bits", lin_result, cur_idx))
-        .or_else(|| inl_identity_2("imm_shift_from_imm64", lin_result, cur_idx))
-        .or_else(|| inl_identity_2("u64_into_imm_logic", lin_result, cur_idx))
-        .or_else(|| inl_identity("u8_from_uimm8", lin_result, cur_idx))
-        .or_else(|| inl_identity("vconst_from_immediate", lin_result, cur_idx))
-        .or_else(|| inl_identity("vec_mask_from_immediate", lin_result, cur_idx))
-        .or_else(|| inl_identity("u64_from_constant", lin_result, cur_idx))
-        .or_else(|| inl_identity("u128_from_constant", lin_result, cur_idx))
-        .or_else(|| inl_identity("const_to_vconst", lin_result, cur_idx))
-        .or_else(|| inl_identity("u8_into_uimm5", lin_result, cur_idx))
-        .or_else(|| inl_identity("u8_into_imm12", lin_result, cur_idx))
-        .or_else(|| inl_identity("i32_from_u64", lin_result, cur_idx))
-        .or_else(|| inl_identity("i16_from_u64", lin_result, cur_idx))
-        .or_else(|| inl_identity("i16_from_u32", lin_result, cur_idx))
-        .or_else(|| inl_identity("uimm32shifted_from_u64", lin_result, cur_idx))
-        .or_else(|| inl_identity("uimm16shifted_from_u64", lin_result, cur_idx))
-        .or_else(|| inl_identity("shuffle_mask_from_u128", lin_result, cur_idx))
-        .or_else(|| inl_identity("u64_from_value", lin_result, cur_idx))
-        .or_else(|| inl_identity("u32_from_value", lin_result, cur_idx))
-        .or_else(|| inl_identity("u8_from_value", lin_result, cur_idx))
-        .or_else(|| inl_identity("u8_from_signed_value", lin_result, cur_idx))
-        .or_else(|| inl_identity("i64_from_value", lin_result, cur_idx))
-        .or_else(|| inl_identity("i32_from_value", lin_result, cur_idx))
-        .or_else(|| inl_identity("i16_from_value", lin_result, cur_idx))
-        .or_else(|| inl_identity("uimm16shifted_from_value", lin_result, cur_idx))
-        .or_else(|| inl_identity("uimm32shifted_from_value", lin_result, cur_idx))
-        .or_else(|| inl_identity("i64_from_offset", lin_result, cur_idx))
-}
-
-// shift_amount_masked: nop
-fn inl_shift_amount_masked(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_identity_2("shift_amount_masked", lin_result, cur_idx)
-}
-
-// imm12_const: nop
-fn inl_imm12_const(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_identity("imm12_const", lin_result, cur_idx)
-}
-
-// offset32_imm: nop
-fn inl_offset32_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_identity("offset32_imm", lin_result, cur_idx)
-}
-
-// u64_truncate_to_u32: nop
-fn inl_u64_truncate_to_u32(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_identity("u64_truncate_to_u32", lin_result, cur_idx)
-}
-
-// shift_masked_imm: nop
-fn inl_shift_masked_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_identity_2("shift_masked_imm", lin_result, cur_idx)
-}
-
 // value_type: (value_type ty) -> (identity ty new_var): identity defines type of new_var as ty
 fn inl_value_type(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
     let stmt = lin_result.get(&cur_idx)?;
@@ -1095,16 +805,6 @@ fn inl_imm64_power_of_two(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Optio
     }
 }
 
-// u64_nonzero: add constraint to the param, specialize typevar of predec. iconst to imm64
-fn inl_u64_nonzero(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let types = get_imm64_types().to_vec();
-    inl_novarcond_imm("u64_nonzero", "_nonzero", &types, lin_result, cur_idx)
-        .or_else(|| inl_novarcond_imm("i64_nonzero", "_nonzero", &types, lin_result, cur_idx))
-        .or_else(|| inl_novarcond_imm("nonzero_u64_from_imm64", "_nonzero", &types, lin_result, cur_idx))
-        .or_else(|| inl_novarcond_imm("u64_nonzero_hipart", "_nonzero_hipart", &types, lin_result, cur_idx))
-        .or_else(|| inl_novarcond_imm("u64_nonzero_lopart", "_nonzero_lopart", &types, lin_result, cur_idx))
-}
-
 // nonzero_u64_fits_in_u32: add constraint to the param, specialize typevar of predec. iconst to imm64
 fn inl_nonzero_u64_fits_in_u32(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
     let stmt = lin_result.get(&cur_idx)?;
@@ -1158,107 +858,6 @@ fn inl_vconst_all_ones_or_all_zeros(lin_result: &LinResult, cur_idx: &LinExprIdx
     }
 }
 
-// pshufd_lhs_imm: add constraint to the param
-fn inl_pshufd_lhs_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_novarcond("pshufd_lhs_imm", "_pshufd_lhs_imm", lin_result, cur_idx)
-}
-
-// pshufd_rhs_imm: add constraint to the param
-fn inl_pshufd_rhs_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_novarcond("pshufd_rhs_imm", "_pshufd_rhs_imm", lin_result, cur_idx)
-}
-
-// shufps_imm: add constraint to the param
-fn inl_shufps_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_novarcond("shufps_imm", "_shufps_imm", lin_result, cur_idx)
-}
-
-// shufps_rev_imm: add constraint to the param
-fn inl_shufps_rev_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_novarcond("shufps_rev_imm", "_shufps_rev_imm", lin_result, cur_idx)
-}
-
-// pshuflw_lhs_imm: add constraint to the param
-fn inl_pshuflw_lhs_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_novarcond("pshuflw_lhs_imm", "_pshuflw_lhs_imm", lin_result, cur_idx)
-}
-
-// pshuflw_rhs_imm: add constraint to the param
-fn inl_pshuflw_rhs_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_novarcond("pshuflw_rhs_imm", "_pshuflw_rhs_imm", lin_result, cur_idx)
-}
-
-// pshufhw_lhs_imm: add constraint to the param
-fn inl_pshufhw_lhs_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_novarcond("pshufhw_lhs_imm", "_pshufhw_lhs_imm", lin_result, cur_idx)
-}
-
-// pshufhw_rhs_imm: add constraint to the param
-fn inl_pshufhw_rhs_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_novarcond("pshufhw_rhs_imm", "_pshufhw_rhs_imm", lin_result, cur_idx)
-}
-
-// palignr_imm_from_immediate: add constraint to the param
-fn inl_palignr_imm_from_immediate(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_novarcond("palignr_imm_from_immediate", "_palignr_imm_from_immediate", lin_result, cur_idx)
-}
-
-// pblendw_imm: add constraint to the param
-fn inl_pblendw_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_novarcond("pblendw_imm", "_pblendw_imm", lin_result, cur_idx)
-}
-
-// u32_nonnegative: add constraint to the param, specialize typevar of predec. iconst to imm32
-fn inl_u32_nonnegative(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let types = get_imm32_types().to_vec();
-    inl_novarcond_imm("u32_nonnegative", "_nonnegative", &types, lin_result, cur_idx)
-}
-
-// sse_interps_lane_imm: add constraint to the param, 0 | lane << 4
-fn inl_sse_interps_lane_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("sse_interps_lane_imm", "_sse_interps_lane_imm", lin_result, cur_idx)
-}
-
-// u64_from_inverted_value: add constraint to the param
-fn inl_u64_from_inverted_value(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("u64_from_inverted_value", "_inverted", lin_result, cur_idx)
-}
-
-// uimm16shifted_from_inverted_value: add constraint to the param
-fn inl_uimm16shifted_from_inverted_value(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("uimm16shifted_from_inverted_value", "_inverted", lin_result, cur_idx)
-}
-
-// uimm32shifted_from_inverted_value: add constraint to the param
-fn inl_uimm32shifted_from_inverted_value(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("uimm32shifted_from_inverted_value", "_inverted", lin_result, cur_idx)
-}
-
-// i16_from_swapped_value: add constraint to the param
-fn inl_i16_from_swapped_value(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("i16_from_swapped_value", "_swapped", lin_result, cur_idx)
-}
-
-// i64_from_negated_value: add constraint to the param
-fn inl_i64_from_negated_value(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("i64_from_negated_value", "_negate", lin_result, cur_idx)
-}
-
-// i32_from_negated_value: add constraint to the param
-fn inl_i32_from_negated_value(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("i32_from_negated_value", "_negate", lin_result, cur_idx)
-}
-
-// i16_from_negated_value: add constraint to the param
-fn inl_i16_from_negated_value(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("i16_from_negated_value", "_negate", lin_result, cur_idx)
-}
-
-// len_minus_one: add constraint to the param
-fn inl_len_minus_one(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("len_minus_one", "_minusone", lin_result, cur_idx)
-}
-
 // shuffle64_from_imm: apply conds (with no vars) while substituting the expr to a var., while the cond is dependent to a param
 fn inl_shuffle64_from_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
     let stmt = lin_result.get(&cur_idx)?;
@@ -1331,56 +930,6 @@ fn inl_shuffle16_from_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Optio
     }
 }
 
-// shuffle_dup8_from_imm: apply conds (with no vars) while substituting the expr to a var., while the cond is dependent to a param
-fn inl_shuffle_dup8_from_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("shuffle_dup8_from_imm", "_shuffle_dup8_from_imm", lin_result, cur_idx)
-}
-
-// shuffle_dup16_from_imm: apply conds (with no vars) while substituting the expr to a var., while the cond is dependent to a param
-fn inl_shuffle_dup16_from_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("shuffle_dup16_from_imm", "_shuffle_dup16_from_imm", lin_result, cur_idx)
-}
-
-// shuffle_dup32_from_imm: apply conds (with no vars) while substituting the expr to a var., while the cond is dependent to a param
-fn inl_shuffle_dup32_from_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("shuffle_dup32_from_imm", "_shuffle_dup32_from_imm", lin_result, cur_idx)
-}
-
-// shuffle_dup64_from_imm: apply conds (with no vars) while substituting the expr to a var., while the cond is dependent to a param
-fn inl_shuffle_dup64_from_imm(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("shuffle_dup64_from_imm", "_shuffle_dup64_from_imm", lin_result, cur_idx)
-}
-
-// u64_low32_bits_unset: apply conds (with no vars) while substituting the expr to a var., while the cond is dependent to a param
-fn inl_u64_low32_bits_unset(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("u64_low32_bits_unset", "_u64_low32_bits_unset", lin_result, cur_idx)
-}
-
-// u128_replicated_u64: apply conds (with no vars) while substituting the expr to a var., while the cond is dependent to a param
-fn inl_u128_replicated_u64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("u128_replicated_u64", "_u128_replicated_u64", lin_result, cur_idx)
-}
-
-// u64_replicated_u32: apply conds (with no vars) while substituting the expr to a var., while the cond is dependent to a param
-fn inl_u64_replicated_u32(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("u64_replicated_u32", "_u64_replicated_u32", lin_result, cur_idx)
-}
-
-// u32_replicated_u16: apply conds (with no vars) while substituting the expr to a var., while the cond is dependent to a param
-fn inl_u32_replicated_u16(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("u32_replicated_u16", "_u32_replicated_u16", lin_result, cur_idx)
-}
-
-// u16_replicated_u8: apply conds (with no vars) while substituting the expr to a var., while the cond is dependent to a param
-fn inl_u16_replicated_u8(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("u16_replicated_u8", "_u16_replicated_u8", lin_result, cur_idx)
-}
-
-// neg_imm12: apply conds (with no vars) while substituting the expr to a var., while the cond is dependent to a param
-fn inl_neg_imm12(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    inl_varcond_1("inl_imm5_from_i8", "_neg", lin_result, cur_idx)
-}
-
 // safe_divisor_from_imm64: apply conds (with no vars) while substituting the expr to a var., while the cond is dependent to a param
 fn inl_safe_divisor_from_imm64(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
     let stmt = lin_result.get(&cur_idx)?;
@@ -1447,50 +996,6 @@ fn inl_has_type(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinR
     }
 }
 
-// ty_bits: group typevars with ty.bits(), change expression to const of ty.bits()
-fn inl_ty_bits(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let func = |ty: &Type| Some(ty.bits() as i128);
-    inl_ty_sep("ty_bits", func, lin_result, cur_idx)
-        .or_else(|| inl_ty_sep("ty_bits_u16", func, lin_result, cur_idx))
-        .or_else(|| inl_ty_sep("ty_bits_u64", func, lin_result, cur_idx))
-}
-
-// ty_bytes: group typevars with ty.bytes(), change expression to const of ty.bytes()
-fn inl_ty_bytes(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let func = |ty: &Type| Some(ty.bytes() as i128);
-    inl_ty_sep("ty_bytes", func, lin_result, cur_idx)
-}
-
-// ty_mask: group typevars with mask, change expression to const of mask
-fn inl_ty_mask(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let func = |ty: &Type| {
-        if ty.bits() > 64 || ty.bits() == 0 { None }
-        else {
-            let ty_shift = 64_u64.checked_sub(ty.bits().into()).unwrap();
-            Some((u64::MAX >> ty_shift) as i128)
-        }
-    };
-    inl_ty_sep("ty_mask", func, lin_result, cur_idx)
-}
-
-// ty_lane_mask: group typevars with lane mask, change expression to const of lane mask
-fn inl_ty_lane_mask(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let func = |ty: &Type| {
-        if ty.lane_count() > 64 || ty.lane_count() == 0 { None }
-        else {
-            let ty_shift = 64_u64.checked_sub(ty.lane_count().into()).unwrap();
-            Some((u64::MAX >> ty_shift) as i128)
-        }
-    };
-    inl_ty_sep("ty_lane_mask", func, lin_result, cur_idx)
-}
-
-// ty_lane_count: group typevars with lane count, change expression to const of lane count
-fn inl_ty_lane_count(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let func = |ty: &Type| Some(ty.lane_count() as i128);
-    inl_ty_sep("ty_lane_count", func, lin_result, cur_idx)
-}
-
 // ty_umin: 0
 fn inl_ty_umin(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
     let stmt = lin_result.get(&cur_idx)?;
@@ -1511,48 +1016,6 @@ fn inl_ty_umin(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinRe
     }
 }
 
-// ty_umax: ty_mask
-fn inl_ty_umax(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let func = |ty: &Type| {
-        if ty.bits() > 64 || ty.bits() == 0 { None }
-        else {
-            let ty_shift = 64_u64.checked_sub(ty.bits().into()).unwrap();
-            Some((u64::MAX >> ty_shift) as i128)
-        }
-    };
-    inl_ty_sep("ty_umax", func, lin_result, cur_idx)
-}
-
-// ty_smin: group typevars with smin, change expression to const of smin
-fn inl_ty_smin(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let func = |ty: &Type| {
-        if ty.bits() > 64 || ty.bits() == 0 { None }
-        else {
-            let ty_shift = 64_u64.checked_sub(ty.bits().into()).unwrap();
-            Some(((i64::MIN as u64) >> ty_shift) as i128)
-        }
-    };
-    inl_ty_sep("ty_smin", func, lin_result, cur_idx)
-}
-
-// ty_smax: group typevars with smax, change expression to const of smax
-fn inl_ty_smax(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let func = |ty: &Type| {
-        if ty.bits() > 64 || ty.bits() == 0 { None }
-        else {
-            let ty_shift = 64_u64.checked_sub(ty.bits().into()).unwrap();
-            Some(((i64::MAX as u64) >> ty_shift) as i128)
-        }
-    };
-    inl_ty_sep("ty_smax", func, lin_result, cur_idx)
-}
-
-// shift_mask: group typevars with shift mask, change expression to const of shift mask
-fn inl_shift_mask(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
-    let func = |ty: &Type| Some((ty.lane_bits().checked_sub(1)?) as i128);
-    inl_ty_sep("shift_mask", func, lin_result, cur_idx)
-}
-
 // zero_offset: const(0)
 fn inl_zero_offset(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
     let stmt = lin_result.get(&cur_idx)?;
@@ -1800,12 +1263,42 @@ fn inl_u64_from_iconst(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<V
     }
 }
 
+// sinkable loads: the value is defined by a load, e.g., (sinkable_load x) -> (load ty flags x offset)
+fn inl_sink_load(expr_name: &str, load_name: &str, load_types: &Vec<Type>, lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
+    let stmt = lin_result.get(&cur_idx)?;
+    match stmt {
+        LinExpr::Expr { name, params } => {
+            if name == expr_name {
+                let is_lower_int = lin_result.rule.is_lower as usize;
+                assert!(params.len() == 1 + is_lower_int);
+                // the load itself is only used on RHS, so its var stands for the address
+                let mut new_lin_result = change_typevar_to_var(lin_result.deep_clone(), &params[is_lower_int])?;
+                let addr_idx = new_lin_result.get_idx(&params[is_lower_int])?;
+                let expr_idx = new_lin_result.get_idx(cur_idx)?;
+                let mut new_params = Vec::new();
+                for new_stmt in [LinExpr::TypeVar(load_types.clone()), LinExpr::Var(Vec::new()), LinExpr::Var(Vec::new())] {
+                    let new_idx = expr_idx.deep_clone();
+                    new_lin_result.insert_pair(new_idx.clone(), new_stmt);
+                    new_params.push(new_idx);
+                }
+                new_params.insert(2, addr_idx); // ty flags addr offset
+                *new_lin_result.get_mut(&expr_idx)? = LinExpr::Expr { name: String::from(load_name), params: new_params };
+                Some(vec![new_lin_result])
+            }
+            else {
+                None
+            }
+        },
+        _ => None
+    }
+}
+
 // reverts typevar back to var
 fn inl_revert_typevar(lin_result: &LinResult, cur_idx: &LinExprIdx) -> Option<Vec<LinResult>> {
     let stmt = lin_result.get(&cur_idx)?;
     match stmt {
         LinExpr::Expr { name, params } => {
-            if TYPEVAR_REVERT_LIST.contains(&name.as_str()) ||
+            if matches!(get_directive(name), Some(Directive { kind: Revert, .. })) ||
                name.starts_with("IntCC.") || name.starts_with("FloatCC.") {
                 let typevar_idx = params.get(0)?;
                 let typevar_stmt = lin_result.get(typevar_idx)?;
@@ -2130,14 +1623,24 @@ fn inl_varcond_1(expr_name: &str, cond_name: &str, lin_result: &LinResult, cur_i
 
 #[cfg(test)]
 mod test {
-    use crate::{isle::ISLEParseOptions, isle_lin::linearize_rules_opt};
+    use crate::{isle::{default_cranelift_src, ISLEParseOptions}, isle_lin::linearize_rules_opt};
 
     use super::*;
 
     #[test]
     fn test_process_internals() {
-        let rules = linearize_rules_opt(ISLEParseOptions::Lower);
+        let rules = linearize_rules_opt(ISLEParseOptions::Lower, &default_cranelift_src());
         let processed_rules: Vec<_> = rules.into_iter().flat_map(process_internals).collect();
         println!("{:#?}", processed_rules);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_directives_unique() {
+        let mut names = std::collections::HashSet::new();
+        for directive in DIRECTIVES {
+            assert!(names.insert(directive.name), "duplicate directive {}", directive.name);
+        }
+        assert!(get_directive("fits_in_64").is_some());
+        assert!(get_directive("simplify").is_none());
+    }
+}
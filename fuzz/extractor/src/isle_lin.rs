@@ -1,8 +1,8 @@
 // Step 1.3: Linearize normalized ISLE rules
 
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path};
 
-use crate::{norm::{get_all_types, LinExpr, LinExprIdx, LinResult, LinRule, LinType, LinVec, NormExpr, NormRule, NormVar}, isle::ISLEParseOptions, isle_norm::norm_rules_opt};
+use crate::{norm::{get_all_types, LinExpr, LinExprIdx, LinResult, LinRule, LinType, LinVec, NormExpr, NormRule, NormVar}, isle::ISLEParseOptions, isle_norm::norm_rules_opt_diag, diagnostics::Diagnostics};
 
 #[derive(Clone, Debug)]
 struct LinEnv {
@@ -56,7 +56,7 @@ fn linearize_expr(env: &mut LinEnv, expr: NormExpr, lin_type: LinType, is_lower:
         },
         NormExpr::Expr { name, subexprs } => {
             let mut params = Vec::new();
-            if is_lower && name != "lower" {
+            if is_lower && name != "lower" && name != "lower_branch" {
                 // add typevar to the first of the expression
                 let typevar_param = match lin_type {
                     LinType::LHS => LinExprIdx::LHS(env.lhs.push(LinExpr::TypeVar(get_all_types().to_vec()))),
@@ -162,24 +162,30 @@ fn linearize_rule(rule: NormRule) -> LinResult {
     }
     
     LinResult {
-        rule: LinRule::new(env.lhs, env.rhs, rule.is_lower),
+        rule: LinRule::new(env.lhs, env.rhs, rule.is_lower).with_sources(vec![rule.source]),
         cond_stmts: env.cond,
         cond_pairs: conds,
     }
 }
 
-pub fn linearize_rules_opt(opt: ISLEParseOptions) -> Vec<LinResult> {
-    let norm_rules = norm_rules_opt(opt);
+pub fn linearize_rules_opt(opt: ISLEParseOptions, src_root: &Path) -> Vec<LinResult> {
+    linearize_rules_opt_diag(opt, src_root, &mut Diagnostics::disabled())
+}
+
+pub fn linearize_rules_opt_diag(opt: ISLEParseOptions, src_root: &Path, diag: &mut Diagnostics) -> Vec<LinResult> {
+    let norm_rules = norm_rules_opt_diag(opt, src_root, diag);
     norm_rules.into_iter().map(linearize_rule).collect()
 }
 
 #[cfg(test)]
 mod test {
+    use crate::isle::default_cranelift_src;
+
     use super::*;
 
     #[test]
     fn test_linearize_rules() {
-        let rules = linearize_rules_opt(ISLEParseOptions::TestOpt);
+        let rules = linearize_rules_opt(ISLEParseOptions::TestOpt, &default_cranelift_src());
         println!("{:#?}", rules);
     }
 }
\ No newline at end of file
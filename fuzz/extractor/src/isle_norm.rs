@@ -1,10 +1,17 @@
 // Step 1.2: Normalize parsed ISLE rules
 
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path, sync::Arc};
 
-use cranelift_isle::ast::{Ident, Pattern, LetDef, Expr, IfLet, Rule};
+use anyhow::{anyhow, Error};
+use cranelift_isle::ast::{Def, Ident, Pattern, LetDef, Expr, IfLet, Rule};
 
-use crate::{norm::{NormExpr, NormVar, NormConstraint, NormRule}, isle::{ISLEParseOptions, run_parse_opt}};
+use crate::{diagnostics::{DiagStage, Diagnostics}, norm::{NormExpr, NormVar, NormConstraint, NormRule, RuleRole, RuleSource}, isle::{ISLEParseOptions, run_parse_opt}, isle_inl::get_directive};
+
+// extractor macros with a depth beyond this are considered cyclic
+const MACRO_DEPTH_LIMIT: usize = 64;
+
+// extractor macro name -> (arity, template with MacroArg placeholders)
+type MacroMap = HashMap<String, (usize, Pattern)>;
 
 #[derive(Clone, Debug)]
 struct NormEnv {
@@ -66,13 +73,13 @@ fn process_sym(sym: &Ident) -> String {
     sym.0.clone()
 }
 
-fn process_pattern(pattern: &Pattern, env: &mut NormEnv) -> NormExpr {
-    match pattern {
+fn process_pattern(pattern: &Pattern, env: &mut NormEnv, macros: &MacroMap, depth: usize) -> Result<NormExpr, Error> {
+    let result = match pattern {
         Pattern::Var { var, pos: _ } => {
             let name = process_sym(var);
 
             match env.get_bound_var_idx(&name) {
-                Some(idx) => { return NormExpr::BoundVar(idx); },
+                Some(idx) => { return Ok(NormExpr::BoundVar(idx)); },
                 None => (),
             }
 
@@ -88,25 +95,42 @@ fn process_pattern(pattern: &Pattern, env: &mut NormEnv) -> NormExpr {
         },
         Pattern::BindPattern { var, subpat, pos: _ } => {
             let name = process_sym(var);
-            let bound_expr = process_pattern(subpat, env);
+            let bound_expr = process_pattern(subpat, env, macros, depth)?;
             let bound_var_idx = env.add_boundvar(name, bound_expr);
             NormExpr::BoundVar(bound_var_idx)
         },
         Pattern::ConstInt { val, pos: _ } => NormExpr::ConstInt(*val),
         Pattern::ConstPrim { val, pos: _ } => NormExpr::ConstPrim(process_sym(val)),
+        Pattern::Term { sym, args, pos: _ } if macros.contains_key(&sym.0) => {
+            // expand the extractor macro in place; the template may use other macros
+            let (arity, template) = macros.get(&sym.0).unwrap();
+            if args.len() != *arity {
+                return Err(anyhow!("macro {} expects {} arguments, got {}", sym.0, arity, args.len()));
+            }
+            if depth >= MACRO_DEPTH_LIMIT {
+                return Err(anyhow!("macro {} expands too deeply", sym.0));
+            }
+            let expanded = template.subst_macro_args(args)
+                .ok_or(anyhow!("cannot substitute arguments of macro {}", sym.0))?;
+            process_pattern(&expanded, env, macros, depth + 1)?
+        },
         Pattern::Term { sym, args, pos: _ } => {
             let name = process_sym(sym);
-            let subexprs = args.iter().map(|x| process_pattern(x, env)).collect();
+            let subexprs = args.iter().map(|x| process_pattern(x, env, macros, depth)).collect::<Result<_, _>>()?;
             
             NormExpr::Expr { name, subexprs }
         },
         Pattern::Wildcard { pos: _ } => NormExpr::Wildcard,
         Pattern::And { subpats, pos: _ } => {
-            let subexprs = subpats.iter().map(|x| process_pattern(x, env)).collect();
+            let subexprs = subpats.iter().map(|x| process_pattern(x, env, macros, depth)).collect::<Result<_, _>>()?;
             NormExpr::Expr { name: String::from("and"), subexprs }
         },
-        Pattern::MacroArg { .. } => todo!(),
-    }
+        // only templates contain these, and they are substituted on expansion
+        Pattern::MacroArg { index, pos: _ } => {
+            return Err(anyhow!("unexpanded macro argument {}", index));
+        },
+    };
+    Ok(result)
 }
 
 fn process_letdef(letdef: &LetDef, env: &mut NormEnv) -> () {
@@ -155,35 +179,85 @@ fn process_expr(expr: &Expr, env: &mut NormEnv) -> NormExpr {
     }
 }
 
-fn process_iflet(iflet: &IfLet, env: &mut NormEnv) -> NormConstraint {
-    let lhs = process_pattern(&iflet.pattern, env);
+fn process_iflet(iflet: &IfLet, env: &mut NormEnv, macros: &MacroMap) -> Result<NormConstraint, Error> {
+    let lhs = process_pattern(&iflet.pattern, env, macros, 0)?;
     let rhs = process_expr(&iflet.expr, env);
-    NormConstraint { lhs, rhs }
+    Ok(NormConstraint { lhs, rhs })
+}
+
+fn get_rule_source(rule: &Rule, filenames: &[Arc<str>], src_root: &Path) -> RuleSource {
+    let filename = Path::new(&*filenames[rule.pos.file]);
+    RuleSource {
+        role: RuleRole::Target,
+        file: filename.strip_prefix(src_root).unwrap_or(filename).to_string_lossy().to_string(),
+        line: rule.pos.line,
+        name: rule.pattern.root_term().map(|x| x.0.clone()).unwrap_or_default(),
+        prio: rule.prio.unwrap_or(0),
+    }
 }
 
-fn process_rule(rule: &Rule, is_lower: bool) -> NormRule {
+// extractor macros to expand; the ones handled as directives in isle_inl are kept as terms
+fn get_macros(defs: &[Def]) -> MacroMap {
+    let mut macros = MacroMap::new();
+    for def in defs {
+        match def {
+            Def::Extractor(extractor) if get_directive(&extractor.term.0).is_none() => {
+                let template = extractor.template.make_macro_template(&extractor.args);
+                macros.insert(extractor.term.0.clone(), (extractor.args.len(), template));
+            },
+            _ => (),
+        }
+    }
+    macros
+}
+
+// (lower_branch inst targets) -> (lower_branch inst)
+// targets are labels chosen by the generator, so only the branch instruction is matched
+fn strip_branch_targets(lhs: NormExpr) -> NormExpr {
+    match lhs {
+        NormExpr::Expr { name, mut subexprs } if name == "lower_branch" && subexprs.len() == 2 => {
+            subexprs.truncate(1);
+            NormExpr::Expr { name, subexprs }
+        },
+        _ => lhs,
+    }
+}
+
+fn process_rule(rule: &Rule, is_lower: bool, source: RuleSource, macros: &MacroMap) -> Result<NormRule, Error> {
     let mut env = NormEnv::new();
-    let lhs = process_pattern(&rule.pattern, &mut env);
+    let lhs = strip_branch_targets(process_pattern(&rule.pattern, &mut env, macros, 0)?);
     let rhs = process_expr(&rule.expr, &mut env);
-    let constraints = rule.iflets.iter().map(|x| process_iflet(x, &mut env)).collect();
-    NormRule {
+    let constraints = rule.iflets.iter().map(|x| process_iflet(x, &mut env, macros)).collect::<Result<_, _>>()?;
+    Ok(NormRule {
         var_len: env.vars.len(),
         is_lower,
         lhs,
         rhs,
         constraints,
         bound_vars: env.bound_exprs,
-    }
+        source,
+    })
 }
 
-pub fn norm_rules_opt(opt: ISLEParseOptions) -> Vec<NormRule> {
-    let parsed_result = run_parse_opt(opt).unwrap();
+fn norm_defs(defs: &[Def], filenames: &[Arc<str>], is_lower: bool, src_root: &Path, diag: &mut Diagnostics) -> Vec<NormRule> {
+    let macros = get_macros(defs);
     let mut norm_rules = Vec::new();
-    for def in parsed_result.defs {
+    for def in defs {
         match def {
-            cranelift_isle::ast::Def::Rule(rule) => {
-                let norm_rule = process_rule(&rule, opt.is_lower());
-                norm_rules.push(norm_rule);
+            Def::Rule(rule) => {
+                let source = get_rule_source(rule, filenames, src_root);
+                diag.add_rule(&source);
+                match process_rule(rule, is_lower, source.clone(), &macros) {
+                    Ok(norm_rule) => {
+                        diag.add_survivors(&source, DiagStage::Norm, 1);
+                        norm_rules.push(norm_rule);
+                    },
+                    Err(error) => {
+                        // other rules are still usable
+                        eprintln!("WARNING: skipping rule at {}:{}: {}", source.file, source.line, error);
+                        diag.add_drop(&source, DiagStage::Norm, error.to_string());
+                    },
+                }
             },
             _ => continue,
         }
@@ -191,12 +265,61 @@ pub fn norm_rules_opt(opt: ISLEParseOptions) -> Vec<NormRule> {
     norm_rules
 }
 
+pub fn norm_rules_opt(opt: ISLEParseOptions, src_root: &Path) -> Vec<NormRule> {
+    norm_rules_opt_diag(opt, src_root, &mut Diagnostics::disabled())
+}
+
+pub fn norm_rules_opt_diag(opt: ISLEParseOptions, src_root: &Path, diag: &mut Diagnostics) -> Vec<NormRule> {
+    let parsed_result = run_parse_opt(opt, src_root).unwrap();
+    norm_defs(&parsed_result.defs, &parsed_result.filenames, opt.is_lower(), src_root, diag)
+}
+
 #[cfg(test)]
 mod test {
+    use crate::isle::default_cranelift_src;
+
     use super::*;
 
     #[test]
     fn test_process_rules() {
-        println!("{:#?}", norm_rules_opt(ISLEParseOptions::Lower));
+        println!("{:#?}", norm_rules_opt(ISLEParseOptions::Lower, &default_cranelift_src()));
+    }
+
+    #[test]
+    fn test_process_macro_rules() {
+        let src = "
+            (extractor (eq ty x y) (icmp ty (IntCC.Equal) x y))
+            (extractor (eq_zero ty x) (eq ty x (iconst_u ty 0)))
+            (rule (simplify (eq_zero ty x)) x)
+            (rule (simplify (eq ty x)) x)
+        ";
+        let lexer = cranelift_isle::lexer::Lexer::from_str(src, "test.isle").unwrap();
+        let defs = cranelift_isle::parser::parse(lexer).unwrap();
+        let macros = get_macros(&defs.defs);
+        let rules = defs.defs.iter().filter_map(|x| match x {
+            Def::Rule(rule) => Some(rule),
+            _ => None,
+        }).collect::<Vec<_>>();
+        let source = get_rule_source(rules[0], &defs.filenames, Path::new(""));
+
+        let norm_rule = process_rule(rules[0], false, source.clone(), &macros).unwrap();
+        println!("{:#?}", norm_rule);
+        match &norm_rule.lhs {
+            NormExpr::Expr { subexprs, .. } => {
+                assert!(matches!(&subexprs[0], NormExpr::Expr { name, .. } if name == "icmp"));
+            },
+            _ => panic!(),
+        }
+        assert!(process_rule(rules[1], false, source, &macros).is_err());
+
+        // the rule that fails to expand is reported at the norm stage
+        let mut diag = Diagnostics::new();
+        let norm_rules = norm_defs(&defs.defs, &defs.filenames, false, Path::new(""), &mut diag);
+        assert_eq!(norm_rules.len(), 1);
+        let report = diag.report();
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].dropped.as_ref().unwrap().stage, DiagStage::Inline);
+        let dropped = report[1].dropped.as_ref().unwrap();
+        assert_eq!((dropped.stage, dropped.reason.as_str()), (DiagStage::Norm, "macro eq expects 3 arguments, got 2"));
     }
 }
\ No newline at end of file
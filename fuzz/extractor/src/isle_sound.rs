@@ -0,0 +1,943 @@
+// Soundness checker: runs both sides of simplify rules in the Cranelift interpreter
+// Each simplify rule is instantiated with concrete types picked from its TypeVars and concrete values
+// for its immediates, and its LHS and RHS become CLIF functions taking the value vars as params.
+// Both are run on boundary and random inputs that satisfy the rule conditions, and the rule is
+// reported as unsound if the results differ (any NaN equals any NaN) or only the RHS traps.
+
+use std::{collections::{BTreeMap, HashMap, HashSet}, fmt, path::Path};
+
+use cranelift_codegen::{
+    data_value::DataValue,
+    ir::{
+        condcodes::{FloatCC, IntCC},
+        immediates::{Ieee32, Ieee64, Imm64},
+        instructions::{InstructionFormat, ResolvedConstraint},
+        types, AbiParam, Function, InstructionData, Opcode, Signature, Type, UserFuncName, Value, ValueList,
+    },
+    isa::CallConv,
+    settings,
+    verify_function,
+};
+use cranelift_interpreter::{environment::FunctionStore, interpreter::{Interpreter, InterpreterState}, step::ControlFlow};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    diagnostics::{format_table, Diagnostics},
+    norm::{get_all_types, CondExpr, LinExpr, LinExprIdx, LinType, RuleSource, UnifiedRule},
+    prod_solve::{apply_function, check_predicate, get_mask, is_comparison},
+    rule_match::{get_simplify_rules, MatchOption},
+};
+
+const MAX_INSTANCES: usize = 4; // type instantiations per rule
+const IMM_SAMPLES: usize = 8; // immediate assignments per type instantiation
+const MAX_TRIES: usize = 64; // samples tried to satisfy the conditions
+const FUEL: u64 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SoundOption {
+    pub inputs: usize, // inputs per type and immediate instantiation
+    pub seed: u64,
+}
+
+impl Default for SoundOption {
+    fn default() -> Self {
+        Self { inputs: 64, seed: 0 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SoundStatus {
+    Sound, // LHS and RHS agreed on every input
+    Unsound,
+    Skipped, // no input could be run
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SoundCounterexample {
+    pub args: Vec<String>,
+    pub lhs_result: String,
+    pub rhs_result: String,
+    pub lhs: String, // CLIF functions, with the immediates of the counterexample
+    pub rhs: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SoundOutcome {
+    pub source: RuleSource,
+    pub status: SoundStatus,
+    pub runs: usize, // inputs run on both sides
+    pub reason: Option<String>, // why it was skipped
+    pub counterexample: Option<SoundCounterexample>,
+}
+
+// splitmix64, so that a report can be reproduced from its seed
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn next_u128(&mut self) -> u128 {
+        ((self.next_u64() as u128) << 64) | self.next_u64() as u128
+    }
+
+    fn choose(&mut self, items: &[u128]) -> u128 {
+        items[(self.next_u64() % items.len() as u64) as usize]
+    }
+}
+
+pub(crate) type StmtKey = (LinType, usize);
+
+fn get_key(idx: &LinExprIdx) -> StmtKey {
+    match idx {
+        LinExprIdx::LHS(x) => (LinType::LHS, *x.borrow()),
+        LinExprIdx::RHS(x) => (LinType::RHS, *x.borrow()),
+        LinExprIdx::Cond(x) => (LinType::Cond, *x.borrow()),
+    }
+}
+
+fn get_stmt(rule: &UnifiedRule, key: StmtKey) -> Option<&LinExpr> {
+    match key.0 {
+        LinType::LHS => rule.lhs.get(key.1),
+        LinType::RHS => rule.rhs.get(key.1),
+        LinType::Cond => None,
+    }
+}
+
+// follows identities
+fn resolve(rule: &UnifiedRule, idx: &LinExprIdx) -> StmtKey {
+    let mut key = get_key(idx);
+    while let Some(LinExpr::Ident(next)) = get_stmt(rule, key) {
+        key = get_key(next);
+    }
+    key
+}
+
+// types that the interpreter runs: scalars and 128-bit vectors
+fn is_supported_type(ty: &Type) -> bool {
+    let lane = ty.lane_type();
+    !ty.is_dynamic_vector() && (lane.is_int() || lane.is_float()) &&
+    (ty.lane_count() == 1 || (ty.bits() == 128 && lane.bits() <= 64))
+}
+
+fn get_supported_types() -> Vec<Type> {
+    get_all_types().into_iter().filter(is_supported_type).collect()
+}
+
+// an instruction of the rule, e.g., icmp: ty cc x y
+#[derive(Clone, Debug)]
+struct RuleInst {
+    key: StmtKey,
+    opcode: Opcode,
+    typevar: StmtKey,
+    cc: Option<String>, // e.g., IntCC.Equal
+    args: Vec<StmtKey>, // value operands
+    imm: Option<StmtKey>,
+}
+
+pub(crate) struct RuleShape {
+    insts: Vec<RuleInst>, // operands before users
+    pub(crate) value_vars: Vec<StmtKey>, // params of the functions
+    imm_vars: Vec<StmtKey>, // including the vars only used in conditions (e.g., exponent of pow2)
+    pub(crate) lhs_root: StmtKey,
+    pub(crate) rhs_root: StmtKey, // the LHS root if RHS is not used
+}
+
+fn get_rule_inst(rule: &UnifiedRule, key: StmtKey, name: &str, params: &[LinExprIdx]) -> Result<RuleInst, String> {
+    let opcode = name.parse::<Opcode>().map_err(|_| format!("unsupported term {}", name))?;
+    let (has_cc, has_imm) = match opcode.format() {
+        InstructionFormat::Unary | InstructionFormat::Binary | InstructionFormat::Ternary => (false, false),
+        InstructionFormat::UnaryImm | InstructionFormat::UnaryIeee32 | InstructionFormat::UnaryIeee64 |
+        InstructionFormat::BinaryImm64 | InstructionFormat::BinaryImm8 | InstructionFormat::TernaryImm8 => (false, true),
+        InstructionFormat::IntCompare | InstructionFormat::FloatCompare => (true, false),
+        InstructionFormat::IntCompareImm => (true, true),
+        format => return Err(format!("unsupported instruction format {:?} of {}", format, name)),
+    };
+    let constraints = opcode.constraints();
+    if constraints.num_fixed_results() != 1 {
+        return Err(format!("{} does not have a single result", name));
+    }
+    let arg_cnt = constraints.num_fixed_value_arguments();
+    if params.len() != 1 + has_cc as usize + arg_cnt + has_imm as usize {
+        return Err(format!("unexpected operands of {}", name));
+    }
+
+    let params = params.iter().map(|x| resolve(rule, x)).collect::<Vec<_>>();
+    if !matches!(get_stmt(rule, params[0]), Some(LinExpr::TypeVar(_))) {
+        return Err(format!("no typevar for {}", name));
+    }
+    let cc = if has_cc {
+        match get_stmt(rule, params[1]) {
+            Some(LinExpr::Expr { name: cc_name, params: cc_params }) if cc_params.is_empty() => Some(cc_name.clone()),
+            _ => return Err(format!("condition code of {} is not fixed", name)),
+        }
+    } else {
+        None
+    };
+    let args_start = 1 + has_cc as usize;
+    Ok(RuleInst {
+        key,
+        opcode,
+        typevar: params[0],
+        cc,
+        args: params[args_start..args_start + arg_cnt].to_vec(),
+        imm: if has_imm { params.last().copied() } else { None },
+    })
+}
+
+// with_rhs is false for LHS-only uses (e.g., filetests of lowering rules, whose RHS are machine instructions)
+pub(crate) fn get_rule_shape(rule: &UnifiedRule, with_rhs: bool) -> Result<RuleShape, String> {
+    let mut insts = Vec::new();
+    let mut vars = Vec::new();
+    let rhs_len = if with_rhs { rule.rhs.len() } else { 0 };
+    for (lin_ty, len) in [(LinType::LHS, rule.lhs.len()), (LinType::RHS, rhs_len)] {
+        for idx in 0..len {
+            let key = (lin_ty, idx);
+            match get_stmt(rule, key).unwrap() {
+                LinExpr::Expr { name, params } if name.parse::<Opcode>().is_ok() => {
+                    insts.push(get_rule_inst(rule, key, name, params)?);
+                },
+                LinExpr::Var(_) if lin_ty == LinType::LHS => vars.push(key),
+                LinExpr::Var(_) => return Err(String::from("unbound var in RHS")),
+                _ => (),
+            }
+        }
+    }
+
+    // operands of the instructions
+    let inst_keys = insts.iter().map(|x| x.key).collect::<HashSet<_>>();
+    let mut value_uses = HashSet::new();
+    let mut imm_uses = HashSet::new();
+    for inst in &insts {
+        for arg in &inst.args {
+            match get_stmt(rule, *arg) {
+                Some(LinExpr::Var(_)) => { value_uses.insert(*arg); },
+                _ if inst_keys.contains(arg) => (),
+                _ => return Err(format!("unsupported value operand of {}", inst.opcode)),
+            }
+        }
+        match inst.imm.map(|x| (x, get_stmt(rule, x))) {
+            Some((key, Some(LinExpr::Var(_)))) => { imm_uses.insert(key); },
+            Some((_, Some(LinExpr::Const(_)))) | None => (),
+            Some((key, Some(LinExpr::Expr { .. }))) if key.0 == LinType::RHS && !inst_keys.contains(&key) => (), // e.g., u64_add
+            Some(_) => return Err(format!("unsupported immediate of {}", inst.opcode)),
+        }
+    }
+    if let Some(key) = value_uses.intersection(&imm_uses).next() {
+        return Err(format!("var {} is both a value and an immediate", key.1));
+    }
+
+    // the LHS root is the instruction that no other LHS expression uses
+    let mut lhs_used = HashSet::new();
+    for idx in 0..rule.lhs.len() {
+        if let Some(LinExpr::Expr { params, .. }) = rule.lhs.get(idx) {
+            lhs_used.extend(params.iter().map(|x| resolve(rule, x)));
+        }
+    }
+    let lhs_root = insts.iter().rev().map(|x| x.key)
+        .find(|x| x.0 == LinType::LHS && !lhs_used.contains(x))
+        .ok_or(String::from("no LHS instruction"))?;
+    let rhs_root = match rule.rhs.get_idx_ref(rule.rhs.len().wrapping_sub(1)) {
+        _ if !with_rhs => lhs_root,
+        Some(idx) => resolve(rule, &LinExprIdx::RHS(idx.clone())),
+        None => return Err(String::from("empty RHS")),
+    };
+    if !inst_keys.contains(&rhs_root) && !value_uses.contains(&rhs_root) {
+        return Err(String::from("RHS is not a value"));
+    }
+
+    // instructions in dependency order
+    let mut ordered: Vec<RuleInst> = Vec::new();
+    let mut done = HashSet::new();
+    while ordered.len() < insts.len() {
+        let prev_len = ordered.len();
+        for inst in &insts {
+            if !done.contains(&inst.key) && inst.args.iter().all(|x| !inst_keys.contains(x) || done.contains(x)) {
+                done.insert(inst.key);
+                ordered.push(inst.clone());
+            }
+        }
+        if ordered.len() == prev_len {
+            return Err(String::from("cyclic instructions"));
+        }
+    }
+
+    Ok(RuleShape {
+        insts: ordered,
+        value_vars: vars.iter().filter(|x| value_uses.contains(x)).copied().collect(),
+        imm_vars: vars.iter().filter(|x| !value_uses.contains(x)).copied().collect(),
+        lhs_root,
+        rhs_root,
+    })
+}
+
+// concrete types of an instantiation
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RuleTypes {
+    pub(crate) types: HashMap<StmtKey, Type>, // typevars, value vars and results of the instructions
+    ctrl_types: HashMap<StmtKey, Type>, // controlling type of each instruction
+}
+
+fn satisfies(constraint: ResolvedConstraint, ty: Type) -> bool {
+    match constraint {
+        ResolvedConstraint::Bound(x) => x == ty,
+        ResolvedConstraint::Free(typeset) => typeset.contains(ty),
+    }
+}
+
+// the interpreter panics on these
+fn is_interpretable(opcode: Opcode, ctrl_type: Type) -> bool {
+    !([Opcode::Umulhi, Opcode::Smulhi].contains(&opcode) && ctrl_type.lane_bits() == 128)
+}
+
+// types the value operands; untyped vars take the type that the instruction expects
+fn bind_args(rule: &UnifiedRule, inst: &RuleInst, ctrl_type: Type, types: &mut RuleTypes) -> bool {
+    let constraints = inst.opcode.constraints();
+    for (idx, arg) in inst.args.iter().enumerate() {
+        let constraint = constraints.value_argument_constraint(idx, ctrl_type);
+        match types.types.get(arg) {
+            Some(ty) => {
+                if !satisfies(constraint, *ty) {
+                    return false;
+                }
+            },
+            None if matches!(get_stmt(rule, *arg), Some(LinExpr::Var(_))) => {
+                let ty = match constraint {
+                    ResolvedConstraint::Bound(ty) => ty,
+                    ResolvedConstraint::Free(typeset) => { // e.g., shift amounts; the controlling type if possible
+                        let candidates = get_supported_types().into_iter().filter(|x| typeset.contains(*x)).collect::<Vec<_>>();
+                        match candidates.iter().find(|x| **x == ctrl_type).or(candidates.first()) {
+                            Some(x) => *x,
+                            None => return false,
+                        }
+                    },
+                };
+                if !is_supported_type(&ty) {
+                    return false;
+                }
+                types.types.insert(*arg, ty);
+            },
+            None => return false,
+        }
+    }
+    true
+}
+
+// up to MAX_INSTANCES type instantiations of the rule
+pub(crate) fn get_instances(rule: &UnifiedRule, shape: &RuleShape) -> Vec<RuleTypes> {
+    let mut instances = Vec::new();
+    type_insts(rule, &shape.insts, RuleTypes::default(), &mut instances);
+    instances
+}
+
+fn type_insts(rule: &UnifiedRule, insts: &[RuleInst], cur: RuleTypes, results: &mut Vec<RuleTypes>) {
+    let (inst, rest) = match insts.split_first() {
+        Some(x) => x,
+        None => {
+            results.push(cur);
+            return;
+        },
+    };
+
+    let result_types = match (cur.types.get(&inst.typevar), get_stmt(rule, inst.typevar)) {
+        (Some(ty), _) => vec![*ty],
+        (None, Some(LinExpr::TypeVar(types))) => types.iter().copied().filter(is_supported_type).collect(),
+        _ => Vec::new(),
+    };
+    let constraints = inst.opcode.constraints();
+    let ctrl_types = match constraints.ctrl_typeset() {
+        Some(typeset) => get_supported_types().into_iter().filter(|x| typeset.contains(*x)).collect(),
+        None => vec![types::INVALID],
+    };
+    for result_type in result_types {
+        for ctrl_type in &ctrl_types {
+            if results.len() >= MAX_INSTANCES {
+                return;
+            }
+            if constraints.result_type(0, *ctrl_type) != result_type || !is_interpretable(inst.opcode, *ctrl_type) {
+                continue;
+            }
+            let mut next = cur.clone();
+            if bind_args(rule, inst, *ctrl_type, &mut next) {
+                next.types.insert(inst.typevar, result_type);
+                next.types.insert(inst.key, result_type);
+                next.ctrl_types.insert(inst.key, *ctrl_type);
+                type_insts(rule, rest, next, results);
+            }
+        }
+    }
+}
+
+// values a var can take: the bits of a value of the type, or an immediate of the width up to max
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct VarDomain {
+    ty: Option<Type>,
+    width: u32,
+    max: u128,
+}
+
+// width and largest value of the immediate of the instruction
+fn get_imm_domain(inst: &RuleInst, ctrl_type: Type) -> VarDomain {
+    let (width, max) = match inst.opcode.format() {
+        InstructionFormat::UnaryImm => (ctrl_type.bits(), get_mask(ctrl_type.bits())),
+        InstructionFormat::UnaryIeee32 => (32, get_mask(32)),
+        InstructionFormat::BinaryImm8 | InstructionFormat::TernaryImm8 => (8, (ctrl_type.lane_count() - 1) as u128), // lane index
+        _ => (64, get_mask(64)),
+    };
+    VarDomain { ty: None, width, max }
+}
+
+pub(crate) fn get_domains(rule: &UnifiedRule, shape: &RuleShape, types: &RuleTypes) -> (BTreeMap<StmtKey, VarDomain>, BTreeMap<StmtKey, VarDomain>) {
+    let mut imm_domains = shape.imm_vars.iter()
+        .map(|x| (*x, VarDomain { ty: None, width: 64, max: get_mask(64) }))
+        .collect::<BTreeMap<_, _>>();
+    for inst in &shape.insts {
+        if let Some(key) = inst.imm.filter(|x| matches!(get_stmt(rule, *x), Some(LinExpr::Var(_)))) {
+            let domain = get_imm_domain(inst, types.ctrl_types[&inst.key]);
+            let cur = imm_domains.get_mut(&key).unwrap();
+            *cur = VarDomain { ty: None, width: domain.width, max: cur.max.min(domain.max) };
+        }
+    }
+    let value_domains = shape.value_vars.iter().map(|x| {
+        let ty = types.types[x];
+        (*x, VarDomain { ty: Some(ty), width: ty.bits(), max: get_mask(ty.bits()) })
+    }).collect();
+    (imm_domains, value_domains)
+}
+
+fn splat(lane: u128, lane_bits: u32, lane_count: u32) -> u128 {
+    (0..lane_count).fold(0, |acc, x| acc | (lane << (x * lane_bits)))
+}
+
+// edge cases of each lane, in all lanes
+fn get_boundary_values(ty: Type) -> Vec<u128> {
+    let lane = ty.lane_type();
+    let lane_values: Vec<u128> = match lane {
+        types::F32 => vec![0, 0x8000_0000, 0x3f80_0000, 0xbf80_0000, 0x7f80_0000, 0xff80_0000, 0x7fc0_0000, 0x1, 0x7f7f_ffff],
+        types::F64 => vec![
+            0, 0x8000_0000_0000_0000, 0x3ff0_0000_0000_0000, 0xbff0_0000_0000_0000, 0x7ff0_0000_0000_0000,
+            0xfff0_0000_0000_0000, 0x7ff8_0000_0000_0000, 0x1, 0x7fef_ffff_ffff_ffff,
+        ],
+        _ => {
+            let mask = get_mask(lane.bits());
+            let sign = 1 << (lane.bits() - 1);
+            vec![0, 1, 2, mask, mask - 1, sign, sign - 1, sign + 1]
+        },
+    };
+    lane_values.into_iter().map(|x| splat(x, lane.bits(), ty.lane_count())).collect()
+}
+
+fn get_candidate(domain: &VarDomain, rng: &mut Rng, boundary: bool) -> u128 {
+    let val = match (domain.ty, boundary) {
+        (Some(ty), true) => rng.choose(&get_boundary_values(ty)),
+        (None, true) => {
+            let (width, max) = (domain.width as u128, domain.max);
+            rng.choose(&[0, 1, 2, width - 1, width, max / 2, max / 2 + 1, max - 1, max])
+        },
+        (_, false) => rng.next_u128(),
+    };
+    match domain.max.checked_add(1) {
+        Some(x) => val % x,
+        None => val,
+    }
+}
+
+fn eval_param(rule: &UnifiedRule, cond: &[CondExpr], param: &LinExprIdx, values: &HashMap<StmtKey, u128>) -> Option<u128> {
+    match param {
+        LinExprIdx::Cond(idx) => eval_cond_expr(rule, cond, *idx.borrow(), values, 128),
+        _ => {
+            let key = resolve(rule, param);
+            match get_stmt(rule, key) {
+                Some(LinExpr::Const(val)) => Some(*val as u128),
+                _ => values.get(&key).copied(),
+            }
+        },
+    }
+}
+
+// None if it depends on a var without value or on an unknown function
+fn eval_cond_expr(rule: &UnifiedRule, cond: &[CondExpr], idx: usize, values: &HashMap<StmtKey, u128>, width: u32) -> Option<u128> {
+    let val = match cond.get(idx)? {
+        CondExpr::Const(val) => *val as u128,
+        CondExpr::ConstPrim(sym) if sym == "true" => 1,
+        CondExpr::ConstPrim(sym) if sym == "false" => 0,
+        CondExpr::Expr { name, params } => {
+            let param_vals = params.iter().map(|x| eval_param(rule, cond, x, values)).collect::<Option<Vec<_>>>()?;
+            apply_function(name, &param_vals)?
+        },
+        CondExpr::Ident(param) => eval_param(rule, cond, param, values)?,
+        _ => return None,
+    };
+    Some(val & get_mask(width))
+}
+
+// the value that an equality condition (e.g., pow2(k)) gives to its var
+fn eval_equality(rule: &UnifiedRule, cond: &[CondExpr], values: &HashMap<StmtKey, u128>, width: u32) -> Option<u128> {
+    match cond.last()? {
+        CondExpr::Var => None,
+        CondExpr::Expr { name, params } if params.is_empty() || is_comparison(name) => None,
+        _ => eval_cond_expr(rule, cond, cond.len() - 1, values, width),
+    }
+}
+
+// same semantics as the conditions of productions (see prod_solve); None if it cannot be evaluated
+fn check_cond(rule: &UnifiedRule, cond: &[CondExpr], x: u128, width: u32, values: &HashMap<StmtKey, u128>) -> Option<bool> {
+    match cond.last()? {
+        CondExpr::Var => Some(true),
+        CondExpr::Expr { name, params } if params.is_empty() => check_predicate(name, x, width),
+        CondExpr::Expr { name, params } if is_comparison(name) && params.len() == 1 => {
+            let bound = eval_param(rule, cond, &params[0], values)? & get_mask(width);
+            Some(match name.as_str() {
+                "lt" => x < bound,
+                "le" => x <= bound,
+                "gt" => x > bound,
+                _ => x >= bound,
+            })
+        },
+        CondExpr::Expr { name, params } if name == "_pow2" && params.len() == 1 => {
+            let exp = eval_param(rule, cond, &params[0], values)?;
+            Some(exp < width as u128 && x == 1 << exp)
+        },
+        _ => Some(eval_cond_expr(rule, cond, cond.len() - 1, values, width)? == x),
+    }
+}
+
+pub(crate) fn get_conds(rule: &UnifiedRule, key: StmtKey) -> &[Vec<CondExpr>] {
+    match get_stmt(rule, key) {
+        Some(LinExpr::Var(conds)) => conds,
+        _ => &[],
+    }
+}
+
+// values of the vars of the domains; vars with an equality condition take its value if it can be evaluated
+fn sample_vars(rule: &UnifiedRule, domains: &BTreeMap<StmtKey, VarDomain>, fixed: &HashMap<StmtKey, u128>, rng: &mut Rng, boundary: bool) -> HashMap<StmtKey, u128> {
+    let mut values = fixed.clone();
+    for (key, domain) in domains {
+        let forced = get_conds(rule, *key).iter().find_map(|x| eval_equality(rule, x, &values, domain.width));
+        let val = forced.unwrap_or_else(|| get_candidate(domain, rng, boundary));
+        values.insert(*key, val);
+    }
+    values
+}
+
+// immediates that satisfy their conditions, trying boundary values first if boundary
+pub(crate) fn sample_imms(rule: &UnifiedRule, imm_domains: &BTreeMap<StmtKey, VarDomain>, rng: &mut Rng, boundary: bool) -> Option<HashMap<StmtKey, u128>> {
+    (0..MAX_TRIES)
+        .map(|x| sample_vars(rule, imm_domains, &HashMap::new(), rng, boundary || x % 2 == 0))
+        .find(|x| check_conds(rule, imm_domains, x, false) == Ok(true))
+}
+
+// arguments of the functions for the immediates; None if no sample satisfies the conditions
+pub(crate) fn sample_args(rule: &UnifiedRule, shape: &RuleShape, types: &RuleTypes, domains: (&BTreeMap<StmtKey, VarDomain>, &BTreeMap<StmtKey, VarDomain>), imms: &HashMap<StmtKey, u128>, rng: &mut Rng, boundary: bool) -> Result<Option<Vec<DataValue>>, String> {
+    let (imm_domains, value_domains) = domains;
+    let all_domains = imm_domains.iter().chain(value_domains.iter()).map(|(x, y)| (*x, *y)).collect();
+    let values = match (0..MAX_TRIES).map(|_| sample_vars(rule, value_domains, imms, rng, boundary)).find(|x| check_conds(rule, &all_domains, x, false) == Ok(true)) {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+    if !check_conds(rule, &all_domains, &values, true)? {
+        return Ok(None);
+    }
+    Ok(Some(shape.value_vars.iter().map(|x| to_data_value(values[x], types.types[x])).collect()))
+}
+
+// whether the values satisfy the conditions of the vars; conditions that cannot be evaluated are
+// errors if strict, and skipped otherwise (e.g., on immediates depending on runtime values)
+pub(crate) fn check_conds(rule: &UnifiedRule, domains: &BTreeMap<StmtKey, VarDomain>, values: &HashMap<StmtKey, u128>, strict: bool) -> Result<bool, String> {
+    for (key, domain) in domains {
+        let val = values[key];
+        if val > domain.max {
+            return Ok(false);
+        }
+        for cond in get_conds(rule, *key) {
+            match check_cond(rule, cond, val, domain.width, values) {
+                Some(true) => (),
+                Some(false) => return Ok(false),
+                None if strict => return Err(format!("condition {:?} cannot be evaluated", cond.last())),
+                None => (),
+            }
+        }
+    }
+    Ok(true)
+}
+
+// value of an immediate: a var, a constant or a function of them (e.g., u64_add in RHS)
+fn eval_imm(rule: &UnifiedRule, key: StmtKey, imms: &HashMap<StmtKey, u128>) -> Option<u128> {
+    match get_stmt(rule, key)? {
+        LinExpr::Var(_) => imms.get(&key).copied(),
+        LinExpr::Const(val) => Some(*val as u128),
+        LinExpr::Expr { name, params } => {
+            let param_vals = params.iter().map(|x| eval_imm(rule, resolve(rule, x), imms)).collect::<Option<Vec<_>>>()?;
+            apply_function(name, &param_vals)
+        },
+        _ => None,
+    }
+}
+
+fn get_intcc(cc: &Option<String>) -> Result<IntCC, String> {
+    cc.as_ref().and_then(|x| x.strip_prefix("IntCC."))
+        .and_then(|x| IntCC::all().iter().find(|y| format!("{:?}", y) == x).copied())
+        .ok_or(format!("unknown condition code {:?}", cc))
+}
+
+fn get_floatcc(cc: &Option<String>) -> Result<FloatCC, String> {
+    cc.as_ref().and_then(|x| x.strip_prefix("FloatCC."))
+        .and_then(|x| FloatCC::all().iter().find(|y| format!("{:?}", y) == x).copied())
+        .ok_or(format!("unknown condition code {:?}", cc))
+}
+
+fn get_inst_data(inst: &RuleInst, args: &[Value], imm: u128) -> Result<InstructionData, String> {
+    let opcode = inst.opcode;
+    let data = match opcode.format() {
+        InstructionFormat::Unary => InstructionData::Unary { opcode, arg: args[0] },
+        InstructionFormat::Binary => InstructionData::Binary { opcode, args: [args[0], args[1]] },
+        InstructionFormat::Ternary => InstructionData::Ternary { opcode, args: [args[0], args[1], args[2]] },
+        InstructionFormat::UnaryImm => InstructionData::UnaryImm { opcode, imm: Imm64::new(imm as i64) },
+        InstructionFormat::UnaryIeee32 => InstructionData::UnaryIeee32 { opcode, imm: Ieee32::with_bits(imm as u32) },
+        InstructionFormat::UnaryIeee64 => InstructionData::UnaryIeee64 { opcode, imm: Ieee64::with_bits(imm as u64) },
+        InstructionFormat::BinaryImm64 => InstructionData::BinaryImm64 { opcode, arg: args[0], imm: Imm64::new(imm as i64) },
+        InstructionFormat::BinaryImm8 => InstructionData::BinaryImm8 { opcode, arg: args[0], imm: imm as u8 },
+        InstructionFormat::TernaryImm8 => InstructionData::TernaryImm8 { opcode, args: [args[0], args[1]], imm: imm as u8 },
+        InstructionFormat::IntCompare => InstructionData::IntCompare { opcode, args: [args[0], args[1]], cond: get_intcc(&inst.cc)? },
+        InstructionFormat::IntCompareImm => InstructionData::IntCompareImm { opcode, arg: args[0], cond: get_intcc(&inst.cc)?, imm: Imm64::new(imm as i64) },
+        InstructionFormat::FloatCompare => InstructionData::FloatCompare { opcode, args: [args[0], args[1]], cond: get_floatcc(&inst.cc)? },
+        format => return Err(format!("unsupported instruction format {:?}", format)),
+    };
+    Ok(data)
+}
+
+// keys of the instructions that the root depends on
+fn get_used_insts(shape: &RuleShape, root: StmtKey) -> HashSet<StmtKey> {
+    let mut used = HashSet::new();
+    let mut pending = vec![root];
+    while let Some(key) = pending.pop() {
+        if let Some(inst) = shape.insts.iter().find(|x| x.key == key) {
+            if used.insert(key) {
+                pending.extend(inst.args.iter().copied());
+            }
+        }
+    }
+    used
+}
+
+// one side of the rule as a function of the value vars; Ok(None) if an immediate is out of its range
+pub(crate) fn build_function(name: &str, rule: &UnifiedRule, shape: &RuleShape, types: &RuleTypes, imms: &HashMap<StmtKey, u128>, root: StmtKey) -> Result<Option<Function>, String> {
+    let mut sig = Signature::new(CallConv::Fast); // the default of CLIF text
+    sig.params.extend(shape.value_vars.iter().map(|x| AbiParam::new(types.types[x])));
+    sig.returns.push(AbiParam::new(types.types[&root]));
+    let mut func = Function::with_name_signature(UserFuncName::testcase(name), sig);
+    let block = func.dfg.make_block();
+    func.layout.append_block(block);
+
+    let mut values = HashMap::new();
+    for key in &shape.value_vars {
+        values.insert(*key, func.dfg.append_block_param(block, types.types[key]));
+    }
+
+    let used_insts = get_used_insts(shape, root);
+    for inst in shape.insts.iter().filter(|x| used_insts.contains(&x.key)) {
+        let ctrl_type = types.ctrl_types[&inst.key];
+        let imm = match inst.imm {
+            Some(key) => {
+                let domain = get_imm_domain(inst, ctrl_type);
+                match eval_imm(rule, key, imms) {
+                    Some(x) if x & get_mask(domain.width) <= domain.max => x & get_mask(domain.width),
+                    Some(_) => return Ok(None),
+                    None => return Err(format!("immediate of {} cannot be evaluated", inst.opcode)),
+                }
+            },
+            None => 0,
+        };
+        let args = inst.args.iter().map(|x| values[x]).collect::<Vec<_>>();
+        let cur = func.dfg.make_inst(get_inst_data(inst, &args, imm)?);
+        func.dfg.make_inst_results(cur, ctrl_type);
+        func.layout.append_inst(cur, block);
+        values.insert(inst.key, func.dfg.first_result(cur));
+    }
+
+    let ret_args = ValueList::from_slice(&[values[&root]], &mut func.dfg.value_lists);
+    let ret = func.dfg.make_inst(InstructionData::MultiAry { opcode: Opcode::Return, args: ret_args });
+    func.dfg.make_inst_results(ret, types::INVALID);
+    func.layout.append_inst(ret, block);
+    Ok(Some(func))
+}
+
+fn to_data_value(bits: u128, ty: Type) -> DataValue {
+    DataValue::read_from_slice_le(&bits.to_le_bytes()[..ty.bytes() as usize], ty)
+}
+
+fn to_bits(value: &DataValue) -> u128 {
+    let mut bytes = [0; 16];
+    value.write_to_slice_le(&mut bytes);
+    u128::from_le_bytes(bytes)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum RunResult {
+    Value(DataValue),
+    Trap(String),
+}
+
+impl fmt::Display for RunResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunResult::Value(x) => write!(f, "{}", x),
+            RunResult::Trap(x) => write!(f, "trap ({})", x),
+        }
+    }
+}
+
+pub(crate) fn run_function(func: &Function, args: &[DataValue]) -> Result<RunResult, String> {
+    let state = InterpreterState::default().with_function_store(FunctionStore::from(func));
+    let mut interpreter = Interpreter::new(state).with_fuel(Some(FUEL));
+    match interpreter.call_by_name(&func.name.to_string(), args) {
+        Ok(ControlFlow::Return(rets)) if rets.len() == 1 => Ok(RunResult::Value(rets[0].clone())),
+        Ok(ControlFlow::Trap(trap)) => Ok(RunResult::Trap(trap.to_string())),
+        Ok(_) => Err(String::from("unexpected control flow in the interpreter")),
+        Err(e) => Err(format!("interpreter: {}", e)),
+    }
+}
+
+fn get_lanes(value: &DataValue, ty: Type) -> Vec<u128> {
+    let bits = to_bits(value);
+    let lane_bits = ty.lane_type().bits();
+    (0..ty.lane_count()).map(|x| (bits >> (x * lane_bits)) & get_mask(lane_bits)).collect()
+}
+
+fn is_nan_lane(lane: u128, lane_type: Type) -> bool {
+    match lane_type {
+        types::F32 => f32::from_bits(lane as u32).is_nan(),
+        types::F64 => f64::from_bits(lane as u64).is_nan(),
+        _ => false,
+    }
+}
+
+pub(crate) fn has_nan(value: &DataValue, ty: Type) -> bool {
+    get_lanes(value, ty).into_iter().any(|x| is_nan_lane(x, ty.lane_type()))
+}
+
+// bitwise equality, except that NaN lanes are equal to each other
+fn is_equivalent(x: &DataValue, y: &DataValue, ty: Type) -> bool {
+    get_lanes(x, ty).into_iter().zip(get_lanes(y, ty)).all(|(x_lane, y_lane)| {
+        x_lane == y_lane || (is_nan_lane(x_lane, ty.lane_type()) && is_nan_lane(y_lane, ty.lane_type()))
+    })
+}
+
+enum RuleCheck {
+    Sound(usize),
+    Unsound(SoundCounterexample),
+    Skipped(String),
+}
+
+// Ok(Some) with a counterexample if the sides disagree
+fn check_instance(rule: &UnifiedRule, shape: &RuleShape, types: &RuleTypes, option: &SoundOption, rng: &mut Rng, runs: &mut usize) -> Result<Option<SoundCounterexample>, String> {
+    let (imm_domains, value_domains) = get_domains(rule, shape, types);
+    let flags = settings::Flags::new(settings::builder());
+    let ret_type = types.types[&shape.lhs_root];
+    let prev_runs = *runs;
+    let mut reason = String::from("no immediates satisfy the conditions");
+
+    let imm_samples = if imm_domains.is_empty() { 1 } else { IMM_SAMPLES };
+    for imm_idx in 0..imm_samples {
+        let imms = match sample_imms(rule, &imm_domains, rng, imm_idx < imm_samples / 2) {
+            Some(x) => x,
+            None => continue,
+        };
+        let (lhs, rhs) = match (
+            build_function("lhs", rule, shape, types, &imms, shape.lhs_root)?,
+            build_function("rhs", rule, shape, types, &imms, shape.rhs_root)?,
+        ) {
+            (Some(x), Some(y)) => (x, y),
+            _ => {
+                reason = String::from("immediate out of range");
+                continue;
+            },
+        };
+        if let Err(e) = verify_function(&lhs, &flags).and_then(|_| verify_function(&rhs, &flags)) {
+            reason = format!("ill-typed instantiation: {}", e.to_string().trim());
+            continue;
+        }
+
+        reason = String::from("no inputs satisfy the conditions");
+        for input_idx in 0..option.inputs {
+            let boundary = input_idx < option.inputs / 2;
+            let args = match sample_args(rule, shape, types, (&imm_domains, &value_domains), &imms, rng, boundary)? {
+                Some(x) => x,
+                None => continue,
+            };
+
+            // the rewrite may do anything where the LHS traps
+            let lhs_result = match run_function(&lhs, &args)? {
+                RunResult::Value(x) => x,
+                RunResult::Trap(_) => {
+                    reason = String::from("LHS traps on every input");
+                    continue;
+                },
+            };
+            let rhs_result = run_function(&rhs, &args)?;
+            *runs += 1;
+            let is_same = match &rhs_result {
+                RunResult::Value(x) => is_equivalent(&lhs_result, x, ret_type),
+                RunResult::Trap(_) => false,
+            };
+            if !is_same {
+                return Ok(Some(SoundCounterexample {
+                    args: args.iter().map(|x| x.to_string()).collect(),
+                    lhs_result: lhs_result.to_string(),
+                    rhs_result: rhs_result.to_string(),
+                    lhs: lhs.display().to_string(),
+                    rhs: rhs.display().to_string(),
+                }));
+            }
+        }
+    }
+
+    if *runs == prev_runs {
+        return Err(reason);
+    }
+    Ok(None)
+}
+
+fn check_rule(rule: &UnifiedRule, option: &SoundOption, rng: &mut Rng) -> RuleCheck {
+    let shape = match get_rule_shape(rule, true) {
+        Ok(x) => x,
+        Err(e) => return RuleCheck::Skipped(e),
+    };
+    let instances = get_instances(rule, &shape);
+    if instances.is_empty() {
+        return RuleCheck::Skipped(String::from("no type instantiation the interpreter runs"));
+    }
+
+    let mut runs = 0;
+    let mut reason = None;
+    for types in &instances {
+        match check_instance(rule, &shape, types, option, rng, &mut runs) {
+            Ok(Some(x)) => return RuleCheck::Unsound(x),
+            Ok(None) => (),
+            Err(e) => { reason.get_or_insert(e); },
+        }
+    }
+    match (runs, reason) {
+        (0, Some(reason)) => RuleCheck::Skipped(reason),
+        (runs, _) => RuleCheck::Sound(runs),
+    }
+}
+
+// outcome of each rule; the variants of a rule (from inlining and substitution) are checked together
+pub fn check_rules(rules: &[UnifiedRule], option: &SoundOption) -> Vec<SoundOutcome> {
+    let mut rng = Rng::new(option.seed);
+    let mut outcomes: BTreeMap<RuleSource, SoundOutcome> = BTreeMap::new();
+    for rule in rules {
+        let source = match Diagnostics::get_target(&rule.sources) {
+            Some(x) => x.clone(),
+            None => continue,
+        };
+        let outcome = outcomes.entry(source.clone()).or_insert(SoundOutcome {
+            source,
+            status: SoundStatus::Skipped,
+            runs: 0,
+            reason: None,
+            counterexample: None,
+        });
+        if outcome.status == SoundStatus::Unsound {
+            continue;
+        }
+        match check_rule(rule, option, &mut rng) {
+            RuleCheck::Sound(runs) => {
+                outcome.status = SoundStatus::Sound;
+                outcome.runs += runs;
+                outcome.reason = None;
+            },
+            RuleCheck::Unsound(counterexample) => {
+                outcome.status = SoundStatus::Unsound;
+                outcome.runs += 1;
+                outcome.reason = None;
+                outcome.counterexample = Some(counterexample);
+            },
+            RuleCheck::Skipped(reason) => {
+                if outcome.status == SoundStatus::Skipped && outcome.reason.is_none() {
+                    outcome.reason = Some(reason);
+                }
+            },
+        }
+    }
+    outcomes.into_values().collect()
+}
+
+pub fn check_soundness(match_option: MatchOption, src_root: &Path, option: &SoundOption) -> Vec<SoundOutcome> {
+    check_rules(&get_simplify_rules(match_option, src_root), option)
+}
+
+pub fn soundness_to_table(outcomes: &[SoundOutcome]) -> String {
+    let rows = outcomes.iter().map(|x| [
+        format!("{}:{}", x.source.file, x.source.line),
+        x.source.prio.to_string(),
+        format!("{:?}", x.status).to_lowercase(),
+        x.runs.to_string(),
+        x.reason.clone().unwrap_or_default(),
+    ]).collect::<Vec<_>>();
+    let mut table = format_table(["rule", "prio", "status", "runs", "reason"], &rows, &[1, 3]);
+
+    // counterexamples
+    for outcome in outcomes {
+        if let Some(x) = &outcome.counterexample {
+            table.push_str(&format!("\n{}:{}: args [{}], lhs {}, rhs {}\n{}{}",
+                outcome.source.file, outcome.source.line, x.args.join(", "), x.lhs_result, x.rhs_result, x.lhs, x.rhs));
+        }
+    }
+
+    let count = |status| outcomes.iter().filter(|x| x.status == status).count();
+    table.push_str(&format!("\n{} sound, {} unsound, {} skipped\n", count(SoundStatus::Sound), count(SoundStatus::Unsound), count(SoundStatus::Skipped)));
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use crate::norm::LinVec;
+
+    use super::*;
+
+    // (opcode ty x y) => (opcode ty y x), or (opcode ty x 0) => x if is_const
+    fn get_rule(opcode: &str, is_const: bool, line: usize) -> UnifiedRule {
+        let mut lhs = LinVec::new();
+        let ty = LinExprIdx::LHS(lhs.push(LinExpr::TypeVar(vec![types::I8, types::I32, types::I64])));
+        let x = LinExprIdx::LHS(lhs.push(LinExpr::Var(Vec::new())));
+        let y = if is_const {
+            let zero = LinExprIdx::LHS(lhs.push(LinExpr::Const(0)));
+            LinExprIdx::LHS(lhs.push(LinExpr::Expr { name: String::from("iconst"), params: vec![ty.clone(), zero] }))
+        } else {
+            LinExprIdx::LHS(lhs.push(LinExpr::Var(Vec::new())))
+        };
+        lhs.push(LinExpr::Expr { name: String::from(opcode), params: vec![ty.clone(), x.clone(), y.clone()] });
+
+        let mut rhs = LinVec::new();
+        if is_const {
+            rhs.push(LinExpr::Ident(x));
+        } else {
+            rhs.push(LinExpr::Expr { name: String::from(opcode), params: vec![ty, y, x] });
+        }
+        UnifiedRule::new(lhs, rhs, false).with_sources(vec![RuleSource::new_target("opts/arithmetic.isle", line)])
+    }
+
+    #[test]
+    fn test_check_rules() {
+        let rules = vec![get_rule("iadd", true, 10), get_rule("iadd", false, 20), get_rule("isub", false, 30), get_rule("udiv", true, 40)];
+        let outcomes = check_rules(&rules, &SoundOption::default());
+        println!("{}", soundness_to_table(&outcomes));
+        assert_eq!(outcomes[0].status, SoundStatus::Sound);
+        assert_eq!(outcomes[1].status, SoundStatus::Sound);
+        assert_eq!(outcomes[2].status, SoundStatus::Unsound);
+        assert!(outcomes[2].counterexample.as_ref().unwrap().lhs.contains("isub"));
+        assert_eq!(outcomes[3].status, SoundStatus::Skipped); // division by zero always traps
+    }
+}
@@ -119,6 +119,9 @@ pub fn subst_apply_result(
     // Step 3: remove dangling expressions
     new_lin_result.remove_dangling_expr(match_stmts);
     new_lin_result.remove_redundant_idents(match_stmts);
+
+    // Step 4: record the applied rule
+    new_lin_result.rule.sources.extend(subst.rule.sources.iter().cloned());
     
     Ok(Some(new_lin_result))
 }
@@ -406,12 +409,12 @@ fn apply_subst_map_idx(expr_map: &Vec<(LinExprIdx, LinExprIdx)>, idx_ref_what_rh
 
 #[cfg(test)]
 mod test {
-    use crate::{isle::ISLEParseOptions, isle_type::type_rules_opt};
+    use crate::{isle::{default_cranelift_src, ISLEParseOptions}, isle_type::type_rules_opt};
 
     use super::*;
 
     fn prepare_subst_map(parse_option: ISLEParseOptions) -> HashMap<String, Vec<LinResult>> {
-        let clir_results = type_rules_opt(parse_option);
+        let clir_results = type_rules_opt(parse_option, &default_cranelift_src());
         let mut clir_simplify_results = Vec::new();
         let mut clir_lower_results = Vec::new();
         let mut subst_map: HashMap<String, Vec<LinResult>> = HashMap::new();
@@ -446,7 +449,7 @@ mod test {
     fn test_subst_results() {
         let parse_option = ISLEParseOptions::Lower;
         let subst_map = prepare_subst_map(parse_option);
-        let results = type_rules_opt(parse_option);
+        let results = type_rules_opt(parse_option, &default_cranelift_src());
         for lin_result in results {
             let cur_result = subst_result(&subst_map, lin_result, true);
             println!("{:#?}", cur_result);
@@ -457,7 +460,7 @@ mod test {
     fn test_subst_result_one() {
         let parse_option = ISLEParseOptions::TestOpt;
         let subst_map = prepare_subst_map(parse_option);
-        let results = type_rules_opt(parse_option);
+        let results = type_rules_opt(parse_option, &default_cranelift_src());
         println!("{:#?}", subst_result(&subst_map, results.last().unwrap().clone(), false));
     }
 }
\ No newline at end of file
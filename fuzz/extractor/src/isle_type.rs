@@ -1,6 +1,8 @@
 // Step 1.4: Type linearized ISLE rules
 
-use crate::{norm::LinResult, isle::ISLEParseOptions, isle_lin::linearize_rules_opt, isle_inl::process_internals};
+use std::path::Path;
+
+use crate::{diagnostics::{DiagStage, Diagnostics}, norm::LinResult, isle::ISLEParseOptions, isle_lin::linearize_rules_opt_diag, isle_inl::process_internals};
 
 fn type_rule(lin_result: LinResult) -> Vec<LinResult> {
     // Basic separation of typevars from vars are already done in linearization
@@ -12,24 +14,40 @@ fn type_rule(lin_result: LinResult) -> Vec<LinResult> {
     inl_results
 }
 
-pub fn type_rules_opt(opt: ISLEParseOptions) -> Vec<LinResult> {
-    let lin_rules = linearize_rules_opt(opt);
-    lin_rules.into_iter().flat_map(type_rule).collect()
+pub fn type_rules_opt(opt: ISLEParseOptions, src_root: &Path) -> Vec<LinResult> {
+    type_rules_opt_diag(opt, src_root, &mut Diagnostics::disabled())
+}
+
+pub fn type_rules_opt_diag(opt: ISLEParseOptions, src_root: &Path, diag: &mut Diagnostics) -> Vec<LinResult> {
+    let lin_rules = linearize_rules_opt_diag(opt, src_root, diag);
+    let mut results = Vec::new();
+    for lin_rule in lin_rules {
+        let target = Diagnostics::get_target(&lin_rule.rule.sources).cloned();
+        let mut typed_rules = type_rule(lin_rule);
+        if let Some(target) = target {
+            diag.add_rule(&target);
+            diag.add_survivors(&target, DiagStage::Inline, typed_rules.len());
+        }
+        results.append(&mut typed_rules);
+    }
+    results
 }
 
 #[cfg(test)]
 mod test {
+    use crate::{isle::default_cranelift_src, isle_lin::linearize_rules_opt};
+
     use super::*;
 
     #[test]
     fn test_type_rules() {
-        let rules = type_rules_opt(ISLEParseOptions::Lower);
+        let rules = type_rules_opt(ISLEParseOptions::Lower, &default_cranelift_src());
         println!("{:#?}", rules);
     }
 
     #[test]
     fn test_type_rule_one() {
-        let rules = linearize_rules_opt(ISLEParseOptions::Opt);
+        let rules = linearize_rules_opt(ISLEParseOptions::Opt, &default_cranelift_src());
         println!("{:#?}", type_rule(rules[89].clone()));
     }
 }
\ No newline at end of file
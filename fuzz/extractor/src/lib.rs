@@ -0,0 +1,68 @@
+//! Rule extractor of RGFuzz.
+//!
+//! Extracts WebAssembly production rules from the ISLE rules of Cranelift
+//! (see README.md for the pipeline). The usual entry point is [`ExtractConfig`]:
+//!
+//! ```no_run
+//! use extractor::{ExtractConfig, MatchOption};
+//!
+//! let config = ExtractConfig::builder()
+//!     .match_option(MatchOption::OptX64)
+//!     .with_typing(true)
+//!     .build()
+//!     .unwrap();
+//! for rule in config.extract().unwrap() {
+//!     println!("{}", rule.to_string());
+//! }
+//! ```
+//!
+//! Each pipeline step is also available on its own:
+//! - [`isle_type::type_rules_opt`]: parsed, linearized and typed ISLE rules ([`norm::LinResult`])
+//! - [`wasm_map::get_clir_wasm_map`]: mapping from Cranelift IR nodes to WebAssembly instructions
+//! - [`rule_match::match_and_learn`]: matched ISLE rules ([`norm::MatchResult`])
+//! - [`prod_extract::learn_prods`]: production rules ([`prod::ProdRule`])
+//! - [`prod_multi::get_multi_value_prods`]: multi-value rules paired from the production rules
+//! - [`prod_solve::solve_prod`]: concrete values satisfying the conditions of a production rule
+//! - [`prod_typing::get_typing_rule_prods`]: typing rules, one instruction each
+//! - [`isle_sound::check_soundness`]: simplify rules run on both sides in the Cranelift interpreter
+//! - [`clif_export::export_clif`]: LHS of simplify/lower rules as CLIF `test run` (and `test optimize`) files
+//! - [`prod_corpus::build_corpus`]: one WebAssembly module per production rule
+//! - [`prod_verify::verify_prods`]: whether the modules make Cranelift fire their target rules (`verify` feature)
+//!
+//! [`ExtractConfig::report`] tells which ISLE rules yield productions and where the others drop out.
+
+pub mod clif_export;
+pub mod config;
+pub mod diagnostics;
+pub mod isle;
+pub mod isle_extern;
+pub mod isle_sound;
+pub mod isle_type;
+pub mod norm;
+pub mod prod;
+pub mod prod_cache;
+pub mod prod_corpus;
+pub mod prod_extract;
+pub mod prod_multi;
+pub mod prod_solve;
+pub mod prod_typing;
+pub mod prod_verify;
+pub mod rule_match;
+pub mod wasm_comp;
+pub mod wasm_env;
+pub mod wasm_map;
+
+mod isle_cond;
+mod isle_inl;
+mod isle_lin;
+mod isle_norm;
+mod isle_subst;
+mod wasm_norm;
+
+pub use config::{ExtractConfig, ExtractConfigBuilder};
+pub use diagnostics::Diagnostics;
+pub use isle::ISLEParseOptions;
+pub use prod::{ProdRule, ProdRuleSet};
+pub use rule_match::MatchOption;
+pub use wasm_env::{TranslateEnv, WasmtimeEnvOption};
+pub use wasm_map::{TargetArch, WasmMapOption};
@@ -1,110 +1,456 @@
-use std::collections::HashSet;
-use std::env;
-
-use enum_iterator::all;
-use prod::ProdRule;
-use prod_typing::get_typing_rule_prods;
-use prod_extract::learn_prods;
-use rule_match::MatchOption;
-use wasm_ast::ValueType;
-
-mod isle;
-mod isle_inl;
-mod isle_norm;
-mod isle_lin;
-mod isle_type;
-mod isle_subst;
-mod isle_cond;
-mod wasm_map;
-mod wasm_comp;
-mod wasm_norm;
-mod prod;
-mod prod_extract;
-mod prod_typing;
-mod norm;
-mod rule_match;
-
-pub fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        return;
-    }
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
 
-    // typing rules
-    let mut typing_rules: Vec<ProdRule> = Vec::new();
-    for ty in all::<ValueType>() {
-        let mut ty_rules = get_typing_rule_prods(vec![ty].into());
-        typing_rules.append(&mut ty_rules);
-    }
-    let mut noret_rules = get_typing_rule_prods(Vec::new().into());
-    typing_rules.append(&mut noret_rules);
-
-    // print rules
-    if args[1] == "all" {
-        let prod_rules = learn_prods(MatchOption::All); // may contain duplicates
-        let mut prod_rules_set: HashSet<String> = HashSet::from_iter(prod_rules.iter().map(|x| x.to_string()));
-        prod_rules_set.extend(typing_rules.iter().map(|x| x.to_string()));
-        for rule in prod_rules_set {
-            println!("{}", rule);
-        }
-    }
-    else if args[1] == "opt" {
-        let prod_rules = learn_prods(MatchOption::Opt); // may contain duplicates
-        let prod_rules_set: HashSet<String> = HashSet::from_iter(prod_rules.iter().map(|x| x.to_string()));
-        for rule in prod_rules_set {
-            println!("{}", rule);
-        }
-    }
-    else if args[1] == "lower" {
-        let prod_rules = learn_prods(MatchOption::Lower); // may contain duplicates
-        let prod_rules_set: HashSet<String> = HashSet::from_iter(prod_rules.iter().map(|x| x.to_string()));
-        for rule in prod_rules_set {
-            println!("{}", rule);
-        }
+use anyhow::{bail, Context, Error};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use extractor::{
+    clif_export::{export_clif, write_clif_files, ClifOption},
+    isle_extern::{directives_to_table, find_unknown_directives},
+    isle_sound::{check_soundness, soundness_to_table, SoundOption},
+    prod::canonicalize_rules,
+    prod_corpus::{build_corpus, write_corpus, MANIFEST_NAME},
+    prod_typing::get_all_typing_rule_prods,
+    prod_verify::{filter_dead_prods, firing_report_to_table, get_firing_report},
+    ExtractConfig, MatchOption, ProdRule, ProdRuleSet, TranslateEnv, WasmtimeEnvOption,
+};
+
+#[derive(Parser, Debug)]
+#[command(about = "Extracts WebAssembly production rules from Cranelift ISLE rules")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// wasmtime checkout, its cranelift directory, or cranelift/codegen/src
+    /// (defaults to the wasmtime in this repository)
+    #[arg(long, global = true)]
+    cranelift_src: Option<PathBuf>,
+
+    /// only use the lowering rules of this backend
+    #[arg(long, value_enum, global = true)]
+    isa: Option<Isa>,
+
+    /// write rules to this file instead of stdout
+    #[arg(long, global = true)]
+    output: Option<PathBuf>,
+
+    /// output format (defaults to python for rules, and to table for report, directives, soundness, and verify)
+    #[arg(long, value_enum, global = true)]
+    format: Option<OutputFormat>,
+
+    /// directory of the learned rule cache (defaults to target/rule-cache)
+    #[arg(long, global = true)]
+    cache_dir: Option<PathBuf>,
+
+    /// always rerun the whole pipeline, without reading or writing the cache
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    /// only use the WebAssembly instructions with the smallest translation for each IR
+    /// (otherwise the others are kept with lower weights)
+    #[arg(long, global = true)]
+    strict_candidates: bool,
+
+    /// translate WebAssembly instructions without NaN canonicalization
+    /// (the engine runs with it, so the rules target its IR by default)
+    #[arg(long, global = true)]
+    no_nan_canonicalization: bool,
+
+    /// also pair the learned rules into multi-value rules
+    #[arg(long, global = true)]
+    multi_value: bool,
+
+    /// translate WebAssembly instructions with the dummy environment of cranelift-wasm
+    /// instead of the wasmtime-like one
+    #[arg(long, global = true)]
+    dummy_env: bool,
+
+    /// translate memory accesses with dynamic heaps (explicit bounds checks)
+    #[arg(long, global = true, conflicts_with = "dummy_env")]
+    dynamic_memory: bool,
+
+    /// translate bounds checks without Spectre mitigation
+    #[arg(long, global = true, conflicts_with = "dummy_env")]
+    no_spectre_mitigation: bool,
+}
+
+#[derive(Subcommand, Clone, Copy, Debug, PartialEq)]
+enum Command {
+    /// optimization, lowering and typing rules
+    #[command(name = "all")]
+    All,
+    /// optimization rules
+    #[command(name = "opt")]
+    Opt,
+    /// lowering rules
+    #[command(name = "lower")]
+    Lower,
+    /// rules from the test ISLE files, as optimization rules
+    #[command(name = "testopt")]
+    TestOpt,
+    /// rules from the test ISLE files, as lowering rules
+    #[command(name = "testlower")]
+    TestLower,
+    /// typing rules only
+    #[command(name = "typing")]
+    Typing,
+    /// optimization and lowering rules
+    #[command(name = "optlower")]
+    OptLower,
+    /// optimization and typing rules
+    #[command(name = "opttyping")]
+    OptTyping,
+    /// lowering and typing rules
+    #[command(name = "lowertyping")]
+    LowerTyping,
+    /// x64 lowering rules
+    #[command(name = "x64")]
+    X64(IsaModeArgs),
+    /// aarch64 lowering rules
+    #[command(name = "aarch64")]
+    Aarch64(IsaModeArgs),
+    /// s390x lowering rules
+    #[command(name = "s390x")]
+    S390x(IsaModeArgs),
+    /// riscv64 lowering rules
+    #[command(name = "riscv64")]
+    Riscv64(IsaModeArgs),
+    /// for each simplify/lower rule, its productions or why it was dropped
+    /// (a table, or JSON with --format json)
+    #[command(name = "report")]
+    Report(ReportArgs),
+    /// extern terms used by simplify/lower rules that have no handler in isle_inl
+    /// (a table, or JSON with --format json)
+    #[command(name = "directives")]
+    Directives(ReportArgs),
+    /// runs both sides of each simplify rule in the Cranelift interpreter and
+    /// reports the rules whose results differ (a table, or JSON with --format json)
+    #[command(name = "soundness")]
+    Soundness(SoundArgs),
+    /// writes the LHS of each simplify/lower rule as a CLIF `test run` file, and
+    /// of each simplify rule as a `test optimize` file, into the --output directory
+    #[command(name = "export-clif")]
+    ExportClif(ExportClifArgs),
+    /// writes each production rule as a standalone WebAssembly module, with a
+    /// manifest mapping the modules to their rules, into the --output directory
+    #[command(name = "export-corpus")]
+    ExportCorpus(ReportArgs),
+    /// compiles each production rule as the engine does and reports, for each simplify/lower
+    /// rule, whether its productions fire it (a table, or JSON with --format json);
+    /// requires the extractor to be built with build-verify.sh
+    #[command(name = "verify")]
+    Verify(VerifyArgs),
+}
+
+#[derive(Args, Clone, Copy, Debug, PartialEq)]
+struct IsaModeArgs {
+    /// also use the optimization rules (as in optlower)
+    #[arg(long)]
+    with_opt: bool,
+
+    /// also print the typing rules (as in lowertyping)
+    #[arg(long)]
+    with_typing: bool,
+}
+
+#[derive(Args, Clone, Copy, Debug, PartialEq)]
+struct ReportArgs {
+    /// rules to report on
+    #[arg(value_enum, default_value_t = ReportRules::All)]
+    rules: ReportRules,
+}
+
+#[derive(Args, Clone, Copy, Debug, PartialEq)]
+struct SoundArgs {
+    /// rules to check
+    #[arg(value_enum, default_value_t = SoundRules::Opt)]
+    rules: SoundRules,
+
+    /// inputs per instantiation of a rule
+    #[arg(long, default_value_t = SoundOption::default().inputs)]
+    inputs: usize,
+
+    /// seed of the inputs
+    #[arg(long, default_value_t = SoundOption::default().seed)]
+    seed: u64,
+}
+
+#[derive(Args, Clone, Copy, Debug, PartialEq)]
+struct ExportClifArgs {
+    /// rules to export
+    #[arg(value_enum, default_value_t = ReportRules::All)]
+    rules: ReportRules,
+
+    /// run lines per function
+    #[arg(long, default_value_t = ClifOption::default().runs)]
+    runs: usize,
+
+    /// seed of the inputs
+    #[arg(long, default_value_t = ClifOption::default().seed)]
+    seed: u64,
+}
+
+#[derive(Args, Clone, Copy, Debug, PartialEq)]
+struct VerifyArgs {
+    /// rules to verify
+    #[arg(value_enum, default_value_t = ReportRules::All)]
+    rules: ReportRules,
+
+    /// print the rules without the productions that never fire their rule, instead of the report
+    #[arg(long)]
+    filter: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum SoundRules {
+    Opt,
+    #[value(name = "testopt")]
+    TestOpt,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum ReportRules {
+    All,
+    Opt,
+    Lower,
+    #[value(name = "testopt")]
+    TestOpt,
+    #[value(name = "testlower")]
+    TestLower,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Isa {
+    X64,
+    Aarch64,
+    S390x,
+    Riscv64,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum OutputFormat {
+    Python, // one python tuple literal per line (consumed by the executor)
+    Json,
+    Table, // plain-text table of report, directives, soundness, and verify
+}
+
+// lowering rules (optionally with optimization rules) for the selected backend
+fn get_lower_option(isa: Option<Isa>, with_opt: bool) -> MatchOption {
+    match (isa, with_opt) {
+        (None, false) => MatchOption::Lower,
+        (None, true) => MatchOption::All,
+        (Some(Isa::X64), false) => MatchOption::X64,
+        (Some(Isa::X64), true) => MatchOption::OptX64,
+        (Some(Isa::Aarch64), false) => MatchOption::ARM64,
+        (Some(Isa::Aarch64), true) => MatchOption::OptARM64,
+        (Some(Isa::S390x), false) => MatchOption::S390X,
+        (Some(Isa::S390x), true) => MatchOption::OptS390X,
+        (Some(Isa::Riscv64), false) => MatchOption::RISCV64,
+        (Some(Isa::Riscv64), true) => MatchOption::OptRISCV64,
     }
-    else if args[1] == "testopt" {
-        let prod_rules = learn_prods(MatchOption::TestOpt); // may contain duplicates
-        let prod_rules_set: HashSet<String> = HashSet::from_iter(prod_rules.iter().map(|x| x.to_string()));
-        for rule in prod_rules_set {
-            println!("{}", rule);
-        }
+}
+
+fn write_rules(rules: Vec<ProdRule>, format: OutputFormat, out: &mut dyn Write) -> Result<(), Error> {
+    match format {
+        OutputFormat::Python => {
+            for rule in rules {
+                writeln!(out, "{}", rule.to_string())?;
+            }
+        },
+        OutputFormat::Json => {
+            writeln!(out, "{}", ProdRuleSet::new(rules).to_json()?)?;
+        },
+        OutputFormat::Table => bail!("--format table is only for report, directives, soundness, and verify"),
     }
-    else if args[1] == "testlower" {
-        let prod_rules = learn_prods(MatchOption::TestLower); // may contain duplicates
-        let prod_rules_set: HashSet<String> = HashSet::from_iter(prod_rules.iter().map(|x| x.to_string()));
-        for rule in prod_rules_set {
-            println!("{}", rule);
+    Ok(())
+}
+
+impl Cli {
+    // the reports are tables or JSON, and the rules are python or JSON
+    fn get_format(&self) -> Result<OutputFormat, Error> {
+        let is_report = matches!(self.command, Command::Report(_) | Command::Directives(_) | Command::Soundness(_) |
+            Command::Verify(VerifyArgs { filter: false, .. }));
+        match (self.format, is_report) {
+            (None, false) => Ok(OutputFormat::Python),
+            (None, true) => Ok(OutputFormat::Table),
+            (Some(OutputFormat::Python), true) => bail!("--format python is only for rules; use table or json"),
+            (Some(OutputFormat::Table), false) => bail!("--format table is only for report, directives, soundness, and verify"),
+            (Some(format), _) => Ok(format),
         }
     }
-    else if args[1] == "typing" {
-        for rule in typing_rules {
-            println!("{}", rule.to_string());
+
+    fn get_translate_env(&self) -> TranslateEnv {
+        if self.dummy_env {
+            return TranslateEnv::Dummy;
         }
-    }
-    else if args[1] == "optlower" {
-        let prod_rules = learn_prods(MatchOption::All); // may contain duplicates
-        let prod_rules_set: HashSet<String> = HashSet::from_iter(prod_rules.iter().map(|x| x.to_string()));
-        for rule in prod_rules_set {
-            println!("{}", rule);
+        let mut option = WasmtimeEnvOption::default();
+        if self.dynamic_memory {
+            option.static_memory_maximum_size = 0;
         }
+        option.spectre_mitigation = !self.no_spectre_mitigation;
+        TranslateEnv::Wasmtime(option)
     }
-    else if args[1] == "opttyping" {
-        let prod_rules = learn_prods(MatchOption::Opt); // may contain duplicates
-        let mut prod_rules_set: HashSet<String> = HashSet::from_iter(prod_rules.iter().map(|x| x.to_string()));
-        prod_rules_set.extend(typing_rules.iter().map(|x| x.to_string()));
-        for rule in prod_rules_set {
-            println!("{}", rule);
+
+    // (match option, with typing rules) of the mode; None for typing rules only
+    fn get_mode(&self) -> Result<Option<(MatchOption, bool)>, Error> {
+        match self.command {
+            Command::Opt | Command::OptTyping | Command::Typing | Command::TestOpt | Command::TestLower if self.isa.is_some() => 
+                bail!("--isa requires a mode with lowering rules"),
+            Command::Report(ReportArgs { rules: ReportRules::Opt | ReportRules::TestOpt | ReportRules::TestLower }) |
+            Command::Directives(ReportArgs { rules: ReportRules::Opt | ReportRules::TestOpt | ReportRules::TestLower }) |
+            Command::ExportClif(ExportClifArgs { rules: ReportRules::Opt | ReportRules::TestOpt | ReportRules::TestLower, .. }) |
+            Command::ExportCorpus(ReportArgs { rules: ReportRules::Opt | ReportRules::TestOpt | ReportRules::TestLower }) |
+            Command::Verify(VerifyArgs { rules: ReportRules::Opt | ReportRules::TestOpt | ReportRules::TestLower, .. }) if self.isa.is_some() => 
+                bail!("--isa requires a mode with lowering rules"),
+            Command::Soundness(_) if self.isa.is_some() => 
+                bail!("--isa requires a mode with lowering rules"),
+            Command::X64(_) | Command::Aarch64(_) | Command::S390x(_) | Command::Riscv64(_) if self.isa.is_some() => 
+                bail!("--isa cannot be used with a per-ISA mode"),
+            _ => (),
         }
+
+        let mode = match self.command {
+            Command::All => Some((get_lower_option(self.isa, true), true)),
+            Command::Opt => Some((MatchOption::Opt, false)),
+            Command::Lower => Some((get_lower_option(self.isa, false), false)),
+            Command::TestOpt => Some((MatchOption::TestOpt, false)),
+            Command::TestLower => Some((MatchOption::TestLower, false)),
+            Command::Typing => None,
+            Command::OptLower => Some((get_lower_option(self.isa, true), false)),
+            Command::OptTyping => Some((MatchOption::Opt, true)),
+            Command::LowerTyping => Some((get_lower_option(self.isa, false), true)),
+            Command::X64(args) => Some((get_lower_option(Some(Isa::X64), args.with_opt), args.with_typing)),
+            Command::Aarch64(args) => Some((get_lower_option(Some(Isa::Aarch64), args.with_opt), args.with_typing)),
+            Command::S390x(args) => Some((get_lower_option(Some(Isa::S390x), args.with_opt), args.with_typing)),
+            Command::Riscv64(args) => Some((get_lower_option(Some(Isa::Riscv64), args.with_opt), args.with_typing)),
+            Command::Report(ReportArgs { rules }) | Command::Directives(ReportArgs { rules }) |
+            Command::ExportClif(ExportClifArgs { rules, .. }) | Command::ExportCorpus(ReportArgs { rules }) |
+            Command::Verify(VerifyArgs { rules, .. }) => {
+                let match_option = match rules {
+                    ReportRules::All => get_lower_option(self.isa, true),
+                    ReportRules::Opt => MatchOption::Opt,
+                    ReportRules::Lower => get_lower_option(self.isa, false),
+                    ReportRules::TestOpt => MatchOption::TestOpt,
+                    ReportRules::TestLower => MatchOption::TestLower,
+                };
+                Some((match_option, false))
+            },
+            Command::Soundness(SoundArgs { rules: SoundRules::Opt, .. }) => Some((MatchOption::Opt, false)),
+            Command::Soundness(SoundArgs { rules: SoundRules::TestOpt, .. }) => Some((MatchOption::TestOpt, false)),
+        };
+        Ok(mode)
     }
-    else if args[1] == "lowertyping" {
-        let prod_rules = learn_prods(MatchOption::Lower); // may contain duplicates
-        let mut prod_rules_set: HashSet<String> = HashSet::from_iter(prod_rules.iter().map(|x| x.to_string()));
-        prod_rules_set.extend(typing_rules.iter().map(|x| x.to_string()));
-        for rule in prod_rules_set {
-            println!("{}", rule);
-        }
+}
+
+fn write_output(cli: &Cli, write: impl FnOnce(&mut dyn Write) -> Result<(), Error>) -> Result<(), Error> {
+    match &cli.output {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+            let mut out = BufWriter::new(file);
+            write(&mut out)?;
+            out.flush()?;
+        },
+        None => write(&mut io::stdout().lock())?,
     }
-    else {
-        //
+    Ok(())
+}
+
+pub fn main() -> Result<(), Error> {
+    let cli = Cli::parse();
+    let format = cli.get_format()?;
+
+    let rules = match cli.get_mode()? {
+        Some((match_option, with_typing)) => {
+            let mut builder = ExtractConfig::builder()
+                .match_option(match_option)
+                .with_typing(with_typing)
+                .multi_value(cli.multi_value)
+                .strict_candidates(cli.strict_candidates)
+                .nan_canonicalization(!cli.no_nan_canonicalization)
+                .translate_env(cli.get_translate_env());
+            if let Some(path) = &cli.cranelift_src {
+                builder = builder.cranelift_src(path);
+            }
+            if let Command::Directives(_) = cli.command {
+                let directives = find_unknown_directives(match_option, builder.build()?.cranelift_src())?;
+                return write_output(&cli, |out| {
+                    match format {
+                        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&directives)?)?,
+                        _ => write!(out, "{}", directives_to_table(&directives))?,
+                    }
+                    Ok(())
+                });
+            }
+            if let Command::Soundness(args) = cli.command {
+                let option = SoundOption { inputs: args.inputs, seed: args.seed };
+                let outcomes = check_soundness(match_option, builder.build()?.cranelift_src(), &option);
+                return write_output(&cli, |out| {
+                    match format {
+                        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&outcomes)?)?,
+                        _ => write!(out, "{}", soundness_to_table(&outcomes))?,
+                    }
+                    Ok(())
+                });
+            }
+            if let Command::ExportClif(args) = cli.command {
+                let dir = cli.output.as_ref().context("export-clif requires --output <dir>")?;
+                let option = ClifOption { runs: args.runs, seed: args.seed };
+                let files = export_clif(match_option, builder.build()?.cranelift_src(), &option);
+                write_clif_files(&files, dir)?;
+                for file in &files {
+                    println!("{}", dir.join(&file.name).display());
+                }
+                return Ok(());
+            }
+            if let Command::Report(_) = cli.command {
+                // the report needs the whole pipeline, so the cache is not used
+                let diag = builder.build()?.report();
+                return write_output(&cli, |out| {
+                    match format {
+                        OutputFormat::Json => writeln!(out, "{}", diag.to_json()?)?,
+                        _ => write!(out, "{}", diag.to_table())?,
+                    }
+                    Ok(())
+                });
+            }
+            if !cli.no_cache {
+                builder = match &cli.cache_dir {
+                    Some(cache_dir) => builder.cache_dir(cache_dir),
+                    None => builder.default_cache(),
+                };
+            }
+            let config = builder.build()?;
+            if let Command::Verify(args) = cli.command {
+                if !cfg!(feature = "verify") {
+                    bail!("verify requires the extractor to be built with build-verify.sh");
+                }
+                let rules = config.extract()?;
+                let verdicts = config.verify(&rules)?;
+                if args.filter {
+                    return write_output(&cli, |out| write_rules(filter_dead_prods(rules, &verdicts), format, out));
+                }
+                let report = get_firing_report(&verdicts);
+                return write_output(&cli, |out| {
+                    match format {
+                        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&report)?)?,
+                        _ => write!(out, "{}", firing_report_to_table(&report))?,
+                    }
+                    Ok(())
+                });
+            }
+            config.extract()?
+        },
+        None => canonicalize_rules(get_all_typing_rule_prods()),
+    };
+
+    if let Command::ExportCorpus(_) = cli.command {
+        let dir = cli.output.as_ref().context("export-corpus requires --output <dir>")?;
+        let corpus = build_corpus(&rules)?;
+        write_corpus(&corpus, dir)?;
+        println!("{} modules ({} rules skipped), see {}",
+            corpus.manifest.modules.len(), corpus.manifest.skipped.len(), dir.join(MANIFEST_NAME).display());
+        return Ok(());
     }
-}
\ No newline at end of file
+
+    write_output(&cli, |out| write_rules(rules, format, out))
+}
@@ -1,9 +1,9 @@
 use std::{collections::HashMap, cell::RefCell, rc::Rc};
 
 use cranelift_codegen::ir::{Type, types};
-use wasm_ast::Instruction;
+use serde::{Deserialize, Serialize};
 
-use crate::wasm_map::IRData;
+use crate::wasm_map::{IRData, WasmCandidate};
 
 // Normalized expressions
 pub type NormVar = usize;
@@ -32,10 +32,44 @@ pub struct NormRule {
     pub rhs: NormExpr,
     pub bound_vars: Vec<NormExpr>,
     pub constraints: Vec<NormConstraint>,
+    pub source: RuleSource,
+}
+
+// Provenance of rules
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleRole {
+    Target, // simplify or lower rule that the production targets
+    Subst, // helper rule substituted in isle_subst
+    Learned, // learned (reversed) simplify rule used in rule_match
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct RuleSource {
+    pub role: RuleRole,
+    pub file: String, // relative to cranelift/codegen/src
+    pub line: usize,
+    pub name: String, // ISLE rules are unnamed in this version, so this is the root term of LHS
+    pub prio: i64,
+}
+
+impl RuleSource {
+    pub fn with_role(&self, role: RuleRole) -> Self {
+        let mut new_source = self.clone();
+        new_source.role = role;
+        new_source
+    }
+
+    // target rule at the line of the file, simplify for opts/ and lower otherwise
+    #[cfg(test)]
+    pub(crate) fn new_target(file: &str, line: usize) -> Self {
+        let name = if file.starts_with("opts/") { "simplify" } else { "lower" };
+        RuleSource { role: RuleRole::Target, file: String::from(file), line, name: String::from(name), prio: 0 }
+    }
 }
 
 // Linearized expressions
-#[derive(Clone, Debug, PartialEq, Copy, Hash, Eq)]
+#[derive(Clone, Debug, PartialEq, Copy, Hash, Eq, PartialOrd, Ord)]
 pub enum LinType {
     LHS,
     RHS,
@@ -259,6 +293,7 @@ pub struct LinRule {
     pub lhs: LinVec<LinExpr>,
     pub rhs: LinVec<LinExpr>,
     pub is_lower: bool,
+    pub sources: Vec<RuleSource>, // first one is the rule itself, followed by the applied rules
 }
 
 impl LinRule {
@@ -267,9 +302,15 @@ impl LinRule {
             lhs,
             rhs,
             is_lower,
+            sources: Vec::new(),
         }
     }
 
+    pub fn with_sources(mut self, sources: Vec<RuleSource>) -> Self {
+        self.sources = sources;
+        self
+    }
+
     pub fn deep_clone(&self) -> Self {
         let mut var_map = HashMap::new();
         let new_lhs = self.lhs.deep_clone_impl(LinType::LHS, &mut var_map);
@@ -278,6 +319,7 @@ impl LinRule {
             lhs: new_lhs,
             rhs: new_rhs,
             is_lower: self.is_lower,
+            sources: self.sources.clone(),
         }
     }
 }
@@ -294,7 +336,7 @@ impl LinResult {
         let mut var_map = HashMap::new();
         let new_lhs = self.rule.lhs.deep_clone_impl(LinType::LHS, &mut var_map);
         let new_rhs = self.rule.rhs.deep_clone_impl(LinType::RHS, &mut var_map);
-        let new_rule = LinRule::new(new_lhs, new_rhs, self.rule.is_lower);
+        let new_rule = LinRule::new(new_lhs, new_rhs, self.rule.is_lower).with_sources(self.rule.sources.clone());
         let new_cond_stmts = self.cond_stmts.deep_clone_impl(LinType::Cond, &mut var_map);
         let new_cond_pairs = self.cond_pairs.iter().map(
             |(x, y)| (
@@ -714,7 +756,7 @@ impl DeepClone for CondExpr {
 // Matched expressions
 #[derive(Clone, Debug, PartialEq)]
 pub enum MatchStmt {
-    Expr { data: IRData, instrs: Vec<Instruction> }, // for link info, refer to LinExpr
+    Expr { data: IRData, instrs: Vec<WasmCandidate> }, // for link info, refer to LinExpr
     Arg(Vec<Vec<CondExpr>>),
     Const(i128),
     Nil, // due to typevar and conds
@@ -744,6 +786,7 @@ pub struct MatchResult {
     pub stmts: Vec<MatchStmt>,
 
     pub from_learned: bool, // if this is derived from learned rules
+    pub sources: Vec<RuleSource>,
     reversed: bool, // if reversed, stmts refer to rhs / if not, lhs
 }
 
@@ -755,6 +798,7 @@ impl MatchResult {
             rhs: rule.rhs,
             stmts,
             from_learned: false,
+            sources: rule.sources,
             reversed: false,
         }
     }
@@ -766,6 +810,7 @@ impl MatchResult {
             rhs: rule.rhs,
             stmts,
             from_learned: false,
+            sources: rule.sources,
             reversed: false,
         }
     }
@@ -778,6 +823,7 @@ impl MatchResult {
             rhs: rule.rhs,
             stmts,
             from_learned: false,
+            sources: rule.sources,
             reversed: true,
         }
     }
@@ -800,10 +846,16 @@ impl MatchResult {
             rhs: new_rhs,
             stmts: new_stmts,
             from_learned: self.from_learned,
+            sources: self.sources.clone(),
             reversed: self.reversed,
         }
     }
 
+    // key for checking duplicates (provenance is not a part of the rule itself)
+    pub fn get_key(&self) -> String {
+        format!("{:?}", (&self.lhs, &self.rhs, &self.stmts, self.from_learned, self.reversed))
+    }
+
     pub fn is_reversed(&self) -> bool {
         self.reversed
     }
@@ -815,7 +867,7 @@ impl MatchResult {
     }
 
     pub fn get_pair(self) -> (LinRule, Vec<MatchStmt>) {
-        (LinRule { lhs: self.lhs, rhs: self.rhs, is_lower: false }, self.stmts) // default of is_lower is false
+        (LinRule { lhs: self.lhs, rhs: self.rhs, is_lower: false, sources: self.sources }, self.stmts) // default of is_lower is false
     }
 
     // get name from end of lhs (use with caution!)
@@ -1030,7 +1082,7 @@ impl MatchResult {
 
         let new_lhs_vec = LinVec::construct(new_lhs_store, new_lhs_idx);
         let new_rhs_vec = LinVec::construct(new_rhs_store, new_rhs_idx);
-        let new_rule = LinRule::new(new_lhs_vec, new_rhs_vec, false);
+        let new_rule = LinRule::new(new_lhs_vec, new_rhs_vec, false).with_sources(self.sources);
         
         let result = Self::new_rev(new_rule, new_stmts);
         result
@@ -1,26 +1,117 @@
+use anyhow::{anyhow, bail, Error};
 use itertools::Itertools;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use wasm_ast::{Instruction, ParametricInstruction, ResultType, ValueType};
 
-use crate::wasm_comp::get_instruction_name;
+use crate::{norm::{RuleRole, RuleSource}, wasm_comp::{get_instruction_from_name, get_instruction_name}};
 
-fn type_to_string(ty: &ValueType) -> String {
+// bump this whenever the JSON layout of the rules below changes
+pub const PROD_SCHEMA_VERSION: u32 = 7;
+
+fn type_to_name(ty: &ValueType) -> &'static str {
     match ty {
-        ValueType::I32 => String::from("\"i32\""),
-        ValueType::I64 => String::from("\"i64\""),
-        ValueType::F32 => String::from("\"f32\""),
-        ValueType::F64 => String::from("\"f64\""),
-        ValueType::V128 => String::from("\"v128\""),
-        ValueType::FunctionReference => String::from("\"funcref\""),
-        ValueType::ExternalReference => String::from("\"externref\""),
+        ValueType::I32 => "i32",
+        ValueType::I64 => "i64",
+        ValueType::F32 => "f32",
+        ValueType::F64 => "f64",
+        ValueType::V128 => "v128",
+        ValueType::FunctionReference => "funcref",
+        ValueType::ExternalReference => "externref",
+    }
+}
+
+fn name_to_type(name: &str) -> Option<ValueType> {
+    match name {
+        "i32" => Some(ValueType::I32),
+        "i64" => Some(ValueType::I64),
+        "f32" => Some(ValueType::F32),
+        "f64" => Some(ValueType::F64),
+        "v128" => Some(ValueType::V128),
+        "funcref" => Some(ValueType::FunctionReference),
+        "externref" => Some(ValueType::ExternalReference),
+        _ => None,
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+fn type_to_string(ty: &ValueType) -> String {
+    format!("\"{}\"", type_to_name(ty))
+}
+
+fn names_to_types(names: &[String]) -> Result<Vec<ValueType>, Error> {
+    names.iter().map(|x| name_to_type(x).ok_or(anyhow!("Unknown value type {}", x))).collect()
+}
+
+// ResultType is serialized as a list of type names, e.g. ["i32","f64"]
+mod result_type_serde {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(types: &ResultType, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(types.kinds().iter().map(type_to_name))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ResultType, D::Error> {
+        let names = Vec::<String>::deserialize(deserializer)?;
+        names_to_types(&names).map(|x| x.into()).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(into = "ProdTokenRepr", try_from = "ProdTokenRepr")]
 pub enum ProdToken {
     Instr(Instruction, Option<ProdOperand>),
     Arg(usize),
 }
 
+// wasm_ast instructions are not serializable, so tokens go through their names
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum ProdTokenRepr {
+    Instr {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        types: Option<Vec<String>>,
+        operand: Option<ProdOperand>,
+    },
+    Arg { idx: usize },
+}
+
+impl From<ProdToken> for ProdTokenRepr {
+    fn from(token: ProdToken) -> Self {
+        match token {
+            ProdToken::Instr(instr, operand) => {
+                let types = match &instr {
+                    Instruction::Parametric(ParametricInstruction::Select(Some(types))) => 
+                        Some(types.iter().map(|x| String::from(type_to_name(x))).collect()),
+                    _ => None,
+                };
+                ProdTokenRepr::Instr { name: get_instruction_name(&instr), types, operand }
+            },
+            ProdToken::Arg(idx) => ProdTokenRepr::Arg { idx },
+        }
+    }
+}
+
+impl TryFrom<ProdTokenRepr> for ProdToken {
+    type Error = Error;
+
+    fn try_from(repr: ProdTokenRepr) -> Result<Self, Self::Error> {
+        match repr {
+            ProdTokenRepr::Instr { name, types, operand } => {
+                let instr = match (name.as_str(), types) {
+                    ("select_t", Some(types)) => 
+                        Instruction::Parametric(ParametricInstruction::Select(Some(names_to_types(&types)?))),
+                    ("select_t", None) => bail!("select_t without types"),
+                    (_, Some(_)) => bail!("Unexpected types for instruction {}", name),
+                    (_, None) => get_instruction_from_name(&name)
+                        .ok_or(anyhow!("Unknown instruction {}", name))?,
+                };
+                Ok(ProdToken::Instr(instr, operand))
+            },
+            ProdTokenRepr::Arg { idx } => Ok(ProdToken::Arg(idx)),
+        }
+    }
+}
+
 impl ToString for ProdToken {
     fn to_string(&self) -> String {
         let mut result_str = String::new();
@@ -69,10 +160,51 @@ impl ToString for ProdToken {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(into = "ProdOperandRepr", try_from = "ProdOperandRepr")]
 pub enum ProdOperand {
     OpConst(i128),
     OpArg(usize),
+    OpMemArg(u32, u32), // (align, offset)
+    OpLabel(usize), // placeholder for labels chosen by the generator
+    OpIndex(ValueType), // local, global, table, or function of the type, chosen by the generator
+}
+
+// ValueType is not serializable, so index operands go through its type name, e.g. "i64"
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+enum ProdOperandRepr {
+    Const(i128),
+    Oparg(usize),
+    Memarg(u32, u32),
+    Label(usize),
+    Index(String),
+}
+
+impl From<ProdOperand> for ProdOperandRepr {
+    fn from(operand: ProdOperand) -> Self {
+        match operand {
+            ProdOperand::OpConst(val) => ProdOperandRepr::Const(val),
+            ProdOperand::OpArg(idx) => ProdOperandRepr::Oparg(idx),
+            ProdOperand::OpMemArg(align, offset) => ProdOperandRepr::Memarg(align, offset),
+            ProdOperand::OpLabel(idx) => ProdOperandRepr::Label(idx),
+            ProdOperand::OpIndex(ty) => ProdOperandRepr::Index(String::from(type_to_name(&ty))),
+        }
+    }
+}
+
+impl TryFrom<ProdOperandRepr> for ProdOperand {
+    type Error = Error;
+
+    fn try_from(repr: ProdOperandRepr) -> Result<Self, Self::Error> {
+        match repr {
+            ProdOperandRepr::Const(val) => Ok(ProdOperand::OpConst(val)),
+            ProdOperandRepr::Oparg(idx) => Ok(ProdOperand::OpArg(idx)),
+            ProdOperandRepr::Memarg(align, offset) => Ok(ProdOperand::OpMemArg(align, offset)),
+            ProdOperandRepr::Label(idx) => Ok(ProdOperand::OpLabel(idx)),
+            ProdOperandRepr::Index(name) => name_to_type(&name).map(ProdOperand::OpIndex).ok_or(anyhow!("Unknown value type {}", name)),
+        }
+    }
 }
 
 impl ToString for ProdOperand {
@@ -87,6 +219,27 @@ impl ToString for ProdOperand {
                 result_str += (*varid).to_string().as_str();
                 result_str += ")";
             },
+            ProdOperand::OpMemArg(align, offset) => {
+                result_str += "(";
+                result_str += (*align).to_string().as_str();
+                result_str += ",";
+                result_str += (*offset).to_string().as_str();
+                result_str += ")";
+            },
+            ProdOperand::OpLabel(idx) => {
+                result_str += "(";
+                result_str += "\"label\"";
+                result_str += ",";
+                result_str += (*idx).to_string().as_str();
+                result_str += ")";
+            },
+            ProdOperand::OpIndex(ty) => {
+                result_str += "(";
+                result_str += "\"index\"";
+                result_str += ",";
+                result_str += type_to_string(ty).as_str();
+                result_str += ")";
+            },
         }
         result_str
     }
@@ -95,7 +248,8 @@ impl ToString for ProdOperand {
 pub type ProdInstr = Vec<ProdToken>;
 
 // tree structure
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
 pub enum ProdCondExpr {
     Wildcard,
     Var(usize),
@@ -136,7 +290,7 @@ impl ToString for ProdCondExpr {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ProdCondition {
     pub arg: usize,
     pub conds: Vec<ProdCondExpr>,
@@ -155,22 +309,94 @@ impl ToString for ProdCondition {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+// how the instructions of a multi-value rule are run, so that its results cross a block or a call boundary
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProdWrap {
+    Block, // block of the multi-value type
+    Call, // call to a helper function of the multi-value signature
+}
+
+impl ProdWrap {
+    fn to_name(&self) -> &'static str {
+        match self {
+            ProdWrap::Block => "block",
+            ProdWrap::Call => "call",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ProdRule {
+    #[serde(with = "result_type_serde")]
     pub param_types: ResultType,
+    #[serde(with = "result_type_serde")]
     pub ret_types: ResultType,
     pub instrs: ProdInstr,
     pub arg_conds: Vec<ProdCondition>,
     pub oparg_conds: Vec<ProdCondition>,
+    #[serde(default)]
+    pub sources: Vec<RuleSource>, // ISLE rules that this rule is extracted from
+    #[serde(default = "default_weight")]
+    pub weight: f64, // lower for rules using less preferred WASM instructions (see WasmCandidate)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wrap: Option<ProdWrap>, // only for multi-value rules (see prod_multi)
 }
 
-impl ToString for ProdRule {
-    fn to_string(&self) -> String {
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn source_to_string(source: &RuleSource) -> String {
+    let role_str = match source.role {
+        RuleRole::Target => "target",
+        RuleRole::Subst => "subst",
+        RuleRole::Learned => "learned",
+    };
+    format!("(\"{}\",\"{}\",{},\"{}\",{})", role_str, source.file, source.line, source.name, source.prio)
+}
+
+impl ProdRule {
+    // rule without conditions, provenance, and wrap
+    #[cfg(test)]
+    pub(crate) fn new(param_types: Vec<ValueType>, ret_types: Vec<ValueType>, instrs: ProdInstr) -> Self {
+        ProdRule {
+            param_types: param_types.into(),
+            ret_types: ret_types.into(),
+            instrs,
+            arg_conds: Vec::new(),
+            oparg_conds: Vec::new(),
+            sources: Vec::new(),
+            weight: 1.0,
+            wrap: None,
+        }
+    }
+
+    // equality without provenance and weight
+    pub fn eq_rule(&self, other: &ProdRule) -> bool {
+        self.param_types == other.param_types &&
+        self.ret_types == other.ret_types &&
+        self.instrs == other.instrs &&
+        self.arg_conds == other.arg_conds &&
+        self.oparg_conds == other.oparg_conds &&
+        self.wrap == other.wrap
+    }
+
+    // conditions and sources are collected from hash maps, so sort them
+    pub fn canonicalize(&mut self) {
+        self.arg_conds.sort_by_key(|x| x.arg);
+        self.oparg_conds.sort_by_key(|x| x.arg);
+        self.sources.sort();
+        self.sources.dedup();
+    }
+
+    // key for sorting rules (provenance is not a part of the rule itself)
+    pub fn get_key(&self) -> String {
         let mut param_str = self.param_types.kinds().iter().map(type_to_string);
         let mut ret_str = self.ret_types.kinds().iter().map(type_to_string);
 
         let mut result_str = String::new();
-        result_str += "([";
+        result_str += "[";
         result_str += param_str.join(",").as_str();
         result_str += "],[";
         result_str += ret_str.join(",").as_str();
@@ -180,7 +406,157 @@ impl ToString for ProdRule {
         result_str += self.arg_conds.iter().map(|x| x.to_string()).join(",").as_str();
         result_str += "],[";
         result_str += self.oparg_conds.iter().map(|x| x.to_string()).join(",").as_str();
-        result_str += "])";
+        result_str += "]";
+        result_str
+    }
+}
+
+impl ToString for ProdRule {
+    fn to_string(&self) -> String {
+        let mut result_str = String::new();
+        result_str += "(";
+        result_str += self.get_key().as_str();
+        result_str += ",[";
+        result_str += self.sources.iter().map(source_to_string).join(",").as_str();
+        result_str += "],";
+        result_str += format!("{:?}", self.weight).as_str();
+        if let Some(wrap) = self.wrap {
+            result_str += ",\"";
+            result_str += wrap.to_name();
+            result_str += "\"";
+        }
+        result_str += ")";
         result_str
     }
-}
\ No newline at end of file
+}
+
+// sorts rules in a canonical order, merging the sources of equal rules
+pub fn canonicalize_rules(rules: Vec<ProdRule>) -> Vec<ProdRule> {
+    let mut keyed_rules = rules.into_iter().map(|mut x| {
+        x.canonicalize();
+        (x.get_key(), x)
+    }).collect::<Vec<_>>();
+    keyed_rules.sort_by(|(x, _), (y, _)| x.cmp(y));
+
+    let mut results: Vec<ProdRule> = Vec::new();
+    let mut run_start = 0; // start of rules in results with the same key
+    let mut prev_key: Option<String> = None;
+    for (key, rule) in keyed_rules {
+        if prev_key.as_ref() != Some(&key) {
+            run_start = results.len();
+            prev_key = Some(key);
+        }
+        match results[run_start..].iter_mut().find(|x| x.eq_rule(&rule)) {
+            Some(prev_rule) => {
+                prev_rule.weight = prev_rule.weight.max(rule.weight);
+                prev_rule.sources.extend(rule.sources);
+                prev_rule.canonicalize();
+            },
+            None => results.push(rule),
+        }
+    }
+    results
+}
+
+// versioned container for the JSON output
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProdRuleSet {
+    pub version: u32,
+    pub rules: Vec<ProdRule>,
+}
+
+impl ProdRuleSet {
+    pub fn new(rules: Vec<ProdRule>) -> Self {
+        ProdRuleSet { version: PROD_SCHEMA_VERSION, rules }
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(json_str: &str) -> Result<Self, Error> {
+        // check the version first so that an old file is not reported as a parse error
+        #[derive(Deserialize)]
+        struct VersionOnly { version: u32 }
+        let version = serde_json::from_str::<VersionOnly>(json_str)?.version;
+        if version != PROD_SCHEMA_VERSION {
+            bail!("Unsupported rule schema version {} (expected {})", version, PROD_SCHEMA_VERSION);
+        }
+        Ok(serde_json::from_str(json_str)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_ast::{ControlInstruction, MemoryArgument, MemoryInstruction, NumericInstruction, NumberType, VariableInstruction};
+
+    use super::*;
+
+    #[test]
+    fn test_prod_rule_json_roundtrip() {
+        let rule = ProdRule {
+            arg_conds: vec![ProdCondition {
+                arg: 0,
+                conds: vec![ProdCondExpr::Expr { 
+                    name: String::from("lt"), 
+                    params: vec![ProdCondExpr::Var(1), ProdCondExpr::Const(-1)],
+                }],
+            }],
+            sources: vec![RuleSource::new_target("opts/arithmetic.isle", 1)],
+            weight: 0.5,
+            ..ProdRule::new(vec![ValueType::I32, ValueType::I32], vec![ValueType::I32], vec![
+                ProdToken::Arg(0),
+                ProdToken::Arg(1),
+                ProdToken::Instr(NumericInstruction::Add(NumberType::I32).into(), None),
+                ProdToken::Instr(Instruction::Parametric(ParametricInstruction::Select(Some(vec![ValueType::F64]))), None),
+                ProdToken::Instr(MemoryInstruction::Load(NumberType::I32, MemoryArgument::default_offset(0)).into(), Some(ProdOperand::OpMemArg(0, 16))),
+                ProdToken::Instr(ControlInstruction::BranchIf(0).into(), Some(ProdOperand::OpLabel(0))),
+                ProdToken::Instr(VariableInstruction::GlobalSet(0).into(), Some(ProdOperand::OpIndex(ValueType::I64))),
+            ])
+        };
+        let rule_set = ProdRuleSet::new(vec![rule]);
+        let json_str = rule_set.to_json().unwrap();
+        println!("{}", json_str);
+        assert_eq!(ProdRuleSet::from_json(&json_str).unwrap(), rule_set);
+    }
+
+    #[test]
+    fn test_canonicalize_rules() {
+        let get_rule = |arg_conds: Vec<usize>, line: usize| ProdRule {
+            arg_conds: arg_conds.into_iter().map(|x| ProdCondition { arg: x, conds: vec![ProdCondExpr::Wildcard] }).collect(),
+            sources: vec![RuleSource::new_target("opts/arithmetic.isle", line)],
+            ..ProdRule::new(vec![ValueType::I64, ValueType::I64], vec![ValueType::I64], vec![
+                ProdToken::Arg(0),
+                ProdToken::Arg(1),
+                ProdToken::Instr(NumericInstruction::Add(NumberType::I64).into(), None),
+            ])
+        };
+        let rules = canonicalize_rules(vec![get_rule(vec![1, 0], 20), get_rule(vec![], 30), get_rule(vec![0, 1], 10)]);
+        assert_eq!(rules.len(), 2);
+        let merged_rule = rules.iter().find(|x| x.arg_conds.len() == 2).unwrap();
+        assert_eq!(merged_rule.sources.iter().map(|x| x.line).collect::<Vec<_>>(), vec![10, 20]);
+        assert_eq!(rules, canonicalize_rules(rules.iter().rev().cloned().collect()));
+    }
+
+    #[test]
+    fn test_canonicalize_rules_weight() {
+        let rule = ProdRule {
+            weight: 0.5,
+            ..ProdRule::new(vec![ValueType::I32], vec![ValueType::I32], vec![
+                ProdToken::Arg(0),
+                ProdToken::Instr(NumericInstruction::EqualToZero(wasm_ast::IntegerType::I32).into(), None),
+            ])
+        };
+        let preferred_rule = ProdRule { weight: 1.0, ..rule.clone() };
+        let rules = canonicalize_rules(vec![rule, preferred_rule]);
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].weight, 1.0);
+        assert!(rules[0].to_string().ends_with("],1.0)"));
+    }
+
+    #[test]
+    fn test_prod_rule_json_version_mismatch() {
+        let json_str = format!("{{\"version\":{},\"rules\":[]}}", PROD_SCHEMA_VERSION + 1);
+        assert!(ProdRuleSet::from_json(&json_str).is_err());
+    }
+}
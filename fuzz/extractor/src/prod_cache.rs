@@ -0,0 +1,121 @@
+// On-disk cache of learned production rules
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    env,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    process,
+};
+
+use anyhow::{Context, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::{isle::get_isle_files, prod::{ProdRule, PROD_SCHEMA_VERSION}, prod_extract::learn_prods, rule_match::MatchOption, wasm_map::WasmMapOption};
+
+// everything the learned rules depend on
+#[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
+struct CacheKey {
+    extractor_version: String,
+    extractor_build: u64, // hash of the extractor binary, so that rebuilding invalidates the cache
+    schema_version: u32,
+    match_option: String,
+    map_option: WasmMapOption,
+    isle_files: Vec<(String, u64)>, // (path, content hash)
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct CacheEntry {
+    key: CacheKey,
+    rules: Vec<ProdRule>,
+}
+
+fn get_hash<T: Hash>(val: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    val.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn default_cache_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("rule-cache")
+}
+
+fn get_cache_key(match_option: MatchOption, map_option: &WasmMapOption, src_root: &Path) -> Result<CacheKey, Error> {
+    let mut isle_files = Vec::new();
+    for parse_option in match_option.parse_options() {
+        for isle_file in get_isle_files(parse_option, src_root) {
+            let contents = fs::read(&isle_file).with_context(|| format!("Cannot read {}", isle_file.display()))?;
+            isle_files.push((isle_file.to_string_lossy().to_string(), get_hash(&contents)));
+        }
+    }
+    isle_files.sort();
+    isle_files.dedup();
+
+    Ok(CacheKey {
+        extractor_version: String::from(env!("CARGO_PKG_VERSION")),
+        extractor_build: get_hash(&fs::read(env::current_exe()?)?),
+        schema_version: PROD_SCHEMA_VERSION,
+        match_option: format!("{:?}", match_option),
+        map_option: map_option.clone(),
+        isle_files,
+    })
+}
+
+fn read_cache(cache_path: &Path, key: &CacheKey) -> Option<Vec<ProdRule>> {
+    let contents = fs::read_to_string(cache_path).ok()?;
+    let entry: CacheEntry = serde_json::from_str(&contents).ok()?;
+    // the file name is only a hash, so check the whole key
+    if &entry.key == key {
+        Some(entry.rules)
+    }
+    else {
+        None
+    }
+}
+
+fn write_cache(cache_path: &Path, entry: &CacheEntry) -> Result<(), Error> {
+    let cache_dir = cache_path.parent().unwrap();
+    fs::create_dir_all(cache_dir).with_context(|| format!("Cannot create {}", cache_dir.display()))?;
+
+    // write then rename, as several fuzzer processes may start at once
+    let temp_path = cache_path.with_extension(format!("tmp{}", process::id()));
+    fs::write(&temp_path, serde_json::to_string(entry)?)?;
+    fs::rename(&temp_path, cache_path)?;
+    Ok(())
+}
+
+// learn_prods, reusing the rules from the cache if the ISLE sources did not change
+pub fn learn_prods_cached(match_option: MatchOption, map_option: &WasmMapOption, src_root: &Path, cache_dir: &Path) -> Result<Vec<ProdRule>, Error> {
+    let key = get_cache_key(match_option, map_option, src_root)?;
+    let cache_path = cache_dir.join(format!("rules-{}-{:016x}.json", key.match_option.to_lowercase(), get_hash(&key)));
+    if let Some(rules) = read_cache(&cache_path, &key) {
+        return Ok(rules);
+    }
+
+    let rules = learn_prods(match_option, map_option, src_root);
+    let entry = CacheEntry { key, rules };
+    if let Err(error) = write_cache(&cache_path, &entry) {
+        eprintln!("WARNING: cannot write rule cache: {:?}", error); // the rules are still usable
+    }
+    Ok(entry.rules)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::isle::default_cranelift_src;
+
+    use super::*;
+
+    #[test]
+    fn test_cache_key_stable() {
+        let src_root = default_cranelift_src();
+        let map_option = WasmMapOption::default();
+        let key1 = get_cache_key(MatchOption::Opt, &map_option, &src_root).unwrap();
+        let key2 = get_cache_key(MatchOption::Opt, &map_option, &src_root).unwrap();
+        assert_eq!(key1, key2);
+        assert_ne!(key1, get_cache_key(MatchOption::X64, &map_option, &src_root).unwrap());
+        let strict_option = WasmMapOption { strict_candidates: true, ..Default::default() };
+        assert_ne!(key1, get_cache_key(MatchOption::Opt, &strict_option, &src_root).unwrap());
+    }
+}
@@ -0,0 +1,297 @@
+// Regression corpus: one WebAssembly module per production rule
+// Each production becomes the body of an exported `main` taking its param_types, in a module built
+// like wasm_comp::wrap_instruction_to_module (so it imports its memory as module.mem). Operands are
+// instantiated from the witness of the production conditions (prod_solve), and args that the
+// conditions fix to a constant are pushed as constants instead of read from the params. Multi-value
+// rules run in a block or a helper function, as the executor runs them (ProdWrap).
+
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Context, Error};
+use serde::{Deserialize, Serialize};
+use wasm_ast::{
+    emitter, BlockType, ControlInstruction, Export, ExportDescription, Expression, Function, FunctionType, Instruction,
+    MemoryArgument, MemoryInstruction, Module, Name, NumericInstruction, ValueType, VariableInstruction, VectorInstruction,
+};
+
+use crate::{
+    prod::{ProdOperand, ProdRule, ProdToken, ProdWrap, PROD_SCHEMA_VERSION},
+    prod_solve::{solve_prod, ProdSolution, ProdWitness},
+    wasm_comp::{get_base_global_types, get_base_module_builder, get_instruction_name, get_memory_argument},
+};
+
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CorpusModule {
+    pub file: String, // e.g., prod_00042.wasm
+    pub rule: ProdRule,
+    pub args: Vec<Option<i128>>, // args fixed to a constant by the conditions (see ProdWitness)
+    pub opargs: Vec<i128>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CorpusSkip {
+    pub rule: ProdRule,
+    pub reason: String,
+}
+
+// maps each module back to its rule
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CorpusManifest {
+    pub version: u32, // schema version of the rules
+    pub modules: Vec<CorpusModule>,
+    pub skipped: Vec<CorpusSkip>,
+}
+
+pub struct Corpus {
+    pub manifest: CorpusManifest,
+    pub binaries: Vec<Vec<u8>>, // of manifest.modules
+}
+
+fn get_value_constant(ty: ValueType, val: i128) -> Result<Instruction, String> {
+    let instr = match ty {
+        ValueType::I32 => NumericInstruction::I32Constant(val as i32).into(),
+        ValueType::I64 => NumericInstruction::I64Constant(val as i64).into(),
+        ValueType::F32 => NumericInstruction::F32Constant(f32::from_bits(val as u32)).into(),
+        ValueType::F64 => NumericInstruction::F64Constant(f64::from_bits(val as u64)).into(),
+        ValueType::V128 => Instruction::Vector(VectorInstruction::V128Constant(val)),
+        _ => return Err(format!("constant of {:?}", ty)),
+    };
+    Ok(instr)
+}
+
+// the instruction with the immediate; float constants are bits, and shuffle lanes are little-endian bytes
+fn with_immediate(instr: &Instruction, val: i128) -> Result<Instruction, String> {
+    let lane = val as u8;
+    let instr = match instr {
+        Instruction::Numeric(NumericInstruction::I32Constant(_)) => get_value_constant(ValueType::I32, val)?,
+        Instruction::Numeric(NumericInstruction::I64Constant(_)) => get_value_constant(ValueType::I64, val)?,
+        Instruction::Numeric(NumericInstruction::F32Constant(_)) => get_value_constant(ValueType::F32, val)?,
+        Instruction::Numeric(NumericInstruction::F64Constant(_)) => get_value_constant(ValueType::F64, val)?,
+        Instruction::Vector(i) => Instruction::Vector(match i {
+            VectorInstruction::V128Constant(_) => VectorInstruction::V128Constant(val),
+            VectorInstruction::I8X16Shuffle(_) => VectorInstruction::I8X16Shuffle(val.to_le_bytes()),
+            VectorInstruction::I8X16ExtractLane(sx, _) => VectorInstruction::I8X16ExtractLane(*sx, lane),
+            VectorInstruction::I16X8ExtractLane(sx, _) => VectorInstruction::I16X8ExtractLane(*sx, lane),
+            VectorInstruction::I32X4ExtractLane(_) => VectorInstruction::I32X4ExtractLane(lane),
+            VectorInstruction::I64X2ExtractLane(_) => VectorInstruction::I64X2ExtractLane(lane),
+            VectorInstruction::FExtractLane(shape, _) => VectorInstruction::FExtractLane(*shape, lane),
+            VectorInstruction::ReplaceLane(shape, _) => VectorInstruction::ReplaceLane(*shape, lane),
+            _ => return Err(format!("unexpected operand of {}", get_instruction_name(instr))),
+        }),
+        _ => return Err(format!("unexpected operand of {}", get_instruction_name(instr))),
+    };
+    Ok(instr)
+}
+
+fn with_memory_argument(instr: &Instruction, memarg: MemoryArgument) -> Result<Instruction, String> {
+    let instr = match instr {
+        Instruction::Memory(i) => match i {
+            MemoryInstruction::Load(ty, _) => MemoryInstruction::Load(*ty, memarg),
+            MemoryInstruction::Store(ty, _) => MemoryInstruction::Store(*ty, memarg),
+            MemoryInstruction::V128Load(_) => MemoryInstruction::V128Load(memarg),
+            MemoryInstruction::V128Store(_) => MemoryInstruction::V128Store(memarg),
+            MemoryInstruction::Load8(ty, sx, _) => MemoryInstruction::Load8(*ty, *sx, memarg),
+            MemoryInstruction::Load16(ty, sx, _) => MemoryInstruction::Load16(*ty, *sx, memarg),
+            MemoryInstruction::Load32(sx, _) => MemoryInstruction::Load32(*sx, memarg),
+            MemoryInstruction::Store8(ty, _) => MemoryInstruction::Store8(*ty, memarg),
+            MemoryInstruction::Store16(ty, _) => MemoryInstruction::Store16(*ty, memarg),
+            MemoryInstruction::Store32(_) => MemoryInstruction::Store32(memarg),
+            MemoryInstruction::V128Load8X8(sx, _) => MemoryInstruction::V128Load8X8(*sx, memarg),
+            MemoryInstruction::V128Load16X4(sx, _) => MemoryInstruction::V128Load16X4(*sx, memarg),
+            MemoryInstruction::V128Load32X2(sx, _) => MemoryInstruction::V128Load32X2(*sx, memarg),
+            MemoryInstruction::V128Load32Zero(_) => MemoryInstruction::V128Load32Zero(memarg),
+            MemoryInstruction::V128Load64Zero(_) => MemoryInstruction::V128Load64Zero(memarg),
+            MemoryInstruction::V128Load8Splat(_) => MemoryInstruction::V128Load8Splat(memarg),
+            MemoryInstruction::V128Load16Splat(_) => MemoryInstruction::V128Load16Splat(memarg),
+            MemoryInstruction::V128Load32Splat(_) => MemoryInstruction::V128Load32Splat(memarg),
+            MemoryInstruction::V128Load64Splat(_) => MemoryInstruction::V128Load64Splat(memarg),
+            _ => return Err(format!("unexpected memarg of {}", get_instruction_name(instr))),
+        }.into(),
+        _ => return Err(format!("unexpected memarg of {}", get_instruction_name(instr))),
+    };
+    Ok(instr)
+}
+
+// locals and globals of the type (locals after the params, one per type); tables and functions are the only ones
+fn with_index(instr: &Instruction, ty: ValueType, param_cnt: usize, locals: &mut Vec<ValueType>) -> Result<Instruction, String> {
+    let mut get_local = || {
+        let idx = locals.iter().position(|x| *x == ty).unwrap_or_else(|| {
+            locals.push(ty);
+            locals.len() - 1
+        });
+        (param_cnt + idx) as u32
+    };
+    let get_global = || get_base_global_types().iter().position(|x| *x == ty)
+        .map(|x| x as u32)
+        .ok_or(format!("no global of {:?}", ty));
+    let instr = match instr {
+        Instruction::Variable(VariableInstruction::LocalGet(_)) => VariableInstruction::LocalGet(get_local()).into(),
+        Instruction::Variable(VariableInstruction::LocalSet(_)) => VariableInstruction::LocalSet(get_local()).into(),
+        Instruction::Variable(VariableInstruction::LocalTee(_)) => VariableInstruction::LocalTee(get_local()).into(),
+        Instruction::Variable(VariableInstruction::GlobalGet(_)) => VariableInstruction::GlobalGet(get_global()?).into(),
+        Instruction::Variable(VariableInstruction::GlobalSet(_)) => VariableInstruction::GlobalSet(get_global()?).into(),
+        _ => instr.clone(), // table 0 and function 0
+    };
+    Ok(instr)
+}
+
+fn instantiate_instr(instr: &Instruction, operand: &Option<ProdOperand>, witness: &ProdWitness, param_cnt: usize, locals: &mut Vec<ValueType>) -> Result<Instruction, String> {
+    match operand {
+        None => Ok(instr.clone()),
+        Some(ProdOperand::OpLabel(_)) => Ok(instr.clone()), // label 0 targets the body of main, whose results are empty
+        Some(ProdOperand::OpIndex(ty)) => with_index(instr, *ty, param_cnt, locals),
+        Some(ProdOperand::OpMemArg(align, offset)) => with_memory_argument(instr, MemoryArgument::new(*align, *offset)),
+        Some(ProdOperand::OpConst(val)) => with_immediate(instr, *val),
+        Some(ProdOperand::OpArg(idx)) => match get_memory_argument(instr) {
+            Some(memarg) => with_memory_argument(instr, MemoryArgument::new(memarg.align(), witness.opargs[*idx] as u32)),
+            None => with_immediate(instr, witness.opargs[*idx]),
+        },
+    }
+}
+
+// instructions of the rule with its operands, and the locals they use
+fn instantiate_instrs(rule: &ProdRule, witness: &ProdWitness) -> Result<(Vec<Instruction>, Vec<ValueType>), String> {
+    let param_types = rule.param_types.kinds();
+    let mut locals = Vec::new();
+    let mut instrs = Vec::new();
+    for token in &rule.instrs {
+        let instr = match token {
+            ProdToken::Arg(idx) => match witness.args[*idx] {
+                Some(val) => get_value_constant(param_types[*idx], val)?,
+                None => VariableInstruction::LocalGet(*idx as u32).into(),
+            },
+            ProdToken::Instr(instr, operand) => instantiate_instr(instr, operand, witness, param_types.len(), &mut locals)?,
+        };
+        instrs.push(instr);
+    }
+    Ok((instrs, locals))
+}
+
+pub fn wrap_prod_to_module(rule: &ProdRule, witness: &ProdWitness) -> Result<Module, String> {
+    let (instrs, locals) = instantiate_instrs(rule, witness)?;
+    let mut module_builder = get_base_module_builder();
+    let func_type = FunctionType::new(rule.param_types.clone(), rule.ret_types.clone());
+    let type_idx = module_builder.add_function_type(func_type).unwrap();
+
+    let (main, helper) = match rule.wrap {
+        None => (Function::new(type_idx, locals.into(), Expression::new(instrs)), None),
+        Some(ProdWrap::Block) => {
+            let block_type = FunctionType::new(Vec::new().into(), rule.ret_types.clone());
+            let block_type_idx = module_builder.add_function_type(block_type).unwrap();
+            let block = ControlInstruction::Block(BlockType::Index(block_type_idx), Expression::new(instrs));
+            (Function::new(type_idx, locals.into(), Expression::new(vec![block.into()])), None)
+        },
+        Some(ProdWrap::Call) => {
+            // main is function 0 (referenced by the element segment), and the helper is function 1
+            let mut body = (0..rule.param_types.kinds().len())
+                .map(|x| VariableInstruction::LocalGet(x as u32).into())
+                .collect::<Vec<Instruction>>();
+            body.push(ControlInstruction::Call(1).into());
+            let helper = Function::new(type_idx, locals.into(), Expression::new(instrs));
+            (Function::new(type_idx, Vec::new().into(), Expression::new(body)), Some(helper))
+        },
+    };
+    let main_idx = module_builder.add_function(main).unwrap();
+    if let Some(helper) = helper {
+        module_builder.add_function(helper).unwrap();
+    }
+
+    module_builder.add_export(Export::new(Name::new("main".into()), ExportDescription::Function(main_idx)));
+    Ok(module_builder.build())
+}
+
+// the module of the rule, instantiated with a witness of its conditions; the reason to skip the rule otherwise
+pub(crate) fn build_prod_module(rule: &ProdRule) -> Result<(Module, ProdWitness), String> {
+    let witness = match solve_prod(rule) {
+        ProdSolution::Sat(x) => x,
+        ProdSolution::Unsat(reason) | ProdSolution::Unknown(reason) => return Err(reason),
+    };
+    Ok((wrap_prod_to_module(rule, &witness)?, witness))
+}
+
+pub(crate) fn emit_module(module: &Module) -> Result<Vec<u8>, Error> {
+    let mut binary = Vec::new();
+    emitter::emit_binary(module, &mut binary).map_err(|e| anyhow!("Cannot emit module: {}", e))?;
+    Ok(binary)
+}
+
+// rules should be in canonical order (see canonicalize_rules), so that the file names are stable
+pub fn build_corpus(rules: &[ProdRule]) -> Result<Corpus, Error> {
+    let mut manifest = CorpusManifest { version: PROD_SCHEMA_VERSION, modules: Vec::new(), skipped: Vec::new() };
+    let mut binaries = Vec::new();
+    for rule in rules {
+        let (module, witness) = match build_prod_module(rule) {
+            Ok(x) => x,
+            Err(reason) => {
+                manifest.skipped.push(CorpusSkip { rule: rule.clone(), reason });
+                continue;
+            },
+        };
+        let binary = emit_module(&module)?;
+        binaries.push(binary);
+        manifest.modules.push(CorpusModule {
+            file: format!("prod_{:05}.wasm", manifest.modules.len()),
+            rule: rule.clone(),
+            args: witness.args,
+            opargs: witness.opargs,
+        });
+    }
+    Ok(Corpus { manifest, binaries })
+}
+
+pub fn write_corpus(corpus: &Corpus, dir: &Path) -> Result<(), Error> {
+    fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    for (module, binary) in corpus.manifest.modules.iter().zip(&corpus.binaries) {
+        let path = dir.join(&module.file);
+        fs::write(&path, binary).with_context(|| format!("Cannot write {}", path.display()))?;
+    }
+    let path = dir.join(MANIFEST_NAME);
+    fs::write(&path, serde_json::to_string_pretty(&corpus.manifest)?).with_context(|| format!("Cannot write {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_ast::{IntegerType, NumberType};
+
+    use crate::prod::{ProdCondExpr, ProdCondition};
+
+    use super::*;
+
+    // (x << k) with 0 < k < 32, loading x from memory at offset 8
+    fn get_rule(wrap: Option<ProdWrap>) -> ProdRule {
+        ProdRule {
+            oparg_conds: vec![ProdCondition { arg: 0, conds: vec![ProdCondExpr::Expr { name: String::from("nonzero"), params: Vec::new() }] }],
+            wrap,
+            ..ProdRule::new(vec![ValueType::I32], vec![ValueType::I32], vec![
+                ProdToken::Arg(0),
+                ProdToken::Instr(MemoryInstruction::Load(NumberType::I32, MemoryArgument::default_offset(2)).into(), Some(ProdOperand::OpMemArg(2, 8))),
+                ProdToken::Instr(NumericInstruction::I32Constant(0).into(), Some(ProdOperand::OpArg(0))),
+                ProdToken::Instr(NumericInstruction::ShiftLeft(IntegerType::I32).into(), None),
+            ])
+        }
+    }
+
+    #[test]
+    fn test_build_corpus() {
+        let mut unsat = get_rule(None);
+        unsat.oparg_conds[0].conds.push(ProdCondExpr::Expr { name: String::from("lt"), params: vec![ProdCondExpr::Const(1)] });
+        let rules = vec![get_rule(None), get_rule(Some(ProdWrap::Call)), unsat];
+        let corpus = build_corpus(&rules).unwrap();
+        println!("{}", serde_json::to_string_pretty(&corpus.manifest).unwrap());
+        assert_eq!(corpus.manifest.modules.len(), 2);
+        assert_eq!(corpus.manifest.skipped.len(), 1);
+        assert_eq!(corpus.manifest.modules[1].file, "prod_00001.wasm");
+        assert_ne!(corpus.manifest.modules[0].opargs[0], 0);
+        assert!(corpus.binaries.iter().all(|x| x.starts_with(b"\0asm")));
+
+        let witness = ProdWitness { args: vec![None], opargs: vec![3] };
+        let module = wrap_prod_to_module(&rules[1], &witness).unwrap();
+        assert_eq!(module.functions().unwrap().len(), 2); // main and its helper
+        let helper = &module.functions().unwrap()[1];
+        assert_eq!(helper.body().instructions()[1], MemoryInstruction::Load(NumberType::I32, MemoryArgument::new(2, 8)).into());
+        assert_eq!(helper.body().instructions()[2], NumericInstruction::I32Constant(3).into());
+    }
+}
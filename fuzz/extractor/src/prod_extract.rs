@@ -1,9 +1,9 @@
-use std::{collections::HashMap, usize};
+use std::{collections::HashMap, path::Path, usize};
 
-use cranelift_codegen::ir::Opcode;
-use wasm_ast::{Instruction, ValueType};
+use cranelift_codegen::ir::{types, Opcode, Type};
+use wasm_ast::{ControlInstruction, Instruction, ValueType};
 
-use crate::{norm::{CondExpr, MatchResult, MatchStmt, UnifiedExprIdx, UnifiedStmt}, prod::{ProdCondExpr, ProdCondition, ProdOperand, ProdRule, ProdToken}, rule_match::{match_and_learn, MatchOption}, wasm_comp::{get_dummy_module, get_instruction_type, InstructionType}};
+use crate::{diagnostics::{is_target_source, DiagStage, Diagnostics}, norm::{CondExpr, MatchResult, MatchStmt, UnifiedExprIdx, UnifiedStmt}, prod::{canonicalize_rules, ProdCondExpr, ProdCondition, ProdOperand, ProdRule, ProdToken}, prod_solve::{solve_prod, ProdSolution}, rule_match::{match_and_learn_diag, MatchOption}, wasm_comp::{get_dummy_module, get_entity_type, get_instruction_type, get_memory_argument, InstructionType}, wasm_map::{IRData, WasmMapOption}};
 
 #[derive(Clone, Debug, PartialEq)]
 struct ExtractEnv {
@@ -178,28 +178,55 @@ impl ExtractEnv {
     }
 }
 
-pub fn learn_prods(match_option: MatchOption) -> Vec<ProdRule> {
-    let match_results = match_and_learn(match_option);
+pub fn learn_prods(match_option: MatchOption, map_option: &WasmMapOption, src_root: &Path) -> Vec<ProdRule> {
+    learn_prods_diag(match_option, map_option, src_root, &mut Diagnostics::disabled())
+}
+
+pub fn learn_prods_diag(match_option: MatchOption, map_option: &WasmMapOption, src_root: &Path, diag: &mut Diagnostics) -> Vec<ProdRule> {
+    let match_results = match_and_learn_diag(match_option, map_option, src_root, diag);
     let mut prod_rules = Vec::new();
     for match_result in match_results {
         assert!(!match_result.is_reversed());
         let mut extracted_rules = extract_prod_from_match_result(match_result);
         prod_rules.append(&mut extracted_rules);
     }
-    prod_rules
+    let prod_rules = canonicalize_rules(prod_rules);
+
+    // drop productions whose conditions no operand can satisfy
+    let mut sat_rules = Vec::new();
+    for prod_rule in prod_rules {
+        let solution = solve_prod(&prod_rule);
+        for source in prod_rule.sources.iter().filter(|x| is_target_source(x)) {
+            diag.add_survivors(source, DiagStage::Extract, 1);
+            match &solution {
+                ProdSolution::Unsat(reason) => diag.add_drop(source, DiagStage::Solve, reason.clone()),
+                _ => diag.add_survivors(source, DiagStage::Solve, 1),
+            }
+        }
+        if !solution.is_unsat() {
+            sat_rules.push(prod_rule);
+        }
+    }
+
+    // count after merging, so that each production is counted once per rule
+    for prod_rule in &sat_rules {
+        for source in prod_rule.sources.iter().filter(|x| is_target_source(x)) {
+            diag.add_productions(source, 1);
+        }
+    }
+    sat_rules
 }
 
 fn extract_prod_from_match_result(match_result: MatchResult) -> Vec<ProdRule> {
     let env = ExtractEnv::new();
     let ret_type = match match_result.stmts.last().unwrap() {
-        MatchStmt::Expr { data: _, instrs } => {
-            let instr_type = get_instruction_type_dummy(&instrs[0]);
+        MatchStmt::Expr { data, instrs } => {
+            let instr_type = get_instruction_type_for_ir(&instrs[0].instr, data);
             let ret_types = instr_type.ret_types;
-            if ret_types.is_empty() {
-                None
-            } else {
-                assert!(ret_types.len() == 1);
-                Some(ret_types[0])
+            match ret_types.len() {
+                0 => None, // no-result root (e.g., stores), extracted with empty ret_types
+                1 => Some(ret_types[0]),
+                _ => { return Vec::new(); },
             }
         },
         _ => unreachable!(),
@@ -217,6 +244,7 @@ fn extract_prod_from_match_result(match_result: MatchResult) -> Vec<ProdRule> {
         let mut new_rule = rule;
         new_rule.arg_conds = env.process_arg_conds();
         new_rule.oparg_conds = env.process_oparg_conds();
+        new_rule.sources = match_result.sources.clone();
         result_rules.push(new_rule);
     }
     result_rules
@@ -241,8 +269,10 @@ fn extract_prod_rule(
                 data.param_types == vec![cranelift_codegen::ir::types::I8]
             };
 
-            for instr in instrs {
-                let instr_type = get_instruction_type_dummy(instr);
+            for candidate in instrs {
+                let instr = &candidate.instr;
+                let instr_type = get_instruction_type_for_ir(instr, data);
+                let entity_type = get_entity_type(&get_dummy_module(), 0, instr);
                 let param_types = instr_type.param_types;
                 let ret_types = instr_type.ret_types;
                 let params = match lhs_stmt {
@@ -260,17 +290,22 @@ fn extract_prod_rule(
                 };
                 
                 // checks: instruction validity
-                if instr_type.has_operand != data.operand_type.is_some() {
+                // branches take labels (or arms) instead of the operand of the clif branch
+                // entity operands (e.g., global index) are not IR operands, offsets of their accesses are fixed
+                let is_branch = data.branch_dests > 0;
+                if !is_branch && entity_type.is_none() && instr_type.has_operand != data.operand_type.is_some() {
                     // println!("ERROR: Instruction invalid: operand type mismatch");
                     continue;
                 }
                 let has_cc = data.intcc.is_some() || data.floatcc.is_some();
-                if param_types.len() != data.param_types.len()  {
+                let has_flags = data.memflags.is_some();
+                let has_trap_code = data.trap_code.is_some();
+                if param_types.len() != data.param_types.len() - data.env_params.len() {
                     // println!("ERROR: Instruction invalid: parameter type length mismatch");
                     continue;
                 }
                 // first one is typevar
-                if params.len() != data.param_types.len() + (instr_type.has_operand as usize) + (has_cc as usize) + 1 {
+                if params.len() != data.param_types.len() + (data.operand_type.is_some() as usize) + (has_cc as usize) + (has_flags as usize) + (has_trap_code as usize) + data.branch_dests + 1 {
                     // println!("ERROR: Instruction invalid: parameter length mismatch");
                     continue;
                 }
@@ -298,13 +333,37 @@ fn extract_prod_rule(
                         _ => { continue; },
                     }
                 }
+
+                // skip flags if exists (wasm accesses always use the same flags)
+                if has_flags {
+                    let _ = params_iter.next();
+                }
                 
                 let mut params_vec = params_iter.collect::<Vec<_>>();
 
+                // skip trap code if exists (fixed by the translation, at last position)
+                if has_trap_code {
+                    let _ = params_vec.pop();
+                }
+
+                // skip branch destinations (at last positions)
+                params_vec.truncate(params_vec.len() - data.branch_dests);
+
                 // operand (at last position)
-                let operand = if instr_type.has_operand {
+                let operand = if is_branch {
+                    get_branch_operand(instr)
+                } else if let Some(ty) = entity_type {
+                    if data.operand_type.is_some() {
+                        let _ = params_vec.pop();
+                    }
+                    Some(ProdOperand::OpIndex(ty))
+                } else if instr_type.has_operand {
                     let operand_expr_idx = params_vec.pop().unwrap();
-                    match extract_prod_operand(&mut new_env, match_result, operand_expr_idx, ret_type) {
+                    let operand = match get_memory_argument(instr) {
+                        Some(memarg) => extract_prod_memarg(&mut new_env, match_result, operand_expr_idx, memarg.align()),
+                        None => extract_prod_operand(&mut new_env, match_result, operand_expr_idx, ret_type),
+                    };
+                    match operand {
                         val @ Some(_) => val,
                         None => { continue; },
                     }
@@ -312,6 +371,14 @@ fn extract_prod_rule(
                     None
                 };
 
+                // env params (e.g., global addresses) come from vmctx, so rules cannot choose them
+                if data.env_params.iter().any(|idx| matches!(match_result.stmts[params_vec[*idx]], MatchStmt::Expr { .. })) {
+                    continue;
+                }
+                for idx in data.env_params.iter().rev() {
+                    params_vec.remove(*idx);
+                }
+
                 // prarameters into ProdRules
                 let mut param_prod_rules = vec![(
                     new_env.clone(),
@@ -321,6 +388,9 @@ fn extract_prod_rule(
                         instrs: Vec::new(),
                         arg_conds: Vec::new(),
                         oparg_conds: Vec::new(),
+                        sources: Vec::new(),
+                        weight: 1.0,
+                        wrap: None,
                     }
                 )];
 
@@ -332,6 +402,11 @@ fn extract_prod_rule(
                         params_vec.push(control_param);
                         params_vec.into_iter()
                     }
+                    else if data.opcode.can_store() && has_flags && params_vec.len() == 2 {
+                        // stored value goes after the address, different from clif stores
+                        params_vec.swap(0, 1);
+                        params_vec.into_iter()
+                    }
                     else {
                         params_vec.into_iter()
                     };
@@ -363,6 +438,9 @@ fn extract_prod_rule(
                                     },
                                     arg_conds: Vec::new(), // later added
                                     oparg_conds: Vec::new(), // later added
+                                    sources: Vec::new(), // later added
+                                    weight: rule.weight * filter_rule.weight,
+                                    wrap: None,
                                 };
                                 Some((filter_env, new_rule))
                             }
@@ -388,6 +466,9 @@ fn extract_prod_rule(
                         },
                         arg_conds: Vec::new(), // later added
                         oparg_conds: Vec::new(), // later added
+                        sources: Vec::new(), // later added
+                        weight: if ignore_this_instr { rule.weight } else { rule.weight * candidate.get_weight() },
+                        wrap: None,
                     }));
                 }
             }
@@ -408,6 +489,9 @@ fn extract_prod_rule(
                         instrs: vec![ProdToken::Arg(local_idx)],
                         arg_conds: Vec::new(), // later added
                         oparg_conds: Vec::new(), // later added
+                        sources: Vec::new(), // later added
+                        weight: 1.0,
+                        wrap: None,
                     };
                     vec![(new_env, new_rule)]
                 },
@@ -446,24 +530,79 @@ fn extract_prod_operand(env: &mut ExtractEnv, match_result: &MatchResult, cur_id
     }
 }
 
-fn get_instruction_type_dummy(instr: &Instruction) -> InstructionType {
+// memarg of a load/store; the offset operand becomes the static offset
+fn extract_prod_memarg(env: &mut ExtractEnv, match_result: &MatchResult, cur_idx: usize, align: u32) -> Option<ProdOperand> {
+    let match_stmt = &match_result.stmts[cur_idx];
+    match match_stmt {
+        MatchStmt::Arg(conds) => {
+            let new_arg = env.get_or_add_oparg(cur_idx, conds);
+            Some(ProdOperand::OpArg(new_arg))
+        },
+        MatchStmt::Const(val) => {
+            // Offset32 is signed, while wasm offsets are unsigned
+            if *val < 0 || *val > i32::MAX as i128 {
+                None
+            }
+            else {
+                Some(ProdOperand::OpMemArg(align, *val as u32))
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+// labels of br_if/br_table are chosen by the generator, as are the arms and block type of if
+fn get_branch_operand(instr: &Instruction) -> Option<ProdOperand> {
+    match instr {
+        Instruction::Control(ControlInstruction::If(..)) => None,
+        _ => Some(ProdOperand::OpLabel(0)),
+    }
+}
+
+// whether a WASM value is translated to a value of the IR type (funcref is a pointer in wasmtime)
+fn is_ir_type_of(wasm_ty: ValueType, ir_ty: Type) -> bool {
+    match wasm_ty {
+        ValueType::I32 => ir_ty == types::I32,
+        ValueType::I64 | ValueType::FunctionReference => ir_ty == types::I64,
+        ValueType::F32 => ir_ty == types::F32,
+        ValueType::F64 => ir_ty == types::F64,
+        ValueType::V128 => ir_ty.is_vector() && ir_ty.bits() == 128,
+        ValueType::ExternalReference => ir_ty == types::R64,
+    }
+}
+
+// type of the instruction whose params are the (non-env) params of the IR, e.g., ref.is_null of externref for is_null.r64
+fn get_instruction_type_for_ir(instr: &Instruction, data: &IRData) -> InstructionType {
     let dummy_module = get_dummy_module();
-    let instr_type = &get_instruction_type(&dummy_module, 0, instr).unwrap()[0];
-    instr_type.clone()
+    let instr_types = get_instruction_type(&dummy_module, 0, instr).unwrap();
+    let mut ir_param_types = data.param_types.iter().enumerate()
+        .filter(|(idx, _)| !data.env_params.contains(idx))
+        .map(|(_, ty)| *ty)
+        .collect::<Vec<_>>();
+    if data.opcode.can_store() && data.memflags.is_some() && ir_param_types.len() == 2 {
+        ir_param_types.swap(0, 1);
+    }
+    let matching_type = instr_types.iter().find(|x| {
+        x.param_types.len() == ir_param_types.len() &&
+        x.param_types.iter().zip(ir_param_types.iter()).all(|(wasm_ty, ir_ty)| is_ir_type_of(*wasm_ty, *ir_ty))
+    });
+    matching_type.unwrap_or(&instr_types[0]).clone()
 }
 
 #[cfg(test)]
 mod test {
+    use crate::isle::default_cranelift_src;
+
     use super::*;
 
     #[test]
     fn test_learn_prods() {
-        println!("{:#?}", learn_prods(MatchOption::All));
+        println!("{:#?}", learn_prods(MatchOption::All, &WasmMapOption::default(), &default_cranelift_src()));
     }
 
     #[test]
     fn test_learn_prods_str() {
-        let prods = learn_prods(MatchOption::All);
+        let prods = learn_prods(MatchOption::All, &WasmMapOption::default(), &default_cranelift_src());
         for prod in prods {
             println!("{:?}", prod.to_string());
         }
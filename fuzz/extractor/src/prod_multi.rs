@@ -0,0 +1,149 @@
+// Step 4.1: Multi-value productions
+// Each single-result production is paired with the next production of the same target ISLE rule
+// (or with itself if it is the only one). The pair leaves both results on the stack, and the
+// executor runs it in a block or a helper function of the multi-value type (ProdWrap), so that
+// the results become block parameters or multiple returns in CLIF.
+
+use std::collections::BTreeMap;
+
+use wasm_ast::Instruction;
+
+use crate::{diagnostics::is_target_source, prod::{ProdCondExpr, ProdCondition, ProdOperand, ProdRule, ProdToken, ProdWrap}};
+
+// number of opargs, including the ones only used in conditions (e.g., pow2)
+pub(crate) fn get_oparg_cnt(rule: &ProdRule) -> usize {
+    fn get_max_var(expr: &ProdCondExpr) -> Option<usize> {
+        match expr {
+            ProdCondExpr::Var(idx) => Some(*idx),
+            ProdCondExpr::Expr { name: _, params } => params.iter().filter_map(get_max_var).max(),
+            _ => None,
+        }
+    }
+    let instr_opargs = rule.instrs.iter().filter_map(|x| match x {
+        ProdToken::Instr(_, Some(ProdOperand::OpArg(idx))) => Some(*idx),
+        _ => None,
+    });
+    let cond_opargs = rule.oparg_conds.iter().flat_map(|x| {
+        std::iter::once(x.arg).chain(x.conds.iter().filter_map(get_max_var))
+    });
+    instr_opargs.chain(cond_opargs).max().map_or(0, |x| x + 1)
+}
+
+fn shift_cond_expr(expr: &ProdCondExpr, offset: usize) -> ProdCondExpr {
+    match expr {
+        ProdCondExpr::Var(idx) => ProdCondExpr::Var(idx + offset),
+        ProdCondExpr::Expr { name, params } => ProdCondExpr::Expr {
+            name: name.clone(),
+            params: params.iter().map(|x| shift_cond_expr(x, offset)).collect(),
+        },
+        _ => expr.clone(),
+    }
+}
+
+fn shift_conds(conds: &[ProdCondition], offset: usize) -> Vec<ProdCondition> {
+    conds.iter().map(|x| ProdCondition {
+        arg: x.arg + offset,
+        conds: x.conds.iter().map(|y| shift_cond_expr(y, offset)).collect(),
+    }).collect()
+}
+
+// instructions of first, then the ones of second with its args and opargs numbered after the ones of first
+pub fn concat_prods(first: &ProdRule, second: &ProdRule, wrap: ProdWrap) -> ProdRule {
+    let arg_offset = first.param_types.kinds().len();
+    let oparg_offset = get_oparg_cnt(first);
+    let second_instrs = second.instrs.iter().map(|x| match x {
+        ProdToken::Arg(idx) => ProdToken::Arg(idx + arg_offset),
+        ProdToken::Instr(instr, Some(ProdOperand::OpArg(idx))) => ProdToken::Instr(instr.clone(), Some(ProdOperand::OpArg(idx + oparg_offset))),
+        _ => x.clone(),
+    });
+
+    let mut rule = ProdRule {
+        param_types: first.param_types.kinds().iter().chain(second.param_types.kinds()).cloned().collect::<Vec<_>>().into(),
+        ret_types: first.ret_types.kinds().iter().chain(second.ret_types.kinds()).cloned().collect::<Vec<_>>().into(),
+        instrs: first.instrs.iter().cloned().chain(second_instrs).collect(),
+        arg_conds: first.arg_conds.iter().cloned().chain(shift_conds(&second.arg_conds, arg_offset)).collect(),
+        oparg_conds: first.oparg_conds.iter().cloned().chain(shift_conds(&second.oparg_conds, oparg_offset)).collect(),
+        sources: first.sources.iter().chain(second.sources.iter()).cloned().collect(),
+        weight: first.weight * second.weight,
+        wrap: Some(wrap),
+    };
+    rule.canonicalize();
+    rule
+}
+
+// branch rules compute the condition of a branch chosen by the generator, so they are not paired
+fn is_single_result_prod(rule: &ProdRule) -> bool {
+    rule.wrap.is_none() &&
+    rule.ret_types.kinds().len() == 1 &&
+    !matches!(rule.instrs.last(), Some(ProdToken::Instr(Instruction::Control(_), _)))
+}
+
+// rules should be in canonical order (see canonicalize_rules), so that the pairs are stable
+pub fn get_multi_value_prods(rules: &[ProdRule]) -> Vec<ProdRule> {
+    let mut target_groups: BTreeMap<(&str, usize), Vec<&ProdRule>> = BTreeMap::new();
+    for rule in rules.iter().filter(|x| is_single_result_prod(x)) {
+        if let Some(target) = rule.sources.iter().find(|x| is_target_source(x)) {
+            target_groups.entry((target.file.as_str(), target.line)).or_default().push(rule);
+        }
+    }
+
+    let mut multi_rules = Vec::new();
+    for group in target_groups.values() {
+        for (idx, first) in group.iter().enumerate() {
+            let second = group[(idx + 1) % group.len()];
+            for wrap in [ProdWrap::Block, ProdWrap::Call] {
+                multi_rules.push(concat_prods(first, second, wrap));
+            }
+        }
+    }
+    multi_rules
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_ast::{MemoryArgument, MemoryInstruction, NumberType, NumericInstruction, ValueType};
+
+    use crate::norm::RuleSource;
+
+    use super::*;
+
+    #[test]
+    fn test_concat_prods() {
+        let add = ProdRule {
+            arg_conds: vec![ProdCondition { arg: 1, conds: vec![ProdCondExpr::Var(0)] }],
+            sources: vec![RuleSource::new_target("opts/arithmetic.isle", 10)],
+            weight: 0.5,
+            ..ProdRule::new(vec![ValueType::I32, ValueType::I32], vec![ValueType::I32], vec![
+                ProdToken::Arg(0),
+                ProdToken::Arg(1),
+                ProdToken::Instr(NumericInstruction::Add(NumberType::I32).into(), None),
+            ])
+        };
+        let load = ProdRule {
+            arg_conds: vec![ProdCondition { arg: 0, conds: vec![ProdCondExpr::Wildcard] }],
+            oparg_conds: vec![ProdCondition { arg: 0, conds: vec![ProdCondExpr::Var(1)] }],
+            sources: vec![RuleSource::new_target("opts/arithmetic.isle", 20)],
+            ..ProdRule::new(vec![ValueType::I32], vec![ValueType::F64], vec![
+                ProdToken::Arg(0),
+                ProdToken::Instr(MemoryInstruction::Load(NumberType::F64, MemoryArgument::default_offset(3)).into(), Some(ProdOperand::OpArg(0))),
+            ])
+        };
+        let rule = concat_prods(&add, &load, ProdWrap::Block);
+        println!("{}", rule.to_string());
+        assert_eq!(rule.param_types.kinds(), &[ValueType::I32, ValueType::I32, ValueType::I32]);
+        assert_eq!(rule.ret_types.kinds(), &[ValueType::I32, ValueType::F64]);
+        assert_eq!(rule.instrs[3], ProdToken::Arg(2));
+        assert_eq!(rule.arg_conds[1].arg, 2);
+        assert_eq!(rule.weight, 0.5);
+        assert!(rule.to_string().ends_with(",0.5,\"block\")"));
+
+        // opargs of the second rule go after the ones of the first, including those only in conditions
+        let rule = concat_prods(&load, &load, ProdWrap::Call);
+        assert_eq!(rule.instrs[3], ProdToken::Instr(MemoryInstruction::Load(NumberType::F64, MemoryArgument::default_offset(3)).into(), Some(ProdOperand::OpArg(2))));
+        assert_eq!(rule.oparg_conds[1], ProdCondition { arg: 2, conds: vec![ProdCondExpr::Var(3)] });
+
+        let multi_rules = get_multi_value_prods(&[add, load]);
+        assert_eq!(multi_rules.len(), 4);
+        assert!(multi_rules.iter().all(|x| x.ret_types.kinds().len() == 2 && x.sources.len() == 1));
+    }
+}
@@ -0,0 +1,485 @@
+// Step 4.2: Solving production conditions
+// Conditions are kept symbolic in ProdRule (e.g., nonzero, lt(var1), _pow2(var2)), so this finds
+// concrete values that satisfy them: a value for every oparg, and a value for every arg that the
+// conditions fix to a constant. Other args are runtime values and their conditions are not solved.
+// Values are unsigned in the width of the operand (or arg), as ISLE compares immediates as u64.
+
+use wasm_ast::{Instruction, ValueType};
+
+use crate::{prod::{ProdCondExpr, ProdCondition, ProdOperand, ProdRule, ProdToken}, prod_multi::get_oparg_cnt, wasm_comp::{get_instruction_name, get_memory_argument}};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProdWitness {
+    pub args: Vec<Option<i128>>, // value of each arg, if its conditions fix it to a constant
+    pub opargs: Vec<i128>, // value of each oparg
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProdSolution {
+    Sat(ProdWitness),
+    Unsat(String), // no values satisfy the conditions
+    Unknown(String), // conditions that the solver does not understand, or no witness found
+}
+
+impl ProdSolution {
+    pub fn is_unsat(&self) -> bool {
+        matches!(self, ProdSolution::Unsat(_))
+    }
+}
+
+enum SolveError {
+    Unsat(String),
+    Unknown(String),
+}
+
+// value of a var, and whether the conditions force it (rather than the solver choosing it)
+#[derive(Clone, Copy, Debug)]
+struct SolvedValue {
+    val: u128,
+    forced: bool,
+}
+
+// candidates are enumerated exhaustively for ranges smaller than this
+const SMALL_RANGE: u128 = 16;
+
+pub(crate) fn get_mask(width: u32) -> u128 {
+    if width >= 128 { u128::MAX } else { (1 << width) - 1 }
+}
+
+fn get_type_width(ty: &ValueType) -> u32 {
+    match ty {
+        ValueType::I32 | ValueType::F32 => 32,
+        ValueType::V128 => 128,
+        _ => 64,
+    }
+}
+
+// width of the operand of the instruction (offset for memargs)
+fn get_operand_width(instr: &Instruction) -> u32 {
+    let name = get_instruction_name(instr);
+    if name == "v128.const" || name == "i8x16.shuffle" {
+        128
+    }
+    else if name.contains("_lane") {
+        8
+    }
+    else if name.starts_with("i32.") || name.starts_with("f32.") || get_memory_argument(instr).is_some() {
+        32
+    }
+    else {
+        64
+    }
+}
+
+// predicates on the var itself
+pub(crate) fn check_predicate(name: &str, x: u128, width: u32) -> Option<bool> {
+    let low32 = get_mask(32);
+    match name {
+        "is_odd" => Some(x & 1 == 1),
+        "is_even" => Some(x & 1 == 0),
+        "nonzero" | "_nonzero" => Some(x != 0),
+        "_nonminusone" => Some(x != get_mask(width)),
+        "_nonnegative" => Some(x < (1 << 31)),
+        "_fits_in_32" => Some(x <= low32),
+        "_nonzero_hipart" => Some((x >> 32) & low32 != 0),
+        "_nonzero_lopart" => Some(x & low32 != 0),
+        "_vconst_all_ones_or_all_zeros" => Some(x.to_le_bytes().iter().all(|y| *y == 0 || *y == 0xff)),
+        _ => None,
+    }
+}
+
+// functions whose result the var equals
+pub(crate) fn apply_function(name: &str, params: &[u128]) -> Option<u128> {
+    let low32 = get_mask(32);
+    let low64 = get_mask(64);
+    match (name, params) {
+        ("u64_add" | "u32_add", [a, b]) => Some(a.wrapping_add(*b)),
+        ("u64_sub" | "u32_sub", [a, b]) => Some(a.wrapping_sub(*b)),
+        ("u64_mul", [a, b]) => Some(a.wrapping_mul(*b)),
+        ("u64_and" | "u32_and" | "u8_and", [a, b]) => Some(a & b),
+        ("u64_or", [a, b]) => Some(a | b),
+        ("u64_xor", [a, b]) => Some(a ^ b),
+        ("u64_shl" | "u8_shl", [a, b]) => a.checked_shl(u32::try_from(*b).ok()?),
+        ("u8_shr", [a, b]) => a.checked_shr(u32::try_from(*b).ok()?),
+        ("u64_not" | "_inverted", [a]) => Some(!a),
+        ("i64_neg" | "_negate", [a]) => Some(a.wrapping_neg()),
+        ("_minusone", [a]) => Some(a.wrapping_sub(1)),
+        ("_pow2", [a]) => 1u128.checked_shl(u32::try_from(*a).ok()?),
+        ("_splat64" | "_u128_replicated_u64", [a]) => Some((a & low64) | ((a & low64) << 64)),
+        ("_u64_replicated_u32", [a]) => Some((a & low32) | ((a & low32) << 32)),
+        ("_u32_replicated_u16", [a]) => Some((a & 0xffff) | ((a & 0xffff) << 16)),
+        ("_u16_replicated_u8", [a]) => Some((a & 0xff) | ((a & 0xff) << 8)),
+        ("_sse_interps_lane_imm", [a]) => Some(a << 4),
+        _ => None,
+    }
+}
+
+pub(crate) fn is_comparison(name: &str) -> bool {
+    ["lt", "le", "gt", "ge"].contains(&name)
+}
+
+// conditions of the args or of the opargs of a rule (Var refers to the same kind)
+struct CondSolver {
+    conds: Vec<Vec<ProdCondExpr>>,
+    widths: Vec<u32>,
+    is_operand: bool, // opargs need values; args are left unsolved unless forced
+    values: Vec<Option<SolvedValue>>,
+    visiting: Vec<bool>,
+}
+
+impl CondSolver {
+    fn new(conds: &[ProdCondition], widths: Vec<u32>, is_operand: bool) -> Self {
+        let mut var_conds = vec![Vec::new(); widths.len()];
+        for cond in conds {
+            var_conds[cond.arg] = cond.conds.clone();
+        }
+
+        // the exponent of pow2 is less than the width of the var
+        for (idx, idx_conds) in var_conds.clone().into_iter().enumerate() {
+            for cond in idx_conds {
+                match cond {
+                    ProdCondExpr::Expr { name, params } if name == "_pow2" => {
+                        if let [ProdCondExpr::Var(exp_idx)] = params.as_slice() {
+                            var_conds[*exp_idx].push(ProdCondExpr::Expr {
+                                name: String::from("lt"),
+                                params: vec![ProdCondExpr::Const(widths[idx] as i128)],
+                            });
+                        }
+                    },
+                    _ => (),
+                }
+            }
+        }
+
+        let len = widths.len();
+        Self { conds: var_conds, widths, is_operand, values: vec![None; len], visiting: vec![false; len] }
+    }
+
+    fn unknown(&self, idx: usize, reason: String) -> Result<(), SolveError> {
+        if self.is_operand {
+            Err(SolveError::Unknown(format!("oparg {}: {}", idx, reason)))
+        }
+        else { // conditions on runtime values
+            Ok(())
+        }
+    }
+
+    fn get_name(&self, idx: usize) -> String {
+        format!("{} {}", if self.is_operand { "oparg" } else { "arg" }, idx)
+    }
+
+    // None if the expression depends on a var without value
+    fn eval(&mut self, expr: &ProdCondExpr, width: u32) -> Result<Option<SolvedValue>, SolveError> {
+        let mask = get_mask(width);
+        match expr {
+            ProdCondExpr::Const(val) => Ok(Some(SolvedValue { val: (*val as u128) & mask, forced: true })),
+            ProdCondExpr::ConstPrim(sym) if sym == "true" => Ok(Some(SolvedValue { val: 1, forced: true })),
+            ProdCondExpr::ConstPrim(sym) if sym == "false" => Ok(Some(SolvedValue { val: 0, forced: true })),
+            ProdCondExpr::Var(idx) => Ok(self.solve_var(*idx)?.map(|x| SolvedValue { val: x.val & mask, forced: x.forced })),
+            ProdCondExpr::Expr { name, params } => {
+                let mut param_vals = Vec::new();
+                let mut forced = true;
+                for param in params {
+                    match self.eval(param, 128)? {
+                        Some(x) => {
+                            param_vals.push(x.val);
+                            forced &= x.forced;
+                        },
+                        None => return Ok(None),
+                    }
+                }
+                Ok(apply_function(name, &param_vals).map(|x| SolvedValue { val: x & mask, forced }))
+            },
+            _ => Ok(None),
+        }
+    }
+
+    // whether the var of the width satisfies the (non-equality) condition; None if it cannot be evaluated
+    fn check(&mut self, cond: &ProdCondExpr, x: u128, width: u32) -> Result<Option<(bool, bool)>, SolveError> {
+        match cond {
+            ProdCondExpr::Expr { name, params } if params.is_empty() => {
+                Ok(check_predicate(name, x, width).map(|y| (y, true)))
+            },
+            ProdCondExpr::Expr { name, params } if is_comparison(name) && params.len() == 1 => {
+                let bound = match self.eval(&params[0], width)? {
+                    Some(y) => y,
+                    None => return Ok(None),
+                };
+                let holds = match name.as_str() {
+                    "lt" => x < bound.val,
+                    "le" => x <= bound.val,
+                    "gt" => x > bound.val,
+                    _ => x >= bound.val,
+                };
+                Ok(Some((holds, bound.forced)))
+            },
+            _ => {
+                let expected = match self.eval(cond, width)? {
+                    Some(y) => y,
+                    None => return Ok(None),
+                };
+                Ok(Some((x == expected.val, expected.forced)))
+            },
+        }
+    }
+
+    fn is_equality(cond: &ProdCondExpr) -> bool {
+        match cond {
+            ProdCondExpr::Wildcard => false,
+            ProdCondExpr::Expr { name, params } => !params.is_empty() && !is_comparison(name),
+            _ => true,
+        }
+    }
+
+    // inclusive (nonempty) range from the comparisons with known bounds, and whether the bounds are forced
+    fn get_range(&mut self, idx: usize) -> Result<(u128, u128, bool), SolveError> {
+        let width = self.widths[idx];
+        let (mut lo, mut hi, mut forced) = (0, get_mask(width), true);
+        for cond in self.conds[idx].clone() {
+            if let ProdCondExpr::Expr { name, params } = &cond {
+                if !is_comparison(name) || params.len() != 1 {
+                    continue;
+                }
+                let bound = match self.eval(&params[0], width)? {
+                    Some(y) => y,
+                    None => continue,
+                };
+                forced &= bound.forced;
+                match name.as_str() {
+                    // nothing is below 0 or above the mask, e.g., a false (u64_le x 0) is "gt 0", which holds for x > 0
+                    "lt" if bound.val == 0 => {
+                        let reason = format!("{} is {} {:#x}", self.get_name(idx), name, bound.val);
+                        return Err(if bound.forced { SolveError::Unsat(reason) } else { SolveError::Unknown(reason) });
+                    },
+                    "gt" if bound.val >= get_mask(width) => {
+                        let reason = format!("{} is {} {:#x}", self.get_name(idx), name, bound.val);
+                        return Err(if bound.forced { SolveError::Unsat(reason) } else { SolveError::Unknown(reason) });
+                    },
+                    "lt" => hi = hi.min(bound.val - 1),
+                    "le" => hi = hi.min(bound.val),
+                    "gt" => lo = lo.max(bound.val + 1),
+                    _ => lo = lo.max(bound.val),
+                }
+            }
+        }
+        if lo > hi {
+            let reason = format!("{} has empty range [{:#x}, {:#x}]", self.get_name(idx), lo, hi);
+            return Err(if forced { SolveError::Unsat(reason) } else { SolveError::Unknown(reason) });
+        }
+        Ok((lo, hi, forced))
+    }
+
+    fn solve_var(&mut self, idx: usize) -> Result<Option<SolvedValue>, SolveError> {
+        if let Some(x) = self.values[idx] {
+            return Ok(Some(x));
+        }
+        if self.visiting[idx] { // cyclic conditions, e.g., var0 == var1 and var1 == var0
+            return Ok(None);
+        }
+        self.visiting[idx] = true;
+        let result = self.solve_var_inner(idx);
+        self.visiting[idx] = false;
+        let value = result?;
+        self.values[idx] = value;
+        Ok(value)
+    }
+
+    fn solve_var_inner(&mut self, idx: usize) -> Result<Option<SolvedValue>, SolveError> {
+        let width = self.widths[idx];
+        let conds = self.conds[idx].clone();
+
+        // unsupported conditions
+        for cond in &conds {
+            match cond {
+                ProdCondExpr::ConstPrim(sym) if sym != "true" && sym != "false" => {
+                    self.unknown(idx, format!("symbolic constant {}", sym))?;
+                },
+                ProdCondExpr::Expr { name, params } if params.is_empty() && check_predicate(name, 0, width).is_none() => {
+                    self.unknown(idx, format!("unsupported condition {}", name))?;
+                },
+                ProdCondExpr::Expr { name, params } if !params.is_empty() && !is_comparison(name) &&
+                    apply_function(name, &vec![0; params.len()]).is_none() => {
+                    self.unknown(idx, format!("unsupported condition {}", name))?;
+                },
+                _ => (),
+            }
+        }
+
+        // the first equality that can be evaluated gives the value
+        let mut value = None;
+        for cond in conds.iter().filter(|x| Self::is_equality(x)) {
+            if let Some(x) = self.eval(cond, width)? {
+                value = Some(x);
+                break;
+            }
+        }
+
+        match value {
+            Some(x) => {
+                for cond in conds.iter().filter(|x| !matches!(x, ProdCondExpr::Wildcard)) {
+                    if let Some((false, cond_forced)) = self.check(cond, x.val, width)? {
+                        let reason = format!("{} = {:#x} violates {}", self.get_name(idx), x.val, cond.to_string());
+                        return Err(if x.forced && cond_forced { SolveError::Unsat(reason) } else { SolveError::Unknown(reason) });
+                    }
+                }
+                Ok(Some(x))
+            },
+            None if !self.is_operand => Ok(None), // runtime value
+            None => {
+                let (lo, hi, range_forced) = self.get_range(idx)?;
+
+                // boundary values first, then the whole range if it is small
+                let is_small = hi - lo < SMALL_RANGE;
+                let candidates: Vec<u128> = if is_small {
+                    (lo..=hi).collect()
+                }
+                else {
+                    let mid = lo + (hi - lo) / 2;
+                    (0..4).map(|x| lo + x).chain((0..4).map(|x| hi - x))
+                        .chain([mid, mid + 1, lo | (1 << 32) | 1])
+                        .filter(|x| *x >= lo && *x <= hi)
+                        .collect()
+                };
+
+                let mut all_forced = range_forced;
+                for candidate in candidates {
+                    let mut holds = true;
+                    for cond in conds.iter().filter(|x| !matches!(x, ProdCondExpr::Wildcard)) {
+                        if let Some((false, cond_forced)) = self.check(cond, candidate, width)? {
+                            all_forced &= cond_forced;
+                            holds = false;
+                            break;
+                        }
+                    }
+                    if holds {
+                        return Ok(Some(SolvedValue { val: candidate, forced: false }));
+                    }
+                }
+
+                let reason = format!("no value of {} in [{:#x}, {:#x}] satisfies its conditions", self.get_name(idx), lo, hi);
+                Err(if is_small && all_forced { SolveError::Unsat(reason) } else { SolveError::Unknown(reason) })
+            },
+        }
+    }
+}
+
+// widths of the opargs, from the instructions using them (64 if only used in conditions)
+fn get_oparg_widths(rule: &ProdRule) -> Vec<u32> {
+    let mut widths = vec![64; get_oparg_cnt(rule)];
+    for instr in &rule.instrs {
+        if let ProdToken::Instr(instr, Some(ProdOperand::OpArg(idx))) = instr {
+            widths[*idx] = get_operand_width(instr);
+        }
+    }
+    widths
+}
+
+pub fn solve_prod(rule: &ProdRule) -> ProdSolution {
+    fn solve_all(solver: &mut CondSolver) -> Result<Vec<Option<SolvedValue>>, SolveError> {
+        for idx in 0..solver.widths.len() {
+            solver.solve_var(idx)?;
+        }
+
+        // conditions skipped while solving cyclic ones
+        for idx in 0..solver.widths.len() {
+            let (val, width) = match solver.values[idx] {
+                Some(x) => (x.val, solver.widths[idx]),
+                None => continue,
+            };
+            for cond in solver.conds[idx].clone() {
+                if let Some((false, _)) = solver.check(&cond, val, width)? {
+                    return Err(SolveError::Unknown(format!("{} = {:#x} violates {}", solver.get_name(idx), val, cond.to_string())));
+                }
+            }
+        }
+        Ok(solver.values.clone())
+    }
+
+    let arg_widths = rule.param_types.kinds().iter().map(get_type_width).collect();
+    let mut arg_solver = CondSolver::new(&rule.arg_conds, arg_widths, false);
+    let mut oparg_solver = CondSolver::new(&rule.oparg_conds, get_oparg_widths(rule), true);
+
+    match (solve_all(&mut arg_solver), solve_all(&mut oparg_solver)) {
+        (Err(SolveError::Unsat(reason)), _) | (_, Err(SolveError::Unsat(reason))) => ProdSolution::Unsat(reason),
+        (Err(SolveError::Unknown(reason)), _) | (_, Err(SolveError::Unknown(reason))) => ProdSolution::Unknown(reason),
+        (Ok(args), Ok(opargs)) => ProdSolution::Sat(ProdWitness {
+            args: args.into_iter().map(|x| x.map(|y| y.val as i128)).collect(),
+            opargs: opargs.into_iter().map(|x| x.unwrap().val as i128).collect(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_ast::{IntegerType, NumericInstruction};
+
+    use super::*;
+
+    fn get_rule(arg_conds: Vec<ProdCondition>, oparg_conds: Vec<ProdCondition>) -> ProdRule {
+        ProdRule {
+            arg_conds,
+            oparg_conds,
+            ..ProdRule::new(vec![ValueType::I32], vec![ValueType::I32], vec![
+                ProdToken::Arg(0),
+                ProdToken::Instr(NumericInstruction::I32Constant(0).into(), Some(ProdOperand::OpArg(0))),
+                ProdToken::Instr(NumericInstruction::ShiftLeft(IntegerType::I32).into(), None),
+            ])
+        }
+    }
+
+    fn get_cond(arg: usize, conds: Vec<ProdCondExpr>) -> ProdCondition {
+        ProdCondition { arg, conds }
+    }
+
+    fn get_expr(name: &str, params: Vec<ProdCondExpr>) -> ProdCondExpr {
+        ProdCondExpr::Expr { name: String::from(name), params }
+    }
+
+    #[test]
+    fn test_solve_prod() {
+        // oparg 0 is an odd value less than 8, arg 0 is fixed to -1
+        let rule = get_rule(
+            vec![get_cond(0, vec![ProdCondExpr::Const(-1)])],
+            vec![get_cond(0, vec![get_expr("is_odd", vec![]), get_expr("lt", vec![ProdCondExpr::Const(8)])])],
+        );
+        let solution = solve_prod(&rule);
+        println!("{:#?}", solution);
+        assert_eq!(solution, ProdSolution::Sat(ProdWitness { args: vec![Some(0xffffffff)], opargs: vec![1] }));
+
+        // oparg 0 is a power of two given by oparg 1, which is only used in the condition
+        let rule = get_rule(Vec::new(), vec![
+            get_cond(0, vec![get_expr("_pow2", vec![ProdCondExpr::Var(1)])]),
+            get_cond(1, vec![get_expr("nonzero", vec![])]),
+        ]);
+        assert_eq!(solve_prod(&rule), ProdSolution::Sat(ProdWitness { args: vec![None], opargs: vec![2, 1] }));
+
+        // contradicting conditions
+        let rule = get_rule(Vec::new(), vec![get_cond(0, vec![ProdCondExpr::Const(4), get_expr("is_odd", vec![])])]);
+        assert!(solve_prod(&rule).is_unsat());
+        let rule = get_rule(Vec::new(), vec![get_cond(0, vec![get_expr("gt", vec![ProdCondExpr::Const(3)]), get_expr("le", vec![ProdCondExpr::Const(2)])])]);
+        assert!(solve_prod(&rule).is_unsat());
+        let rule = get_rule(vec![get_cond(0, vec![ProdCondExpr::Const(0), get_expr("nonzero", vec![])])], Vec::new());
+        assert!(solve_prod(&rule).is_unsat());
+
+        // conditions on runtime args are not solved
+        let rule = get_rule(vec![get_cond(0, vec![get_expr("_pshufd_lhs_imm", vec![])])], Vec::new());
+        assert_eq!(solve_prod(&rule), ProdSolution::Sat(ProdWitness { args: vec![None], opargs: vec![0] }));
+        let rule = get_rule(Vec::new(), vec![get_cond(0, vec![get_expr("_pshufd_lhs_imm", vec![])])]);
+        assert!(matches!(solve_prod(&rule), ProdSolution::Unknown(_)));
+    }
+
+    #[test]
+    fn test_solve_range() {
+        // gt 0 holds for any nonzero value
+        let rule = get_rule(Vec::new(), vec![get_cond(0, vec![get_expr("gt", vec![ProdCondExpr::Const(0)])])]);
+        let solution = solve_prod(&rule);
+        println!("{:#?}", solution);
+        assert!(matches!(solution, ProdSolution::Sat(ProdWitness { ref opargs, .. }) if opargs[0] != 0));
+
+        // lt 0 and gt of the mask (of the i32 oparg) hold for nothing
+        let rule = get_rule(Vec::new(), vec![get_cond(0, vec![get_expr("lt", vec![ProdCondExpr::Const(0)])])]);
+        assert!(solve_prod(&rule).is_unsat());
+        let rule = get_rule(Vec::new(), vec![get_cond(0, vec![get_expr("gt", vec![ProdCondExpr::Const(0xffffffff)])])]);
+        assert!(solve_prod(&rule).is_unsat());
+    }
+}
@@ -1,4 +1,5 @@
-use wasm_ast::{ResultType, Module, FunctionIndex};
+use enum_iterator::all;
+use wasm_ast::{ResultType, Module, FunctionIndex, ValueType};
 
 use crate::{prod::{ProdRule, ProdOperand, ProdToken}, wasm_comp::{get_dummy_module, get_instr_iterator_no_control, get_instruction_type}};
 
@@ -9,6 +10,18 @@ pub fn get_typing_rule_prods(ret_types: ResultType) -> Vec<ProdRule> {
     get_typing_rule_prods_mod(&dummy_module, 0, ret_types)
 }
 
+// typing rules for every value type and for no return value
+pub fn get_all_typing_rule_prods() -> Vec<ProdRule> {
+    let mut typing_rules: Vec<ProdRule> = Vec::new();
+    for ty in all::<ValueType>() {
+        let mut ty_rules = get_typing_rule_prods(vec![ty].into());
+        typing_rules.append(&mut ty_rules);
+    }
+    let mut noret_rules = get_typing_rule_prods(Vec::new().into());
+    typing_rules.append(&mut noret_rules);
+    typing_rules
+}
+
 pub fn get_typing_rule_prods_mod(
     module: &Module, 
     funcidx: FunctionIndex, 
@@ -44,7 +57,10 @@ pub fn get_typing_rule_prods_mod(
                     ret_types: instr_ret_types.clone().into(),
                     arg_conds: Vec::new(),
                     oparg_conds: Vec::new(),
+                    sources: Vec::new(),
                     instrs,
+                    weight: 1.0,
+                    wrap: None,
                 });
                 break;
             }
@@ -0,0 +1,391 @@
+// Rule-firing verification: whether each production makes Cranelift fire the ISLE rule it targets
+// With the `verify` feature, cranelift-codegen is built with `trace-log`, and the code generated from the
+// ISLE rules logs `ISLE rule at <file> line <n>` whenever a rule returns (patches/isle-emit-logging.patch,
+// applied by build-verify.sh to a copy of the ISLE compiler). Each production is instantiated as its corpus module (prod_corpus), compiled in process
+// for the backends of its target rule as the engine compiles it, and the positions logged meanwhile are
+// looked up for the target rule in its sources.
+
+use std::{collections::BTreeMap, sync::{Mutex, OnceLock}};
+
+use anyhow::{anyhow, bail, Error};
+use cranelift_codegen::{control::ControlPlane, ir::Function, isa::{self, OwnedTargetIsa, TargetIsa}, settings::{self, Configurable}, Context};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    diagnostics::{format_table, Diagnostics},
+    norm::RuleSource,
+    prod::ProdRule,
+    prod_corpus::{build_prod_module, emit_module},
+    wasm_env::translate_module_functions,
+    wasm_map::{TargetArch, WasmMapOption},
+};
+
+const FIRING_PREFIX: &str = "ISLE rule at ";
+
+// positions of the rules fired since the last take_firings
+static FIRINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// whether FiringLogger is the installed logger
+static LOGGER_INSTALLED: OnceLock<bool> = OnceLock::new();
+
+struct FiringLogger;
+
+impl Log for FiringLogger {
+    // only the code generated from ISLE logs rule positions
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target().ends_with("::generated_code")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if let Some(pos) = record.args().to_string().strip_prefix(FIRING_PREFIX) {
+            FIRINGS.lock().unwrap().push(pos.to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: FiringLogger = FiringLogger;
+
+fn install_logger() -> Result<(), Error> {
+    let installed = LOGGER_INSTALLED.get_or_init(|| {
+        log::set_logger(&LOGGER).map(|()| log::set_max_level(LevelFilter::Trace)).is_ok()
+    });
+    if !installed {
+        bail!("Another logger is installed, so rule firings cannot be collected");
+    }
+    Ok(())
+}
+
+fn take_firings() -> Vec<String> {
+    std::mem::take(&mut *FIRINGS.lock().unwrap())
+}
+
+// whether a logged position (e.g., src/opts/arithmetic.isle line 12) is the rule of the source
+fn is_source_pos(pos: &str, source: &RuleSource) -> bool {
+    match pos.rsplit_once(" line ") {
+        Some((file, line)) => {
+            line.parse() == Ok(source.line) && (file == source.file || file.ends_with(&format!("/{}", source.file)))
+        },
+        None => false,
+    }
+}
+
+// backends that the rule is compiled with: its own for lowering rules, all of them for the others
+fn get_source_archs(source: &RuleSource) -> Vec<TargetArch> {
+    let arch = match source.file.split('/').collect::<Vec<_>>()[..] {
+        ["isa", "x64", ..] => TargetArch::X64,
+        ["isa", "aarch64", ..] => TargetArch::Aarch64,
+        ["isa", "s390x", ..] => TargetArch::S390x,
+        ["isa", "riscv64", ..] => TargetArch::Riscv64,
+        _ => return TargetArch::ALL.to_vec(),
+    };
+    vec![arch]
+}
+
+// as the engine compiles (opt_level=speed), with the extensions that WebAssembly SIMD needs
+fn get_verify_isa(arch: TargetArch, nan_canonicalization: bool) -> Result<OwnedTargetIsa, Error> {
+    let triple = arch.get_triple();
+    let mut builder = isa::lookup(triple.clone()).map_err(|e| anyhow!("No Cranelift backend for {}: {}", triple, e))?;
+    let extensions: &[&str] = match arch {
+        TargetArch::X64 => &["has_sse3", "has_ssse3", "has_sse41", "has_sse42"],
+        TargetArch::Riscv64 => &["has_v"],
+        TargetArch::Aarch64 | TargetArch::S390x => &[],
+    };
+    for name in extensions {
+        builder.enable(name)?;
+    }
+
+    let mut flags = settings::builder();
+    flags.set("opt_level", "speed")?;
+    flags.set("enable_nan_canonicalization", if nan_canonicalization { "true" } else { "false" })?;
+    Ok(builder.finish(settings::Flags::new(flags))?)
+}
+
+// functions of the corpus module of the rule, as they are translated for the engine
+fn get_prod_functions(rule: &ProdRule, option: &WasmMapOption) -> Result<Vec<Function>, String> {
+    let (module, _) = build_prod_module(rule)?;
+    let binary = emit_module(&module).map_err(|e| e.to_string())?;
+    translate_module_functions(&binary, &option.env).map_err(|e| e.to_string())
+}
+
+// positions of the rules fired while compiling the functions
+fn compile_functions(funcs: &[Function], isa: &dyn TargetIsa) -> Result<Vec<String>, String> {
+    let mut firings = Vec::new();
+    for func in funcs {
+        let mut context = Context::for_function(func.clone());
+        take_firings();
+        let result = context.compile(isa, &mut ControlPlane::default()).map(|_| ()).map_err(|e| e.inner.to_string());
+        firings.append(&mut take_firings());
+        result?;
+    }
+    Ok(firings)
+}
+
+/// Firings of a target rule of a production, per backend it was compiled with
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TargetVerdict {
+    pub source: RuleSource,
+    pub hits: Vec<TargetArch>, // backends on which the rule fired
+    pub misses: Vec<TargetArch>, // backends that compiled the module without firing it
+}
+
+/// Firings of the target rules of a production. A production has several when identical
+/// productions of several rules are merged (canonicalize_rules) or paired (prod_multi).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProdVerdict {
+    pub targets: Vec<TargetVerdict>,
+    pub skipped: Option<String>, // why the module was not compiled, or the first compile error
+}
+
+impl ProdVerdict {
+    // compiled, but none of its target rules fired
+    pub fn is_dead(&self) -> bool {
+        self.targets.iter().all(|x| x.hits.is_empty()) && self.targets.iter().any(|x| !x.misses.is_empty())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FiringStatus {
+    Fired, // some production fires the rule
+    Dead, // no production fires the rule
+    Unchecked, // no production was compiled
+}
+
+/// Verification outcome of a simplify/lower rule over its productions
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RuleFiring {
+    pub source: RuleSource,
+    pub status: FiringStatus,
+    pub hits: usize, // productions that fire the rule on some backend
+    pub misses: usize, // productions that were compiled without firing it
+    pub skipped: usize,
+    pub reason: Option<String>, // first reason a production was skipped
+}
+
+// records whether each target rule compiled for the backend fired
+fn add_firings(verdict: &mut ProdVerdict, arch: TargetArch, firings: &[String]) {
+    for target in verdict.targets.iter_mut().filter(|x| get_source_archs(&x.source).contains(&arch)) {
+        if firings.iter().any(|x| is_source_pos(x, &target.source)) {
+            target.hits.push(arch);
+        }
+        else {
+            target.misses.push(arch);
+        }
+    }
+}
+
+/// Compiles each production with simplify/lower target rules, and checks which of them fire.
+/// The verdicts are in the order of the rules; None for the others (e.g., typing rules).
+pub fn verify_prods(rules: &[ProdRule], option: &WasmMapOption) -> Result<Vec<Option<ProdVerdict>>, Error> {
+    if !cfg!(feature = "verify") {
+        bail!("Rule firings are only logged when the extractor is built with build-verify.sh");
+    }
+    install_logger()?;
+    let isas = TargetArch::ALL.into_iter()
+        .map(|x| get_verify_isa(x, option.nan_canonicalization).map(|isa| (x, isa)))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut verdicts = Vec::new();
+    for rule in rules {
+        let targets = Diagnostics::get_targets(&rule.sources);
+        if targets.is_empty() {
+            verdicts.push(None);
+            continue;
+        }
+        let targets = targets.into_iter().map(|x| TargetVerdict { source: x.clone(), hits: Vec::new(), misses: Vec::new() }).collect();
+        let mut verdict = ProdVerdict { targets, skipped: None };
+        match get_prod_functions(rule, option) {
+            Ok(funcs) => {
+                // each backend of some target rule, once
+                let archs = verdict.targets.iter().flat_map(|x| get_source_archs(&x.source)).collect::<Vec<_>>();
+                for (arch, isa) in isas.iter().filter(|(x, _)| archs.contains(x)) {
+                    match compile_functions(&funcs, isa.as_ref()) {
+                        // lowering any function fires some rule, unless the ISLE compiler is unpatched
+                        Ok(firings) if firings.is_empty() =>
+                            bail!("No ISLE rule firings were logged; build the extractor with build-verify.sh"),
+                        Ok(firings) => add_firings(&mut verdict, *arch, &firings),
+                        Err(reason) => {
+                            verdict.skipped.get_or_insert(format!("{}: {}", format!("{:?}", arch).to_lowercase(), reason));
+                        },
+                    }
+                }
+            },
+            Err(reason) => verdict.skipped = Some(reason),
+        }
+        verdicts.push(Some(verdict));
+    }
+    Ok(verdicts)
+}
+
+/// Rules without their dead productions
+pub fn filter_dead_prods(rules: Vec<ProdRule>, verdicts: &[Option<ProdVerdict>]) -> Vec<ProdRule> {
+    rules.into_iter().zip(verdicts)
+        .filter(|(_, verdict)| !verdict.as_ref().is_some_and(|x| x.is_dead()))
+        .map(|(rule, _)| rule)
+        .collect()
+}
+
+// sorted by file, line
+pub fn get_firing_report(verdicts: &[Option<ProdVerdict>]) -> Vec<RuleFiring> {
+    let mut report: BTreeMap<&RuleSource, RuleFiring> = BTreeMap::new();
+    for verdict in verdicts.iter().flatten() {
+        for target in &verdict.targets {
+            let firing = report.entry(&target.source).or_insert_with(|| RuleFiring {
+                source: target.source.clone(), status: FiringStatus::Unchecked, hits: 0, misses: 0, skipped: 0, reason: None,
+            });
+            if !target.hits.is_empty() {
+                firing.hits += 1;
+            }
+            else if !target.misses.is_empty() {
+                firing.misses += 1;
+            }
+            else {
+                firing.skipped += 1;
+                if firing.reason.is_none() {
+                    firing.reason = verdict.skipped.clone();
+                }
+            }
+        }
+    }
+    report.into_values().map(|mut firing| {
+        firing.status = match (firing.hits, firing.misses) {
+            (0, 0) => FiringStatus::Unchecked,
+            (0, _) => FiringStatus::Dead,
+            _ => FiringStatus::Fired,
+        };
+        firing
+    }).collect()
+}
+
+pub fn firing_report_to_table(report: &[RuleFiring]) -> String {
+    let rows = report.iter().map(|firing| {
+        [
+            format!("{}:{}", firing.source.file, firing.source.line),
+            firing.source.name.clone(),
+            firing.source.prio.to_string(),
+            format!("{:?}", firing.status).to_lowercase(),
+            firing.hits.to_string(),
+            firing.misses.to_string(),
+            firing.skipped.to_string(),
+            firing.reason.clone().unwrap_or_default(),
+        ]
+    }).collect::<Vec<_>>();
+
+    let mut table = format_table(["rule", "term", "prio", "status", "hit", "miss", "skipped", "reason"], &rows, &[2, 4, 5, 6]);
+
+    // summary
+    let fired = report.iter().filter(|x| x.status == FiringStatus::Fired).count();
+    let dead = report.iter().filter(|x| x.status == FiringStatus::Dead).count();
+    table.push_str(&format!("\n{} / {} rules fire, {} dead\n", fired, report.len(), dead));
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_ast::{NumberType, NumericInstruction, ValueType};
+
+    use crate::prod::ProdToken;
+
+    use super::*;
+
+    // i32.add of its two params
+    fn get_rule(source: RuleSource) -> ProdRule {
+        ProdRule {
+            sources: vec![source],
+            ..ProdRule::new(vec![ValueType::I32, ValueType::I32], vec![ValueType::I32], vec![
+                ProdToken::Arg(0),
+                ProdToken::Arg(1),
+                ProdToken::Instr(NumericInstruction::Add(NumberType::I32).into(), None),
+            ])
+        }
+    }
+
+    #[test]
+    fn test_firing_report() {
+        let source = RuleSource::new_target("isa/x64/lower.isle", 40);
+        assert!(is_source_pos("src/isa/x64/lower.isle line 40", &source));
+        assert!(!is_source_pos("src/isa/x64/lower.isle line 400", &source));
+        assert!(!is_source_pos("src/isa/aarch64/lower.isle line 40", &source));
+        assert_eq!(get_source_archs(&source), vec![TargetArch::X64]);
+        assert_eq!(get_source_archs(&RuleSource::new_target("opts/arithmetic.isle", 12)), TargetArch::ALL.to_vec());
+
+        let dead_source = RuleSource::new_target("isa/x64/lower.isle", 50);
+        let get_verdict = |source: &RuleSource, hits: Vec<TargetArch>, misses: Vec<TargetArch>, skipped: Option<&str>| ProdVerdict {
+            targets: vec![TargetVerdict { source: source.clone(), hits, misses }],
+            skipped: skipped.map(String::from),
+        };
+        let verdicts = vec![
+            Some(get_verdict(&dead_source, Vec::new(), vec![TargetArch::X64], None)),
+            Some(get_verdict(&source, vec![TargetArch::X64], Vec::new(), None)),
+            Some(get_verdict(&source, Vec::new(), Vec::new(), Some("unsat"))),
+            None,
+        ];
+        let report = get_firing_report(&verdicts);
+        println!("{}", firing_report_to_table(&report));
+        assert_eq!(report.len(), 2);
+        assert_eq!((report[0].status, report[0].hits, report[0].skipped), (FiringStatus::Fired, 1, 1));
+        assert_eq!((report[1].status, report[1].misses), (FiringStatus::Dead, 1));
+
+        let rules = vec![get_rule(dead_source), get_rule(source.clone()), get_rule(source), get_rule(RuleSource::new_target("opts/arithmetic.isle", 12))];
+        assert_eq!(filter_dead_prods(rules, &verdicts).len(), 3);
+    }
+
+    #[test]
+    fn test_two_target_prod() {
+        // merged from two lowering rules, of which only the second fires
+        let (first, second) = (RuleSource::new_target("isa/x64/lower.isle", 40), RuleSource::new_target("isa/x64/lower.isle", 50));
+        let mut rule = get_rule(first.clone());
+        rule.sources.push(second);
+        rule.sources.push(first.clone()); // e.g., paired with itself by prod_multi
+
+        let targets = Diagnostics::get_targets(&rule.sources).into_iter()
+            .map(|x| TargetVerdict { source: x.clone(), hits: Vec::new(), misses: Vec::new() })
+            .collect::<Vec<_>>();
+        assert_eq!(targets.len(), 2);
+        let mut verdict = ProdVerdict { targets, skipped: None };
+        add_firings(&mut verdict, TargetArch::X64, &[String::from("src/isa/x64/lower.isle line 50")]);
+        assert_eq!(verdict.targets[0].misses, vec![TargetArch::X64]);
+        assert_eq!(verdict.targets[1].hits, vec![TargetArch::X64]);
+        assert!(!verdict.is_dead());
+
+        let verdicts = vec![Some(verdict)];
+        let report = get_firing_report(&verdicts);
+        assert_eq!((report[0].status, report[0].misses), (FiringStatus::Dead, 1));
+        assert_eq!((report[1].status, report[1].hits), (FiringStatus::Fired, 1));
+        assert_eq!(filter_dead_prods(vec![rule], &verdicts).len(), 1);
+    }
+
+    #[cfg(feature = "verify")]
+    #[test]
+    fn test_verify_prods() {
+        // some x64 lowering rule fires for i32.add, but none at line 1
+        let option = WasmMapOption::default();
+        let rule = get_rule(RuleSource::new_target("isa/x64/lower.isle", 1));
+        let verdicts = verify_prods(std::slice::from_ref(&rule), &option).unwrap();
+        assert_eq!(verdicts[0].as_ref().unwrap().targets[0].misses, vec![TargetArch::X64]);
+
+        let isa = get_verify_isa(TargetArch::X64, true).unwrap();
+        let firings = compile_functions(&get_prod_functions(&rule, &option).unwrap(), isa.as_ref()).unwrap();
+        println!("{:#?}", firings);
+        assert!(firings.iter().any(|x| x.starts_with("src/isa/x64/lower.isle line ")));
+    }
+
+    #[cfg(feature = "verify")]
+    #[test]
+    fn test_verify_amode_prods() {
+        // addresses are bare vars, so the to_amode variants fire on the translated iadd(heap_base, uextend(addr))
+        let config = crate::config::ExtractConfig::builder().match_option(crate::rule_match::MatchOption::X64).build().unwrap();
+        let rules = config.extract().unwrap().into_iter()
+            .filter(|x| x.sources.iter().any(|y| y.role == crate::norm::RuleRole::Subst && y.name == "to_amode"))
+            .collect::<Vec<_>>();
+        assert!(!rules.is_empty());
+        let verdicts = config.verify(&rules).unwrap();
+        assert!(verdicts.iter().flatten().any(|x| x.targets.iter().any(|y| y.hits.contains(&TargetArch::X64))));
+    }
+}
@@ -1,11 +1,11 @@
 // Step 3: Matching & Learning
 
-use std::{collections::{HashMap, HashSet, hash_map::DefaultHasher}, fmt::Debug, hash::{Hash, Hasher}};
+use std::{collections::{BTreeSet, HashMap, HashSet, hash_map::DefaultHasher}, fmt::Debug, hash::{Hash, Hasher}, path::Path};
 
 use anyhow::{bail, Context, Error};
-use cranelift_codegen::ir::{types, Type};
+use cranelift_codegen::ir::{types, Opcode, Type};
 
-use crate::{isle::ISLEParseOptions, isle_cond::process_conds, isle_subst::{check_subst_applicable, subst_apply_result, subst_result}, isle_type::type_rules_opt, norm::{LinExprIdx, LinVec, MatchResult, MatchStmt, UnifiedExprIdx, UnifiedResult, UnifiedRule, UnifiedStmt}, wasm_map::get_clir_wasm_map, wasm_norm::wasm_pair_to_matched_result};
+use crate::{diagnostics::{is_target_source, DiagStage, Diagnostics}, isle::ISLEParseOptions, isle_cond::process_conds, isle_subst::{check_subst_applicable, subst_apply_result, subst_result}, isle_type::type_rules_opt_diag, norm::{LinExprIdx, LinVec, MatchResult, MatchStmt, RuleRole, RuleSource, UnifiedExprIdx, UnifiedResult, UnifiedRule, UnifiedStmt}, wasm_map::{get_clir_wasm_map, get_clir_wasm_dag_map, TargetArch, WasmMapOption}, wasm_norm::{wasm_pair_to_matched_result, wasm_dag_pair_to_matched_result}};
 
 #[derive(Clone, Debug, PartialEq, Copy)]
 pub enum MatchOption {
@@ -16,10 +16,46 @@ pub enum MatchOption {
     ARM64,
     S390X,
     RISCV64,
+    OptX64,
+    OptARM64,
+    OptS390X,
+    OptRISCV64,
     TestOpt,
     TestLower,
 }
 
+impl MatchOption {
+    // ISLE rule sets that are matched together
+    pub fn parse_options(&self) -> Vec<ISLEParseOptions> {
+        match self {
+            MatchOption::All => vec![ISLEParseOptions::Opt, ISLEParseOptions::Lower],
+            MatchOption::Opt => vec![ISLEParseOptions::Opt],
+            MatchOption::Lower => vec![ISLEParseOptions::Lower],
+            MatchOption::X64 => vec![ISLEParseOptions::X64],
+            MatchOption::ARM64 => vec![ISLEParseOptions::ARM64],
+            MatchOption::S390X => vec![ISLEParseOptions::S390X],
+            MatchOption::RISCV64 => vec![ISLEParseOptions::RISCV64],
+            MatchOption::OptX64 => vec![ISLEParseOptions::Opt, ISLEParseOptions::X64],
+            MatchOption::OptARM64 => vec![ISLEParseOptions::Opt, ISLEParseOptions::ARM64],
+            MatchOption::OptS390X => vec![ISLEParseOptions::Opt, ISLEParseOptions::S390X],
+            MatchOption::OptRISCV64 => vec![ISLEParseOptions::Opt, ISLEParseOptions::RISCV64],
+            MatchOption::TestOpt => vec![ISLEParseOptions::TestOpt],
+            MatchOption::TestLower => vec![ISLEParseOptions::TestLower],
+        }
+    }
+
+    // backend of the lowering rules, if only one backend is used
+    pub fn target_arch(&self) -> Option<TargetArch> {
+        match self {
+            MatchOption::X64 | MatchOption::OptX64 => Some(TargetArch::X64),
+            MatchOption::ARM64 | MatchOption::OptARM64 => Some(TargetArch::Aarch64),
+            MatchOption::S390X | MatchOption::OptS390X => Some(TargetArch::S390x),
+            MatchOption::RISCV64 | MatchOption::OptRISCV64 => Some(TargetArch::Riscv64),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct MatchContext {
     // name -> [ MatchResult ]
@@ -56,7 +92,8 @@ impl MatchContext {
     //     self.learn_map.entry(name).or_default().push(learn_result);
     // }
 
-    fn add_subst(&mut self, name: String, lin_result: UnifiedResult) {
+    fn add_subst(&mut self, name: String, mut lin_result: UnifiedResult) {
+        lin_result.rule.sources = lin_result.rule.sources.iter().map(|x| x.with_role(RuleRole::Subst)).collect();
         self.subst_map.entry(name).or_default().push(lin_result);
     }
 }
@@ -67,39 +104,12 @@ fn get_hash(str: String) -> u64 {
     hasher.finish()
 }
 
-fn match_and_learn_prepare(match_option: MatchOption) -> (MatchContext, Vec<UnifiedRule>, Vec<UnifiedRule>) {
+fn match_and_learn_prepare(match_option: MatchOption, map_option: &WasmMapOption, src_root: &Path, diag: &mut Diagnostics) -> (MatchContext, Vec<UnifiedRule>, Vec<UnifiedRule>) {
     let mut clir_results = Vec::new();
-    match match_option {
-        MatchOption::All => {
-            clir_results.append(&mut type_rules_opt(ISLEParseOptions::Opt));
-            clir_results.append(&mut type_rules_opt(ISLEParseOptions::Lower));
-        },
-        MatchOption::Opt => {
-            clir_results.append(&mut type_rules_opt(ISLEParseOptions::Opt));
-        },
-        MatchOption::Lower => {
-            clir_results.append(&mut type_rules_opt(ISLEParseOptions::Lower));
-        },
-        MatchOption::X64 => {
-            clir_results.append(&mut type_rules_opt(ISLEParseOptions::X64));
-        },
-        MatchOption::ARM64 => {
-            clir_results.append(&mut type_rules_opt(ISLEParseOptions::ARM64));
-        },
-        MatchOption::S390X => {
-            clir_results.append(&mut type_rules_opt(ISLEParseOptions::S390X));
-        },
-        MatchOption::RISCV64 => {
-            clir_results.append(&mut type_rules_opt(ISLEParseOptions::RISCV64));
-        },
-        MatchOption::TestOpt => {
-            clir_results.append(&mut type_rules_opt(ISLEParseOptions::TestOpt));
-        },
-        MatchOption::TestLower => {
-            clir_results.append(&mut type_rules_opt(ISLEParseOptions::TestLower));
-        },
+    for parse_option in match_option.parse_options() {
+        clir_results.append(&mut type_rules_opt_diag(parse_option, src_root, diag));
     }
-    let clir_wasm_map = get_clir_wasm_map();
+    let clir_wasm_map = get_clir_wasm_map(map_option);
 
     // construct context from wasm
     let mut context = MatchContext::new();
@@ -109,7 +119,20 @@ fn match_and_learn_prepare(match_option: MatchOption) -> (MatchContext, Vec<Unif
             None => (),
         }
     }
+    // multi-IR translations are keyed by their root
+    for (dag, instrs) in get_clir_wasm_dag_map(map_option) {
+        match wasm_dag_pair_to_matched_result(&dag, &instrs) {
+            Some(x) => context.add_expr(dag.get_root().data.opcode.to_string(), x),
+            None => (),
+        }
+    }
+
+    let (clir_simplify_rules, clir_lower_rules) = prepare_clir_rules(&mut context, clir_results, diag);
+    (context, clir_simplify_rules, clir_lower_rules)
+}
 
+// simplify and lowering rules with the substitution rules applied and the conditions embedded
+fn prepare_clir_rules(context: &mut MatchContext, clir_results: Vec<UnifiedResult>, diag: &mut Diagnostics) -> (Vec<UnifiedRule>, Vec<UnifiedRule>) {
     // from Cranelift rules
     let mut clir_simplify_results = Vec::new();
     let mut clir_lower_results = Vec::new();
@@ -123,7 +146,7 @@ fn match_and_learn_prepare(match_option: MatchOption) -> (MatchContext, Vec<Unif
                     new_rule.remove(LinExprIdx::LHS(lin_result.rule.lhs.get_idx_ref(lin_result.rule.lhs.len() - 1).unwrap().clone()));
                     clir_simplify_results.push(new_rule);
                 }
-                else if name == "lower" {
+                else if name == "lower" || name == "lower_branch" {
                     assert!(params.len() == 1);
                     let mut new_rule = lin_result.clone();
                     new_rule.remove(LinExprIdx::LHS(lin_result.rule.lhs.get_idx_ref(lin_result.rule.lhs.len() - 1).unwrap().clone()));
@@ -140,27 +163,128 @@ fn match_and_learn_prepare(match_option: MatchOption) -> (MatchContext, Vec<Unif
     // Process 1: substitute simplify and lowering rules with normal rules
     let mut subst_simplify_results = Vec::new();
     for clir_simplify_result in clir_simplify_results {
-        let mut subst_result = subst_result(&context.subst_map, clir_simplify_result, false).unwrap_or(Vec::new());
+        let mut subst_result = subst_result_diag(context, clir_simplify_result, false, diag);
         subst_simplify_results.append(&mut subst_result);
     }
     let mut subst_lower_results = Vec::new();
     for clir_lower_result in clir_lower_results {
-        let mut subst_result = subst_result(&context.subst_map, clir_lower_result, true).unwrap_or(Vec::new());
+        let mut subst_result = subst_result_diag(context, clir_lower_result, true, diag);
         subst_lower_results.append(&mut subst_result);
     }
 
     // Process 2: process conditions and embed them to variables
-    let clir_simplify_rules = subst_simplify_results.into_iter().flat_map(process_conds).collect();
-    let clir_lower_rules = subst_lower_results.into_iter().flat_map(process_conds).collect();
+    let clir_simplify_rules = subst_simplify_results.into_iter().flat_map(|x| process_conds_diag(x, diag)).collect();
+    let clir_lower_rules = subst_lower_results.into_iter().flat_map(|x| process_conds_diag(x, diag)).collect();
 
-    (context, clir_simplify_rules, clir_lower_rules)
+    (clir_simplify_rules, clir_lower_rules)
+}
+
+// (simplify rules, lowering rules) as they are before matching (e.g., for the CLIF exporter)
+pub fn get_clir_rules(match_option: MatchOption, src_root: &Path) -> (Vec<UnifiedRule>, Vec<UnifiedRule>) {
+    let mut diag = Diagnostics::disabled();
+    let mut clir_results = Vec::new();
+    for parse_option in match_option.parse_options() {
+        clir_results.append(&mut type_rules_opt_diag(parse_option, src_root, &mut diag));
+    }
+    let mut context = MatchContext::new();
+    prepare_clir_rules(&mut context, clir_results, &mut diag)
+}
+
+// simplify rules as they are before matching (e.g., for the soundness checker)
+pub fn get_simplify_rules(match_option: MatchOption, src_root: &Path) -> Vec<UnifiedRule> {
+    get_clir_rules(match_option, src_root).0
+}
+
+fn subst_result_diag(context: &MatchContext, lin_result: UnifiedResult, is_lower: bool, diag: &mut Diagnostics) -> Vec<UnifiedResult> {
+    let target = Diagnostics::get_target(&lin_result.rule.sources).cloned();
+    let results = match subst_result(&context.subst_map, lin_result, is_lower) {
+        Ok(x) => x,
+        Err(e) => {
+            if let Some(target) = &target {
+                diag.add_drop(target, DiagStage::Subst, e.to_string());
+            }
+            Vec::new()
+        },
+    };
+    if let Some(target) = &target {
+        diag.add_survivors(target, DiagStage::Subst, results.len());
+    }
+    results
+}
+
+fn process_conds_diag(lin_result: UnifiedResult, diag: &mut Diagnostics) -> Vec<UnifiedRule> {
+    let target = Diagnostics::get_target(&lin_result.rule.sources).cloned();
+    let results = process_conds(lin_result);
+    if let Some(target) = &target {
+        diag.add_survivors(target, DiagStage::Cond, results.len());
+    }
+    results
+}
+
+// why a rule without any match results could not be matched
+fn get_match_drop_reason(context: &MatchContext, rule: &UnifiedRule) -> String {
+    for cur_idx in 0..rule.lhs.len() {
+        match rule.lhs.get(cur_idx).unwrap() {
+            UnifiedStmt::Expr { name, .. } if !context.match_map.contains_key(name) && !context.learn_map.contains_key(name) => {
+                if name.parse::<Opcode>().is_ok() {
+                    return format!("no WASM mapping for opcode {}", name);
+                }
+                else {
+                    return format!("unhandled extern term {}", name);
+                }
+            },
+            _ => (),
+        }
+    }
+    String::from("no type-compatible match")
+}
+
+// record the match stage of the diagnostics once matching is over
+fn add_match_diag(context: &MatchContext, rules: &[UnifiedRule], results: &[MatchResult], match_errors: &HashMap<RuleSource, String>, diag: &mut Diagnostics) {
+    let mut result_cnts: HashMap<&RuleSource, usize> = HashMap::new();
+    for result in results {
+        for source in result.sources.iter().filter(|x| is_target_source(x)) {
+            *result_cnts.entry(source).or_default() += 1;
+        }
+    }
+
+    let mut visited = BTreeSet::new();
+    for rule in rules {
+        let target = match Diagnostics::get_target(&rule.sources) {
+            Some(x) => x,
+            None => continue,
+        };
+        if !visited.insert(target) {
+            continue;
+        }
+
+        let cnt = result_cnts.get(target).copied().unwrap_or(0);
+        diag.add_survivors(target, DiagStage::Match, cnt);
+        if cnt == 0 {
+            let reason = match match_errors.get(target) {
+                Some(x) => x.clone(),
+                None => get_match_drop_reason(context, rule),
+            };
+            diag.add_drop(target, DiagStage::Match, reason);
+        }
+    }
 }
 
-pub fn match_and_learn(match_option: MatchOption) -> Vec<MatchResult> {
-    let (mut context, clir_simplify_rules, clir_lower_rules) = match_and_learn_prepare(match_option);
+pub fn match_and_learn(match_option: MatchOption, map_option: &WasmMapOption, src_root: &Path) -> Vec<MatchResult> {
+    match_and_learn_diag(match_option, map_option, src_root, &mut Diagnostics::disabled())
+}
+
+pub fn match_and_learn_diag(match_option: MatchOption, map_option: &WasmMapOption, src_root: &Path, diag: &mut Diagnostics) -> Vec<MatchResult> {
+    let (mut context, clir_simplify_rules, clir_lower_rules) = match_and_learn_prepare(match_option, map_option, src_root, diag);
+    let diag_rules = if diag.is_enabled() {
+        clir_simplify_rules.iter().chain(clir_lower_rules.iter()).cloned().collect()
+    } else {
+        Vec::new()
+    };
+    let mut match_errors: HashMap<RuleSource, String> = HashMap::new(); // target -> first match error
 
-    let mut results = Vec::new();
-    let mut results_set = HashSet::new();
+    let mut results: Vec<MatchResult> = Vec::new();
+    let mut results_map: HashMap<String, usize> = HashMap::new(); // key -> index of results
 
     // Step 1: match and learn opt. rules
     let mut is_done = false;
@@ -187,9 +311,10 @@ pub fn match_and_learn(match_option: MatchOption) -> Vec<MatchResult> {
                         .map(|x| x.deep_clone().reverse())
                         .filter(check_match_result_learnable).collect::<Vec<_>>();
                     
-                    for reversed_result in reversed_results {
+                    for mut reversed_result in reversed_results {
                         assert!(reversed_result.is_reversed());
-                        let is_added = context.learn_set.entry(reversed_result.get_name()).or_default().insert(get_hash(format!("{:?}", reversed_result)));
+                        reversed_result.sources = reversed_result.sources.iter().map(|x| x.with_role(RuleRole::Learned)).collect();
+                        let is_added = context.learn_set.entry(reversed_result.get_name()).or_default().insert(get_hash(reversed_result.get_key()));
                         if is_added {
                             context.learn_map.entry(reversed_result.get_name()).or_default().push(reversed_result);
                         }
@@ -197,13 +322,13 @@ pub fn match_and_learn(match_option: MatchOption) -> Vec<MatchResult> {
                     }
 
                     for match_result in match_results {
-                        let is_added = results_set.insert(format!("{:?}", match_result));
-                        if is_added {
-                            results.push(match_result);
-                        }
+                        add_match_result(&mut results, &mut results_map, match_result);
                     }
                 },
-                Err(_) => {
+                Err(e) => {
+                    if let Some(target) = Diagnostics::get_target(&rule.sources) {
+                        match_errors.entry(target.clone()).or_insert(e.to_string());
+                    }
                     temp_rules.push(rule.clone());
                 },
             }
@@ -222,19 +347,39 @@ pub fn match_and_learn(match_option: MatchOption) -> Vec<MatchResult> {
         match match_rule(&context, rule) {
             Ok((match_results, _)) => {
                 for match_result in match_results {
-                    let is_added = results_set.insert(format!("{:?}", &match_result));
-                    if is_added {
-                        results.push(match_result);
-                    }
+                    add_match_result(&mut results, &mut results_map, match_result);
+                }
+            },
+            Err(e) => {
+                if let Some(target) = Diagnostics::get_target(&rule.sources) {
+                    match_errors.entry(target.clone()).or_insert(e.to_string());
                 }
             },
-            Err(_) => {},
         }
     }
-    
+
+    add_match_diag(&context, &diag_rules, &results, &match_errors, diag);
     results
 }
 
+// the same result can be reached from multiple rules; keep it once with all the sources
+fn add_match_result(results: &mut Vec<MatchResult>, results_map: &mut HashMap<String, usize>, match_result: MatchResult) {
+    match results_map.get(&match_result.get_key()) {
+        Some(&idx) => {
+            let cur_sources = &mut results[idx].sources;
+            for source in match_result.sources {
+                if !cur_sources.contains(&source) {
+                    cur_sources.push(source);
+                }
+            }
+        },
+        None => {
+            results_map.insert(match_result.get_key(), results.len());
+            results.push(match_result);
+        },
+    }
+}
+
 fn match_rule(context: &MatchContext, rule: &UnifiedRule) -> Result<(Vec<MatchResult>, bool), Error> {
     // find candidates for each expr
     let mut learn_used = false;
@@ -287,7 +432,7 @@ fn match_rule(context: &MatchContext, rule: &UnifiedRule) -> Result<(Vec<MatchRe
     // repeatedly match and substitute rule
     let mut pending_vec = vec![MatchResult::new(rule.deep_clone())];
     let mut pending_set = HashSet::new();
-    pending_set.insert(get_hash(format!("{:?}", pending_vec[0])));
+    pending_set.insert(get_hash(pending_vec[0].get_key()));
     let mut complete_vec = Vec::new();
     let mut complete_set = HashSet::new();
     while pending_vec.len() > 0 {
@@ -298,7 +443,7 @@ fn match_rule(context: &MatchContext, rule: &UnifiedRule) -> Result<(Vec<MatchRe
         let cur_idx = match cur_result.stmts.iter().rev().position(|x| x == &MatchStmt::None) {
             Some(x) => cur_result.stmts.len() - 1 - x,
             None => {
-                let is_added = complete_set.insert(get_hash(format!("{:?}", &cur_result)));
+                let is_added = complete_set.insert(get_hash(cur_result.get_key()));
                 if is_added {
                     complete_vec.push(cur_result);
                 }
@@ -311,21 +456,21 @@ fn match_rule(context: &MatchContext, rule: &UnifiedRule) -> Result<(Vec<MatchRe
         match cur_stmt {
             UnifiedStmt::Var(conds) => { 
                 cur_result.stmts[cur_idx] = MatchStmt::Arg(conds.clone());
-                let is_added = pending_set.insert(get_hash(format!("{:?}", &cur_result)));
+                let is_added = pending_set.insert(get_hash(cur_result.get_key()));
                 if is_added {
                     pending_vec.push(cur_result);
                 }
             },
             UnifiedStmt::TypeVar(_) => { 
                 cur_result.stmts[cur_idx] = MatchStmt::Nil;
-                let is_added = pending_set.insert(get_hash(format!("{:?}", &cur_result)));
+                let is_added = pending_set.insert(get_hash(cur_result.get_key()));
                 if is_added {
                     pending_vec.push(cur_result);
                 }
             },
             UnifiedStmt::Const(val) => {
                 cur_result.stmts[cur_idx] = MatchStmt::Const(*val);
-                let is_added = pending_set.insert(get_hash(format!("{:?}", &cur_result)));
+                let is_added = pending_set.insert(get_hash(cur_result.get_key()));
                 if is_added {
                     pending_vec.push(cur_result);
                 }
@@ -339,7 +484,7 @@ fn match_rule(context: &MatchContext, rule: &UnifiedRule) -> Result<(Vec<MatchRe
                         match match_and_subst(&cur_result, candidate, cur_idx) {
                             Ok(Some(x)) => {
                                 if check_match_result_types(&x) {
-                                    let is_added = pending_set.insert(get_hash(format!("{:?}", &x)));
+                                    let is_added = pending_set.insert(get_hash(x.get_key()));
                                     if is_added {
                                         pending_vec.push(x);
                                     }
@@ -364,7 +509,7 @@ fn match_rule(context: &MatchContext, rule: &UnifiedRule) -> Result<(Vec<MatchRe
                             match match_and_subst(&cur_result, candidate, cur_idx) {
                                 Ok(Some(mut x)) => {
                                     if check_match_result_types(&x) {
-                                        let is_added = pending_set.insert(get_hash(format!("{:?}", &x)));
+                                        let is_added = pending_set.insert(get_hash(x.get_key()));
                                         if is_added {
                                             x.from_learned = true;
                                             pending_vec.push(x);
@@ -432,7 +577,7 @@ fn check_match_result_types(match_result: &MatchResult) -> bool {
                 match lhs_stmt {
                     UnifiedStmt::Expr { name, .. } => {
                         data.ret_type == cur_ty ||
-                        (name == "icmp" && data.ret_type == Some(types::I8) && cur_ty.is_some() && 
+                        ((name == "icmp" || name == "fcmp") && data.ret_type == Some(types::I8) && cur_ty.is_some() && 
                             vec![types::I16, types::I32, types::I64].contains(&cur_ty.unwrap()))
                     },
                     _ => data.ret_type == cur_ty,
@@ -444,19 +589,34 @@ fn check_match_result_types(match_result: &MatchResult) -> bool {
         match (match_stmt, lhs_stmt) {
             (MatchStmt::Expr { data, .. }, UnifiedStmt::Expr { name: _, params }) => {
                 let mut params_iter = params.iter().skip(1); // skip typevar
-                if data.operand_type.is_some() {
-                    let _ = params_iter.next(); // discard operand
-                }
                 if data.intcc.is_some() || data.floatcc.is_some() {
                     let _ = params_iter.next(); // discard cc
                 }
+                if data.memflags.is_some() {
+                    let _ = params_iter.next(); // discard flags
+                }
+                if data.trap_code.is_some() {
+                    let _ = params_iter.next_back(); // discard trap code (at last position)
+                }
+                for _ in 0..data.branch_dests {
+                    let _ = params_iter.next_back(); // discard branch destinations (at last positions)
+                }
+                if data.operand_type.is_some() {
+                    let _ = params_iter.next_back(); // discard operand (at last position)
+                }
 
                 assert!(params_iter.len() == data.param_types.len());
-                let check_result = params_iter.zip(data.param_types.iter()).all(|(idx, ty)| {
+                let addr_param_idx = data.get_addr_param_idx();
+                let check_result = params_iter.zip(data.param_types.iter()).enumerate().all(|(param_idx, (idx, ty))| {
                     let new_idx = match idx {
                         UnifiedExprIdx::LHS(inner_idx) => *inner_idx.borrow(),
                         _ => unreachable!(),
                     };
+                    // wasm addresses are i32 and translated to iadd(heap_base, uextend(addr)),
+                    // so only a bare var matches the address; an expression would not be what is compiled
+                    if addr_param_idx == Some(param_idx) {
+                        return !matches!(match_result.stmts[new_idx], MatchStmt::Expr { .. });
+                    }
                     check_match_result_types_rec(match_result, Some(ty.clone()), new_idx)
                 });
                 type_check && check_result
@@ -521,15 +681,17 @@ fn check_match_result_learnable(match_result: &MatchResult) -> bool {
 
 #[cfg(test)]
 mod test {
+    use crate::isle::default_cranelift_src;
+
     use super::*;
 
     #[test]
     fn test_match_and_learn_prepare() {
-        println!("{:#?}", match_and_learn_prepare(MatchOption::All).1);
+        println!("{:#?}", match_and_learn_prepare(MatchOption::All, &WasmMapOption::default(), &default_cranelift_src(), &mut Diagnostics::disabled()).1);
     }
 
     #[test]
     fn test_match_and_learn() {
-        println!("{:#?}", match_and_learn(MatchOption::All));
+        println!("{:#?}", match_and_learn(MatchOption::All, &WasmMapOption::default(), &default_cranelift_src()));
     }
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
 use anyhow::{Error, anyhow};
-use wasm_ast::{Instruction, ValueType, Module, FunctionIndex, MemoryType, Limit, FunctionType, VariableInstruction, Expression, Function, Export, ExportDescription, Name, Global, GlobalType, Element, ReferenceType, ElementInitializer, TableType, Table, Data, ImportDescription, Import, ModuleBuilder, VectorShape, IntegerType, FloatType, NumberType, SignExtension, IShape, FShape};
+use wasm_ast::{Instruction, ValueType, Module, FunctionIndex, MemoryType, Limit, FunctionType, VariableInstruction, Expression, Function, Export, ExportDescription, Name, Global, GlobalType, Element, ReferenceType, ElementInitializer, TableType, Table, Data, ImportDescription, Import, ModuleBuilder, VectorShape, IntegerType, FloatType, NumberType, SignExtension, IShape, FShape, MemoryArgument, ControlInstruction, BlockType, TableInstruction, ReferenceInstruction};
 use enum_iterator::all;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -436,14 +436,19 @@ pub fn get_instruction_type(module: &Module, funcidx: FunctionIndex, instr: &Ins
                 wasm_ast::MemoryInstruction::V128Load8Lane(..) |
                 wasm_ast::MemoryInstruction::V128Load16Lane(..) |
                 wasm_ast::MemoryInstruction::V128Load32Lane(..) |
-                wasm_ast::MemoryInstruction::V128Load64Lane(..) |
+                wasm_ast::MemoryInstruction::V128Load64Lane(..) =>
+                    InstructionType {
+                        param_types: vec![ValueType::I32, ValueType::V128],
+                        ret_types: vec![ValueType::V128],
+                        has_operand: true,
+                    },
                 wasm_ast::MemoryInstruction::V128Store8Lane(..) |
                 wasm_ast::MemoryInstruction::V128Store16Lane(..) |
                 wasm_ast::MemoryInstruction::V128Store32Lane(..) |
                 wasm_ast::MemoryInstruction::V128Store64Lane(..) =>
                     InstructionType {
                         param_types: vec![ValueType::I32, ValueType::V128],
-                        ret_types: vec![ValueType::V128],
+                        ret_types: vec![],
                         has_operand: true,
                     },
                 wasm_ast::MemoryInstruction::Size => 
@@ -512,9 +517,11 @@ pub fn get_instruction_type(module: &Module, funcidx: FunctionIndex, instr: &Ins
                             vec![InstructionType { param_types: vec![ValueType::I32], ret_types: vec![ty.clone()], has_operand: false }],
                     }
                 },
-                wasm_ast::ControlInstruction::Branch(_) |
+                // labels are assumed to have empty types, labels are operands
                 wasm_ast::ControlInstruction::BranchIf(_) |
-                wasm_ast::ControlInstruction::BranchTable(_, _) |
+                wasm_ast::ControlInstruction::BranchTable(_, _) => 
+                    vec![InstructionType { param_types: vec![ValueType::I32], ret_types: vec![], has_operand: true }],
+                wasm_ast::ControlInstruction::Branch(_) |
                 wasm_ast::ControlInstruction::Return => 
                     return Err(anyhow!("Stack-polymorphic instructions not supported")),
                 wasm_ast::ControlInstruction::Call(fidx) => {
@@ -1189,7 +1196,7 @@ pub fn get_instruction_name(instr: &Instruction) -> String {
                 wasm_ast::MemoryInstruction::Load(ty, _) => 
                     return format!("{}.load", numbertype_to_name(ty)),
                 wasm_ast::MemoryInstruction::Store(ty, _) => 
-                    return format!("{}.load", numbertype_to_name(ty)),
+                    return format!("{}.store", numbertype_to_name(ty)),
                 wasm_ast::MemoryInstruction::V128Load(_) => "v128.load",
                 wasm_ast::MemoryInstruction::V128Store(_) => "v128.store",
                 wasm_ast::MemoryInstruction::Load8(ty, se, _) =>
@@ -1252,6 +1259,11 @@ pub fn get_instruction_name(instr: &Instruction) -> String {
     String::from(name_str)
 }
 
+// reverse of get_instruction_name; immediates are left as enumerated (zeroed)
+pub fn get_instruction_from_name(name: &str) -> Option<Instruction> {
+    all::<Instruction>().find(|x| get_instruction_name(x) == name)
+}
+
 pub fn get_dummy_module() -> Module {
     let mut dummy_module_builder = get_base_module_builder();
     dummy_module_builder.add_function_type(FunctionType::runnable()).unwrap();
@@ -1259,6 +1271,24 @@ pub fn get_dummy_module() -> Module {
     dummy_module_builder.build()
 }
 
+// one mutable global per number type; the first one (i64) is global 0
+pub(crate) fn get_base_global_types() -> Vec<ValueType> {
+    vec![ValueType::I64, ValueType::I32, ValueType::F32, ValueType::F64, ValueType::V128]
+}
+
+fn get_zero_expression(kind: ValueType) -> Expression {
+    let instr: Instruction = match kind {
+        ValueType::I32 => 0i32.into(),
+        ValueType::I64 => 0i64.into(),
+        ValueType::F32 => 0f32.into(),
+        ValueType::F64 => 0f64.into(),
+        ValueType::V128 => Instruction::Vector(wasm_ast::VectorInstruction::V128Constant(0)),
+        ValueType::FunctionReference => ReferenceInstruction::Null(ReferenceType::Function).into(),
+        ValueType::ExternalReference => ReferenceInstruction::Null(ReferenceType::External).into(),
+    };
+    vec![instr].into()
+}
+
 pub fn get_base_module_builder() -> ModuleBuilder {
     // Module here has default sections:
     // table, memory, globals, elements, data
@@ -1273,10 +1303,11 @@ pub fn get_base_module_builder() -> ModuleBuilder {
     // let memory = Memory::new(memory_type.clone()); // instead of adding this, import this
     // module_builder.add_memory(memory).unwrap();
 
-    let global_type = GlobalType::mutable(ValueType ::I64);
-    let global_init = vec![0i64.into()].into();
-    let global = Global::new(global_type, global_init);
-    let _global_idx = module_builder.add_global(global).unwrap();
+    for global_kind in get_base_global_types() {
+        let global_type = GlobalType::mutable(global_kind);
+        let global = Global::new(global_type, get_zero_expression(global_kind));
+        let _global_idx = module_builder.add_global(global).unwrap();
+    }
     
     let element_offset: Expression = vec![0i32.into()].into();
     let element_initializers = vec![0].to_initializers();
@@ -1343,6 +1374,127 @@ pub fn wrap_instruction_to_module(instr: &Instruction) -> Result<Vec<Module>, Er
     Ok(module_vec)
 }
 
+// memory argument of loads and stores
+pub fn get_memory_argument(instr: &Instruction) -> Option<MemoryArgument> {
+    match instr {
+        Instruction::Memory(i) => match i {
+            wasm_ast::MemoryInstruction::Load(_, memarg) |
+            wasm_ast::MemoryInstruction::Store(_, memarg) |
+            wasm_ast::MemoryInstruction::V128Load(memarg) |
+            wasm_ast::MemoryInstruction::V128Store(memarg) |
+            wasm_ast::MemoryInstruction::Load8(_, _, memarg) |
+            wasm_ast::MemoryInstruction::Load16(_, _, memarg) |
+            wasm_ast::MemoryInstruction::Load32(_, memarg) |
+            wasm_ast::MemoryInstruction::Store8(_, memarg) |
+            wasm_ast::MemoryInstruction::Store16(_, memarg) |
+            wasm_ast::MemoryInstruction::Store32(memarg) |
+            wasm_ast::MemoryInstruction::V128Load8X8(_, memarg) |
+            wasm_ast::MemoryInstruction::V128Load16X4(_, memarg) |
+            wasm_ast::MemoryInstruction::V128Load32X2(_, memarg) |
+            wasm_ast::MemoryInstruction::V128Load32Zero(memarg) |
+            wasm_ast::MemoryInstruction::V128Load64Zero(memarg) |
+            wasm_ast::MemoryInstruction::V128Load8Splat(memarg) |
+            wasm_ast::MemoryInstruction::V128Load16Splat(memarg) |
+            wasm_ast::MemoryInstruction::V128Load32Splat(memarg) |
+            wasm_ast::MemoryInstruction::V128Load64Splat(memarg) |
+            wasm_ast::MemoryInstruction::V128Load8Lane(memarg, _) |
+            wasm_ast::MemoryInstruction::V128Load16Lane(memarg, _) |
+            wasm_ast::MemoryInstruction::V128Load32Lane(memarg, _) |
+            wasm_ast::MemoryInstruction::V128Load64Lane(memarg, _) |
+            wasm_ast::MemoryInstruction::V128Store8Lane(memarg, _) |
+            wasm_ast::MemoryInstruction::V128Store16Lane(memarg, _) |
+            wasm_ast::MemoryInstruction::V128Store32Lane(memarg, _) |
+            wasm_ast::MemoryInstruction::V128Store64Lane(memarg, _) => Some(*memarg),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// branches on an i32 that translate to a single CLIF branch (brif, br_table)
+// - the labels target the function body, whose type is empty
+// - if has empty arms, so only the brif of the condition remains
+pub fn get_branch_instrs() -> Vec<Instruction> {
+    vec![
+        ControlInstruction::BranchIf(0).into(),
+        ControlInstruction::If(BlockType::None, Expression::empty(), None).into(),
+        ControlInstruction::BranchTable(Vec::new(), 0).into(),
+    ]
+}
+
+// global, local, table and reference instructions over the entities of the base module
+// - global.get/set of a global of each number type
+// - local.tee of the local that wrap_instruction_to_module adds
+// - table.fill/copy/init and elem.drop are left out, as memory.fill/copy/init are
+pub fn get_entity_instrs() -> Vec<Instruction> {
+    let mut instrs: Vec<Instruction> = Vec::new();
+    for gidx in 0..get_base_global_types().len() as u32 {
+        instrs.push(VariableInstruction::GlobalGet(gidx).into());
+        instrs.push(VariableInstruction::GlobalSet(gidx).into());
+    }
+    instrs.push(VariableInstruction::LocalTee(0).into());
+    instrs.push(TableInstruction::Get(0).into());
+    instrs.push(TableInstruction::Set(0).into());
+    instrs.push(TableInstruction::Size(0).into());
+    instrs.push(TableInstruction::Grow(0).into());
+    instrs.push(ReferenceInstruction::Null(ReferenceType::Function).into());
+    instrs.push(ReferenceInstruction::Null(ReferenceType::External).into());
+    instrs.push(ReferenceInstruction::IsNull.into());
+    instrs.push(ReferenceInstruction::Function(0).into());
+    instrs
+}
+
+// type of the module entity (local, global, table element, function reference) that the operand
+// of an instruction refers to; None for other operands
+// rules cannot choose these operands, the generator picks an entity of the type instead
+pub fn get_entity_type(module: &Module, funcidx: FunctionIndex, instr: &Instruction) -> Option<ValueType> {
+    match instr {
+        Instruction::Variable(i) => match i {
+            VariableInstruction::LocalGet(lidx) |
+            VariableInstruction::LocalSet(lidx) |
+            VariableInstruction::LocalTee(lidx) => {
+                let func = &module.functions()?[funcidx as usize];
+                func.locals().kinds().get(*lidx as usize).cloned()
+            },
+            VariableInstruction::GlobalGet(gidx) |
+            VariableInstruction::GlobalSet(gidx) => Some(module.globals()?.get(*gidx as usize)?.kind().kind()),
+        },
+        Instruction::Table(i) => match i {
+            TableInstruction::Get(tidx) |
+            TableInstruction::Set(tidx) |
+            TableInstruction::Size(tidx) |
+            TableInstruction::Grow(tidx) |
+            TableInstruction::Fill(tidx) => Some(ValueType::from(module.tables()?.get(*tidx as usize)?.kind().kind())),
+            _ => None,
+        },
+        Instruction::Reference(ReferenceInstruction::Function(_)) => Some(ValueType::FunctionReference),
+        _ => None,
+    }
+}
+
+// loads and stores that translate to a plain CLIF load/store
+// - lane accesses take a lane index besides the memarg, which a single operand cannot carry
+// - memory.size/grow/fill/copy/init and data.drop are stubbed out by DummyEnvironment
+fn is_extractable_memory_instr(instr: &wasm_ast::MemoryInstruction) -> bool {
+    match instr {
+        wasm_ast::MemoryInstruction::V128Load8Lane(..) |
+        wasm_ast::MemoryInstruction::V128Load16Lane(..) |
+        wasm_ast::MemoryInstruction::V128Load32Lane(..) |
+        wasm_ast::MemoryInstruction::V128Load64Lane(..) |
+        wasm_ast::MemoryInstruction::V128Store8Lane(..) |
+        wasm_ast::MemoryInstruction::V128Store16Lane(..) |
+        wasm_ast::MemoryInstruction::V128Store32Lane(..) |
+        wasm_ast::MemoryInstruction::V128Store64Lane(..) |
+        wasm_ast::MemoryInstruction::Size |
+        wasm_ast::MemoryInstruction::Grow |
+        wasm_ast::MemoryInstruction::Fill |
+        wasm_ast::MemoryInstruction::Copy |
+        wasm_ast::MemoryInstruction::Init(_) |
+        wasm_ast::MemoryInstruction::DataDrop(_) => false,
+        _ => true,
+    }
+}
+
 // excludes control instructions
 pub fn get_instr_iterator_no_control() -> impl Iterator<Item = Instruction> {
     // TODO: we need to make sequences better
@@ -1362,7 +1514,7 @@ pub fn get_instr_iterator_no_control() -> impl Iterator<Item = Instruction> {
         Instruction::Parametric(_) => true,
         Instruction::Variable(_) => false,
         Instruction::Table(_) => false,
-        Instruction::Memory(_) => false,
+        Instruction::Memory(i) => is_extractable_memory_instr(i),
         Instruction::Vector(_) => true,
     })
 }
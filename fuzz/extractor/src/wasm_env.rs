@@ -0,0 +1,862 @@
+// Step 2.1: Translation environments for WASM modules
+// DummyEnvironment of cranelift_wasm emits placeholders for heaps, tables, globals and libcalls.
+// WasmtimeEnvironment follows crates/cranelift/src/func_environ.rs of wasmtime for them, so that
+// the translated IR is what wasmtime actually compiles. vmctx offsets are fixed dummies, since
+// they only appear in global values and immediates that are not mapped.
+
+use anyhow::{anyhow, Error};
+use cranelift_codegen::{
+    cursor::FuncCursor,
+    entity::{EntityRef, PrimaryMap},
+    ir::{self, condcodes::IntCC, immediates::{Imm64, Offset32, Uimm64}, types::{I32, I64}, InstBuilder, MemFlags, UserFuncName},
+    isa::{CallConv, TargetFrontendConfig},
+};
+use cranelift_wasm::{
+    translate_module, wasmparser::{FuncValidator, FunctionBody, UnpackedIndex, ValidatorResources, WasmFeatures},
+    DataIndex, DummyEnvironment, DummyFuncEnvironment, DummyModuleInfo, ElemIndex, FuncEnvironment, FuncIndex,
+    FunctionBuilder, Global, GlobalIndex, GlobalInit, GlobalVariable, Heap, HeapData, HeapStyle, Memory, MemoryIndex,
+    ModuleEnvironment, Table, TableIndex, TargetEnvironment, TypeConvert, TypeIndex, WasmFuncType, WasmHeapType,
+    WasmResult, WasmValType,
+};
+use serde::{Deserialize, Serialize};
+use target_lexicon::PointerWidth;
+
+const WASM_PAGE_SIZE: u64 = 0x1_0000;
+const WASM32_MAX_PAGES: u64 = 0x1_0000;
+
+// dummy vmctx layout
+const VMCTX_BUILTIN_FUNCTIONS: i32 = 0x8;
+const VMCTX_IMPORTS_BEGIN: i32 = 0x100; // one pointer per imported entity
+const VMCTX_DEFINITIONS_BEGIN: i32 = 0x1000; // 0x10 bytes per defined entity
+const VMMEMORY_DEFINITION_BASE: i32 = 0x0;
+const VMMEMORY_DEFINITION_CURRENT_LENGTH: i32 = 0x8;
+const VMTABLE_DEFINITION_BASE: i32 = 0x0;
+const VMTABLE_DEFINITION_CURRENT_ELEMENTS: i32 = 0x8;
+const BUILTIN_MEMORY32_GROW: i32 = 0x0;
+const BUILTIN_REF_FUNC: i32 = 0x38;
+const BUILTIN_TABLE_GET_LAZY_INIT_FUNC_REF: i32 = 0x48;
+const BUILTIN_TABLE_GROW_FUNC_REF: i32 = 0x50;
+const FUNCREF_INIT_BIT: i64 = 1;
+const FUNCREF_MASK: i64 = -2;
+
+/// Knobs of wasmtime that change the translated IR (defaults of wasmtime on 64-bit hosts)
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WasmtimeEnvOption {
+    pub spectre_mitigation: bool, // cranelift enable_heap_access_spectre_mitigation
+    pub static_memory_maximum_size: u64, // Config::static_memory_maximum_size, 0 for dynamic heaps only
+    pub static_memory_guard_size: u64, // Config::static_memory_guard_size
+    pub dynamic_memory_guard_size: u64, // Config::dynamic_memory_guard_size
+}
+
+impl Default for WasmtimeEnvOption {
+    fn default() -> Self {
+        WasmtimeEnvOption {
+            spectre_mitigation: true,
+            static_memory_maximum_size: 0x1_0000_0000,
+            static_memory_guard_size: 0x8000_0000,
+            dynamic_memory_guard_size: 0x1_0000,
+        }
+    }
+}
+
+/// Environment that WASM modules are translated with
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TranslateEnv {
+    Dummy,
+    Wasmtime(WasmtimeEnvOption),
+}
+
+impl Default for TranslateEnv {
+    fn default() -> Self {
+        TranslateEnv::Wasmtime(WasmtimeEnvOption::default())
+    }
+}
+
+fn get_frontend_config() -> TargetFrontendConfig {
+    TargetFrontendConfig {
+        default_call_conv: CallConv::SystemV,
+        pointer_width: PointerWidth::U64,
+    }
+}
+
+/// Translates the defined functions of a WASM module into CLIR (Cranelift IR)
+pub fn translate_module_functions(data: &[u8], env: &TranslateEnv) -> Result<Vec<ir::Function>, Error> {
+    match env {
+        TranslateEnv::Dummy => {
+            let mut dummy_environment = DummyEnvironment::new(get_frontend_config());
+            match translate_module(data, &mut dummy_environment) {
+                Ok(_) => Ok(dummy_environment.info.function_bodies.into_iter().map(|(_, f)| f).collect()),
+                Err(error) => Err(anyhow!("translate_data failed with {:?}", error)),
+            }
+        },
+        TranslateEnv::Wasmtime(option) => {
+            let mut wasmtime_environment = WasmtimeEnvironment::new(option.clone());
+            match translate_module(data, &mut wasmtime_environment) {
+                Ok(_) => Ok(wasmtime_environment.function_bodies),
+                Err(error) => Err(anyhow!("translate_data failed with {:?}", error)),
+            }
+        },
+    }
+}
+
+// Module environment: module information is kept by DummyEnvironment, only function bodies
+// are translated with WasmtimeFuncEnvironment
+struct WasmtimeEnvironment {
+    dummy: DummyEnvironment,
+    option: WasmtimeEnvOption,
+    func_types: Vec<WasmFuncType>, // DummyEnvironment only keeps the IR signatures
+    function_bodies: Vec<ir::Function>,
+}
+
+impl WasmtimeEnvironment {
+    fn new(option: WasmtimeEnvOption) -> Self {
+        WasmtimeEnvironment {
+            dummy: DummyEnvironment::new(get_frontend_config()),
+            option,
+            func_types: Vec::new(),
+            function_bodies: Vec::new(),
+        }
+    }
+}
+
+impl TypeConvert for WasmtimeEnvironment {
+    fn lookup_heap_type(&self, index: UnpackedIndex) -> WasmHeapType {
+        self.dummy.lookup_heap_type(index)
+    }
+}
+
+impl<'data> ModuleEnvironment<'data> for WasmtimeEnvironment {
+    fn declare_type_func(&mut self, wasm_func_type: WasmFuncType) -> WasmResult<()> {
+        self.func_types.push(wasm_func_type.clone());
+        self.dummy.declare_type_func(wasm_func_type)
+    }
+
+    fn declare_func_import(&mut self, index: TypeIndex, module: &'data str, field: &'data str) -> WasmResult<()> {
+        self.dummy.declare_func_import(index, module, field)
+    }
+
+    fn declare_table_import(&mut self, table: Table, module: &'data str, field: &'data str) -> WasmResult<()> {
+        self.dummy.declare_table_import(table, module, field)
+    }
+
+    fn declare_memory_import(&mut self, memory: Memory, module: &'data str, field: &'data str) -> WasmResult<()> {
+        self.dummy.declare_memory_import(memory, module, field)
+    }
+
+    fn declare_global_import(&mut self, global: Global, module: &'data str, field: &'data str) -> WasmResult<()> {
+        self.dummy.declare_global_import(global, module, field)
+    }
+
+    fn declare_func_type(&mut self, index: TypeIndex) -> WasmResult<()> {
+        self.dummy.declare_func_type(index)
+    }
+
+    fn declare_table(&mut self, table: Table) -> WasmResult<()> {
+        self.dummy.declare_table(table)
+    }
+
+    fn declare_memory(&mut self, memory: Memory) -> WasmResult<()> {
+        self.dummy.declare_memory(memory)
+    }
+
+    fn declare_global(&mut self, global: Global, init: GlobalInit) -> WasmResult<()> {
+        self.dummy.declare_global(global, init)
+    }
+
+    fn declare_func_export(&mut self, func_index: FuncIndex, name: &'data str) -> WasmResult<()> {
+        self.dummy.declare_func_export(func_index, name)
+    }
+
+    fn declare_table_export(&mut self, table_index: TableIndex, name: &'data str) -> WasmResult<()> {
+        self.dummy.declare_table_export(table_index, name)
+    }
+
+    fn declare_memory_export(&mut self, memory_index: MemoryIndex, name: &'data str) -> WasmResult<()> {
+        self.dummy.declare_memory_export(memory_index, name)
+    }
+
+    fn declare_global_export(&mut self, global_index: GlobalIndex, name: &'data str) -> WasmResult<()> {
+        self.dummy.declare_global_export(global_index, name)
+    }
+
+    fn declare_start_func(&mut self, index: FuncIndex) -> WasmResult<()> {
+        self.dummy.declare_start_func(index)
+    }
+
+    fn declare_table_elements(
+        &mut self,
+        table_index: TableIndex,
+        base: Option<GlobalIndex>,
+        offset: u32,
+        elements: Box<[FuncIndex]>,
+    ) -> WasmResult<()> {
+        self.dummy.declare_table_elements(table_index, base, offset, elements)
+    }
+
+    fn declare_passive_element(&mut self, index: ElemIndex, elements: Box<[FuncIndex]>) -> WasmResult<()> {
+        self.dummy.declare_passive_element(index, elements)
+    }
+
+    fn declare_passive_data(&mut self, data_index: DataIndex, data: &'data [u8]) -> WasmResult<()> {
+        self.dummy.declare_passive_data(data_index, data)
+    }
+
+    fn declare_data_initialization(
+        &mut self,
+        memory_index: MemoryIndex,
+        base: Option<GlobalIndex>,
+        offset: u64,
+        data: &'data [u8],
+    ) -> WasmResult<()> {
+        self.dummy.declare_data_initialization(memory_index, base, offset, data)
+    }
+
+    fn define_function_body(
+        &mut self,
+        mut validator: FuncValidator<ValidatorResources>,
+        body: FunctionBody<'data>,
+    ) -> WasmResult<()> {
+        let func_index = FuncIndex::new(self.dummy.get_num_func_imports() + self.function_bodies.len());
+        let sig_index = self.dummy.get_func_type(func_index);
+        let mut func_environ = WasmtimeFuncEnvironment::new(&self.dummy.info, &self.option);
+        // wasmtime passes vmctx (and the caller vmctx) first; the dummy signature keeps the
+        // wasm parameters first, which does not change the translated body
+        let mut sig = func_environ.inner.vmctx_sig(sig_index);
+        // funcref values are plain pointers in wasmtime, not reference types
+        let wasm_func_type = &self.func_types[sig_index.index()];
+        for (param, wasm_ty) in sig.params.iter_mut().zip(wasm_func_type.params()) {
+            param.value_type = func_environ.get_ir_type(wasm_ty);
+        }
+        for (ret, wasm_ty) in sig.returns.iter_mut().zip(wasm_func_type.returns()) {
+            ret.value_type = func_environ.get_ir_type(wasm_ty);
+        }
+        let mut func = ir::Function::with_name_signature(UserFuncName::user(0, func_index.as_u32()), sig);
+        self.dummy.trans.translate_body(&mut validator, body, &mut func, &mut func_environ)?;
+        self.function_bodies.push(func);
+        Ok(())
+    }
+
+    fn wasm_features(&self) -> WasmFeatures {
+        self.dummy.wasm_features()
+    }
+}
+
+// Function environment: heaps, tables, globals, memory.size/grow, funcref table accesses and
+// references as in wasmtime, the rest (calls, bulk memory, externref tables) as in DummyFuncEnvironment
+struct WasmtimeFuncEnvironment<'a> {
+    inner: DummyFuncEnvironment<'a>,
+    option: &'a WasmtimeEnvOption,
+    heaps: PrimaryMap<Heap, HeapData>,
+}
+
+impl<'a> WasmtimeFuncEnvironment<'a> {
+    fn new(mod_info: &'a DummyModuleInfo, option: &'a WasmtimeEnvOption) -> Self {
+        WasmtimeFuncEnvironment {
+            inner: DummyFuncEnvironment::new(mod_info, None),
+            option,
+            heaps: PrimaryMap::new(),
+        }
+    }
+
+    fn get_memory(&self, index: MemoryIndex) -> &Memory {
+        &self.inner.mod_info.memories[index].entity
+    }
+
+    // VMMemoryDefinition, VMTableDefinition, and VMGlobalDefinition are either in vmctx
+    // (defined entities) or behind a pointer in vmctx (imported entities)
+    fn get_definition_ptr(&self, func: &mut ir::Function, index: usize, num_imported: usize) -> (ir::GlobalValue, i32) {
+        let vmctx = func.create_global_value(ir::GlobalValueData::VMContext);
+        if index < num_imported {
+            let from_offset = VMCTX_IMPORTS_BEGIN + (index as i32) * (self.pointer_bytes() as i32);
+            let ptr = func.create_global_value(ir::GlobalValueData::Load {
+                base: vmctx,
+                offset: Offset32::new(from_offset),
+                global_type: self.pointer_type(),
+                flags: MemFlags::trusted().with_readonly(),
+            });
+            (ptr, 0)
+        } else {
+            (vmctx, VMCTX_DEFINITIONS_BEGIN + (index as i32) * 0x10)
+        }
+    }
+
+    // MemoryPlan::for_memory of wasmtime: static if the whole memory fits in the reservation
+    fn is_static_memory(&self, memory: &Memory) -> bool {
+        let max_pages = match (memory.memory64, memory.maximum) {
+            (true, _) => return false,
+            (false, Some(maximum)) => maximum,
+            (false, None) => WASM32_MAX_PAGES,
+        };
+        max_pages * WASM_PAGE_SIZE <= self.option.static_memory_maximum_size
+    }
+
+    fn get_ir_type(&self, ty: &WasmValType) -> ir::Type {
+        match ty {
+            WasmValType::I32 => I32,
+            WasmValType::I64 => I64,
+            WasmValType::F32 => ir::types::F32,
+            WasmValType::F64 => ir::types::F64,
+            WasmValType::V128 => ir::types::I8X16,
+            WasmValType::Ref(rt) => self.reference_type(rt.heap_type),
+        }
+    }
+
+    fn is_func_ref_table(&self, index: TableIndex) -> bool {
+        matches!(self.inner.mod_info.tables[index].entity.wasm_ty.heap_type, WasmHeapType::Func | WasmHeapType::TypedFunc(_))
+    }
+
+    // signature of a builtin function, vmctx comes first
+    fn import_builtin_signature(&self, func: &mut ir::Function, params: &[ir::Type], returns: &[ir::Type]) -> ir::SigRef {
+        let mut sig = ir::Signature::new(self.target_config().default_call_conv);
+        sig.params.push(ir::AbiParam::special(self.pointer_type(), ir::ArgumentPurpose::VMContext));
+        sig.params.extend(params.iter().map(|ty| ir::AbiParam::new(*ty)));
+        sig.returns.extend(returns.iter().map(|ty| ir::AbiParam::new(*ty)));
+        func.import_signature(sig)
+    }
+
+    // translate_load_builtin_function_address of wasmtime: (vmctx, address of the builtin)
+    fn load_builtin_function_address(&self, pos: &mut FuncCursor, builtin_offset: i32) -> (ir::Value, ir::Value) {
+        let pointer_type = self.pointer_type();
+        let vmctx_gv = pos.func.create_global_value(ir::GlobalValueData::VMContext);
+        let vmctx = pos.ins().global_value(pointer_type, vmctx_gv);
+        let builtins = pos.ins().load(pointer_type, MemFlags::trusted().with_readonly(), vmctx, VMCTX_BUILTIN_FUNCTIONS);
+        let func_addr = pos.ins().load(pointer_type, MemFlags::trusted().with_readonly(), builtins, builtin_offset);
+        (vmctx, func_addr)
+    }
+
+    // funcref tables are lazily initialized; null entries are filled by a libcall
+    fn get_or_init_func_ref_table_elem(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        table_index: TableIndex,
+        table: ir::Table,
+        index: ir::Value,
+    ) -> ir::Value {
+        let pointer_type = self.pointer_type();
+        let table_entry_addr = builder.ins().table_addr(pointer_type, table, index, 0);
+        let value = builder.ins().load(pointer_type, MemFlags::trusted().with_table(), table_entry_addr, 0);
+        let value_masked = builder.ins().band_imm(value, Imm64::from(FUNCREF_MASK));
+
+        let null_block = builder.create_block();
+        let continuation_block = builder.create_block();
+        let result_param = builder.append_block_param(continuation_block, pointer_type);
+        builder.set_cold_block(null_block);
+        builder.ins().brif(value, continuation_block, &[value_masked], null_block, &[]);
+        builder.seal_block(null_block);
+
+        builder.switch_to_block(null_block);
+        let table_index = builder.ins().iconst(I32, table_index.index() as i64);
+        let builtin_sig = self.import_builtin_signature(builder.func, &[I32, I32], &[pointer_type]);
+        let (vmctx, builtin_addr) = self.load_builtin_function_address(&mut builder.cursor(), BUILTIN_TABLE_GET_LAZY_INIT_FUNC_REF);
+        let call_inst = builder.ins().call_indirect(builtin_sig, builtin_addr, &[vmctx, table_index, index]);
+        let returned_entry = builder.func.dfg.inst_results(call_inst)[0];
+        builder.ins().jump(continuation_block, &[returned_entry]);
+        builder.seal_block(continuation_block);
+
+        builder.switch_to_block(continuation_block);
+        result_param
+    }
+
+    fn cast_pointer_to_memory_index(&self, mut pos: FuncCursor, val: ir::Value, index: MemoryIndex) -> ir::Value {
+        if self.get_memory(index).memory64 {
+            val
+        } else {
+            pos.ins().ireduce(I32, val)
+        }
+    }
+}
+
+impl<'a> TypeConvert for WasmtimeFuncEnvironment<'a> {
+    fn lookup_heap_type(&self, index: UnpackedIndex) -> WasmHeapType {
+        self.inner.lookup_heap_type(index)
+    }
+}
+
+impl<'a> TargetEnvironment for WasmtimeFuncEnvironment<'a> {
+    fn target_config(&self) -> TargetFrontendConfig {
+        self.inner.target_config()
+    }
+
+    fn heap_access_spectre_mitigation(&self) -> bool {
+        self.option.spectre_mitigation
+    }
+
+    fn reference_type(&self, ty: WasmHeapType) -> ir::Type {
+        match ty {
+            WasmHeapType::Func | WasmHeapType::TypedFunc(_) => self.pointer_type(),
+            WasmHeapType::Extern => ir::types::R64,
+        }
+    }
+
+    fn proof_carrying_code(&self) -> bool {
+        false
+    }
+}
+
+impl<'a> FuncEnvironment for WasmtimeFuncEnvironment<'a> {
+    fn make_global(&mut self, func: &mut ir::Function, index: GlobalIndex) -> WasmResult<GlobalVariable> {
+        let ty = match self.inner.mod_info.globals[index].entity.wasm_ty {
+            WasmValType::I32 => I32,
+            WasmValType::I64 => I64,
+            WasmValType::F32 => ir::types::F32,
+            WasmValType::F64 => ir::types::F64,
+            WasmValType::V128 => ir::types::I8X16,
+            WasmValType::Ref(_) => return self.inner.make_global(func, index), // GC barriers are not modeled
+        };
+        let num_imported = self.inner.mod_info.imported_globals.len();
+        let (gv, offset) = self.get_definition_ptr(func, index.index(), num_imported);
+        Ok(GlobalVariable::Memory { gv, offset: Offset32::new(offset), ty })
+    }
+
+    fn heaps(&self) -> &PrimaryMap<Heap, HeapData> {
+        &self.heaps
+    }
+
+    fn make_heap(&mut self, func: &mut ir::Function, index: MemoryIndex) -> WasmResult<Heap> {
+        let memory = self.get_memory(index).clone();
+        let pointer_type = self.pointer_type();
+        let num_imported = self.inner.mod_info.imported_memories.len();
+        let (ptr, definition_offset) = self.get_definition_ptr(func, index.index(), num_imported);
+        let min_size = memory.minimum.saturating_mul(WASM_PAGE_SIZE);
+        let max_size = memory.maximum.and_then(|x| x.checked_mul(WASM_PAGE_SIZE));
+
+        let (offset_guard_size, style, readonly_base) = if self.is_static_memory(&memory) {
+            let style = HeapStyle::Static { bound: self.option.static_memory_maximum_size };
+            (self.option.static_memory_guard_size, style, true)
+        } else {
+            // the length (and the base, when moved on growth) is reloaded on each access
+            let bound_gv = func.create_global_value(ir::GlobalValueData::Load {
+                base: ptr,
+                offset: Offset32::new(definition_offset + VMMEMORY_DEFINITION_CURRENT_LENGTH),
+                global_type: pointer_type,
+                flags: MemFlags::trusted(),
+            });
+            (self.option.dynamic_memory_guard_size, HeapStyle::Dynamic { bound_gv }, false)
+        };
+
+        let mut base_flags = MemFlags::trusted();
+        if readonly_base && !memory.shared {
+            base_flags.set_readonly();
+        }
+        let base = func.create_global_value(ir::GlobalValueData::Load {
+            base: ptr,
+            offset: Offset32::new(definition_offset + VMMEMORY_DEFINITION_BASE),
+            global_type: pointer_type,
+            flags: base_flags,
+        });
+
+        Ok(self.heaps.push(HeapData {
+            base,
+            min_size,
+            max_size,
+            offset_guard_size,
+            style,
+            index_type: if memory.memory64 { I64 } else { I32 },
+            memory_type: None,
+        }))
+    }
+
+    fn make_table(&mut self, func: &mut ir::Function, index: TableIndex) -> WasmResult<ir::Table> {
+        let table = &self.inner.mod_info.tables[index].entity;
+        let min_size = table.minimum;
+        let is_fixed_size = table.maximum == Some(table.minimum);
+        let num_imported = self.inner.mod_info.imported_tables.len();
+        let (ptr, definition_offset) = self.get_definition_ptr(func, index.index(), num_imported);
+
+        // tables of fixed size are never reallocated
+        let mut base_flags = MemFlags::trusted();
+        if is_fixed_size {
+            base_flags.set_readonly();
+        }
+        let base_gv = func.create_global_value(ir::GlobalValueData::Load {
+            base: ptr,
+            offset: Offset32::new(definition_offset + VMTABLE_DEFINITION_BASE),
+            global_type: self.pointer_type(),
+            flags: base_flags,
+        });
+        let bound_gv = func.create_global_value(ir::GlobalValueData::Load {
+            base: ptr,
+            offset: Offset32::new(definition_offset + VMTABLE_DEFINITION_CURRENT_ELEMENTS),
+            global_type: I32,
+            flags: MemFlags::trusted(),
+        });
+
+        Ok(func.create_table(ir::TableData {
+            base_gv,
+            min_size: Uimm64::new(u64::from(min_size)),
+            bound_gv,
+            element_size: Uimm64::new(u64::from(self.pointer_bytes())),
+            index_type: I32,
+        }))
+    }
+
+    fn make_indirect_sig(&mut self, func: &mut ir::Function, index: TypeIndex) -> WasmResult<ir::SigRef> {
+        self.inner.make_indirect_sig(func, index)
+    }
+
+    fn make_direct_func(&mut self, func: &mut ir::Function, index: FuncIndex) -> WasmResult<ir::FuncRef> {
+        self.inner.make_direct_func(func, index)
+    }
+
+    fn translate_call(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        callee_index: FuncIndex,
+        callee: ir::FuncRef,
+        call_args: &[ir::Value],
+    ) -> WasmResult<ir::Inst> {
+        self.inner.translate_call(builder, callee_index, callee, call_args)
+    }
+
+    fn translate_call_indirect(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        table_index: TableIndex,
+        table: ir::Table,
+        sig_index: TypeIndex,
+        sig_ref: ir::SigRef,
+        callee: ir::Value,
+        call_args: &[ir::Value],
+    ) -> WasmResult<ir::Inst> {
+        self.inner.translate_call_indirect(builder, table_index, table, sig_index, sig_ref, callee, call_args)
+    }
+
+    fn translate_return_call_indirect(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        table_index: TableIndex,
+        table: ir::Table,
+        sig_index: TypeIndex,
+        sig_ref: ir::SigRef,
+        callee: ir::Value,
+        call_args: &[ir::Value],
+    ) -> WasmResult<()> {
+        self.inner.translate_return_call_indirect(builder, table_index, table, sig_index, sig_ref, callee, call_args)
+    }
+
+    fn translate_return_call_ref(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        sig_ref: ir::SigRef,
+        callee: ir::Value,
+        call_args: &[ir::Value],
+    ) -> WasmResult<()> {
+        self.inner.translate_return_call_ref(builder, sig_ref, callee, call_args)
+    }
+
+    fn translate_call_ref(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        sig_ref: ir::SigRef,
+        callee: ir::Value,
+        call_args: &[ir::Value],
+    ) -> WasmResult<ir::Inst> {
+        self.inner.translate_call_ref(builder, sig_ref, callee, call_args)
+    }
+
+    // libcall through the builtin function table of vmctx
+    fn translate_memory_grow(
+        &mut self,
+        mut pos: FuncCursor,
+        index: MemoryIndex,
+        _heap: Heap,
+        val: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        let pointer_type = self.pointer_type();
+        let func_sig = self.import_builtin_signature(pos.func, &[I64, I32], &[pointer_type]);
+
+        let memory_index = pos.ins().iconst(I32, index.index() as i64);
+        let (vmctx, func_addr) = self.load_builtin_function_address(&mut pos, BUILTIN_MEMORY32_GROW);
+
+        let val = if self.get_memory(index).memory64 { val } else { pos.ins().uextend(I64, val) };
+        let call_inst = pos.ins().call_indirect(func_sig, func_addr, &[vmctx, val, memory_index]);
+        let result = *pos.func.dfg.inst_results(call_inst).first().unwrap();
+        Ok(self.cast_pointer_to_memory_index(pos, result, index))
+    }
+
+    fn translate_memory_size(
+        &mut self,
+        mut pos: FuncCursor,
+        index: MemoryIndex,
+        _heap: Heap,
+    ) -> WasmResult<ir::Value> {
+        let pointer_type = self.pointer_type();
+        let num_imported = self.inner.mod_info.imported_memories.len();
+        let vmctx_gv = pos.func.create_global_value(ir::GlobalValueData::VMContext);
+        let vmctx = pos.ins().global_value(pointer_type, vmctx_gv);
+        let current_length = if index.index() < num_imported {
+            let from_offset = VMCTX_IMPORTS_BEGIN + (index.index() as i32) * (self.pointer_bytes() as i32);
+            let definition_ptr = pos.ins().load(pointer_type, MemFlags::trusted(), vmctx, from_offset);
+            pos.ins().load(pointer_type, MemFlags::trusted(), definition_ptr, VMMEMORY_DEFINITION_CURRENT_LENGTH)
+        } else {
+            let offset = VMCTX_DEFINITIONS_BEGIN + (index.index() as i32) * 0x10 + VMMEMORY_DEFINITION_CURRENT_LENGTH;
+            pos.ins().load(pointer_type, MemFlags::trusted(), vmctx, offset)
+        };
+        let current_pages = pos.ins().udiv_imm(current_length, WASM_PAGE_SIZE as i64);
+        Ok(self.cast_pointer_to_memory_index(pos, current_pages, index))
+    }
+
+    fn translate_memory_copy(
+        &mut self,
+        pos: FuncCursor,
+        src_index: MemoryIndex,
+        src_heap: Heap,
+        dst_index: MemoryIndex,
+        dst_heap: Heap,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()> {
+        self.inner.translate_memory_copy(pos, src_index, src_heap, dst_index, dst_heap, dst, src, len)
+    }
+
+    fn translate_memory_fill(
+        &mut self,
+        pos: FuncCursor,
+        index: MemoryIndex,
+        heap: Heap,
+        dst: ir::Value,
+        val: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()> {
+        self.inner.translate_memory_fill(pos, index, heap, dst, val, len)
+    }
+
+    fn translate_memory_init(
+        &mut self,
+        pos: FuncCursor,
+        index: MemoryIndex,
+        heap: Heap,
+        seg_index: u32,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()> {
+        self.inner.translate_memory_init(pos, index, heap, seg_index, dst, src, len)
+    }
+
+    fn translate_data_drop(&mut self, pos: FuncCursor, seg_index: u32) -> WasmResult<()> {
+        self.inner.translate_data_drop(pos, seg_index)
+    }
+
+    fn translate_table_size(&mut self, mut pos: FuncCursor, _index: TableIndex, table: ir::Table) -> WasmResult<ir::Value> {
+        let size_gv = pos.func.tables[table].bound_gv;
+        Ok(pos.ins().global_value(I32, size_gv))
+    }
+
+    fn translate_table_grow(
+        &mut self,
+        mut pos: FuncCursor,
+        table_index: TableIndex,
+        table: ir::Table,
+        delta: ir::Value,
+        init_value: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        // externref tables need GC barriers, which are not modeled
+        if !self.is_func_ref_table(table_index) {
+            return self.inner.translate_table_grow(pos, table_index, table, delta, init_value);
+        }
+        let pointer_type = self.pointer_type();
+        let func_sig = self.import_builtin_signature(pos.func, &[I32, I32, pointer_type], &[I32]);
+        let (vmctx, func_addr) = self.load_builtin_function_address(&mut pos, BUILTIN_TABLE_GROW_FUNC_REF);
+        let table_index_arg = pos.ins().iconst(I32, table_index.index() as i64);
+        let call_inst = pos.ins().call_indirect(func_sig, func_addr, &[vmctx, table_index_arg, delta, init_value]);
+        Ok(pos.func.dfg.first_result(call_inst))
+    }
+
+    fn translate_table_get(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        table_index: TableIndex,
+        table: ir::Table,
+        index: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        if !self.is_func_ref_table(table_index) {
+            return self.inner.translate_table_get(builder, table_index, table, index);
+        }
+        Ok(self.get_or_init_func_ref_table_elem(builder, table_index, table, index))
+    }
+
+    fn translate_table_set(
+        &mut self,
+        builder: &mut FunctionBuilder,
+        table_index: TableIndex,
+        table: ir::Table,
+        value: ir::Value,
+        index: ir::Value,
+    ) -> WasmResult<()> {
+        if !self.is_func_ref_table(table_index) {
+            return self.inner.translate_table_set(builder, table_index, table, value, index);
+        }
+        let pointer_type = self.pointer_type();
+        let table_entry_addr = builder.ins().table_addr(pointer_type, table, index, 0);
+        let value_with_init_bit = builder.ins().bor_imm(value, Imm64::from(FUNCREF_INIT_BIT));
+        builder.ins().store(MemFlags::trusted().with_table(), value_with_init_bit, table_entry_addr, 0);
+        Ok(())
+    }
+
+    fn translate_table_copy(
+        &mut self,
+        pos: FuncCursor,
+        dst_table_index: TableIndex,
+        dst_table: ir::Table,
+        src_table_index: TableIndex,
+        src_table: ir::Table,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()> {
+        self.inner.translate_table_copy(pos, dst_table_index, dst_table, src_table_index, src_table, dst, src, len)
+    }
+
+    fn translate_table_fill(
+        &mut self,
+        pos: FuncCursor,
+        table_index: TableIndex,
+        dst: ir::Value,
+        val: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()> {
+        self.inner.translate_table_fill(pos, table_index, dst, val, len)
+    }
+
+    fn translate_table_init(
+        &mut self,
+        pos: FuncCursor,
+        seg_index: u32,
+        table_index: TableIndex,
+        table: ir::Table,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()> {
+        self.inner.translate_table_init(pos, seg_index, table_index, table, dst, src, len)
+    }
+
+    fn translate_elem_drop(&mut self, pos: FuncCursor, seg_index: u32) -> WasmResult<()> {
+        self.inner.translate_elem_drop(pos, seg_index)
+    }
+
+    fn translate_ref_null(&mut self, mut pos: FuncCursor, ty: WasmHeapType) -> WasmResult<ir::Value> {
+        Ok(match ty {
+            WasmHeapType::Func | WasmHeapType::TypedFunc(_) => pos.ins().iconst(self.pointer_type(), 0),
+            WasmHeapType::Extern => pos.ins().null(self.reference_type(ty)),
+        })
+    }
+
+    fn translate_ref_is_null(&mut self, mut pos: FuncCursor, value: ir::Value) -> WasmResult<ir::Value> {
+        let is_null = match pos.func.dfg.value_type(value) {
+            ty if ty.is_ref() => pos.ins().is_null(value),
+            _ => pos.ins().icmp_imm(IntCC::Equal, value, 0), // funcref
+        };
+        Ok(pos.ins().uextend(I32, is_null))
+    }
+
+    fn translate_ref_func(&mut self, mut pos: FuncCursor, func_index: FuncIndex) -> WasmResult<ir::Value> {
+        let pointer_type = self.pointer_type();
+        let func_index = pos.ins().iconst(I32, func_index.as_u32() as i64);
+        let func_sig = self.import_builtin_signature(pos.func, &[I32], &[pointer_type]);
+        let (vmctx, func_addr) = self.load_builtin_function_address(&mut pos, BUILTIN_REF_FUNC);
+        let call_inst = pos.ins().call_indirect(func_sig, func_addr, &[vmctx, func_index]);
+        Ok(pos.func.dfg.first_result(call_inst))
+    }
+
+    fn translate_custom_global_get(&mut self, pos: FuncCursor, global_index: GlobalIndex) -> WasmResult<ir::Value> {
+        self.inner.translate_custom_global_get(pos, global_index)
+    }
+
+    fn translate_custom_global_set(&mut self, pos: FuncCursor, global_index: GlobalIndex, val: ir::Value) -> WasmResult<()> {
+        self.inner.translate_custom_global_set(pos, global_index, val)
+    }
+
+    fn translate_atomic_wait(
+        &mut self,
+        pos: FuncCursor,
+        index: MemoryIndex,
+        heap: Heap,
+        addr: ir::Value,
+        expected: ir::Value,
+        timeout: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        self.inner.translate_atomic_wait(pos, index, heap, addr, expected, timeout)
+    }
+
+    fn translate_atomic_notify(
+        &mut self,
+        pos: FuncCursor,
+        index: MemoryIndex,
+        heap: Heap,
+        addr: ir::Value,
+        count: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        self.inner.translate_atomic_notify(pos, index, heap, addr, count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_ast::{emitter, Instruction, MemoryArgument, MemoryInstruction, NumberType, TableInstruction};
+
+    use crate::wasm_comp::wrap_instruction_to_module;
+
+    use super::*;
+
+    fn translate_instr(instr: &Instruction, env: &TranslateEnv) -> ir::Function {
+        let module = wrap_instruction_to_module(instr).unwrap().remove(0);
+        let mut data: Vec<u8> = Vec::new();
+        let _ = emitter::emit_binary(&module, &mut data).unwrap();
+        translate_module_functions(&data, env).unwrap().remove(0)
+    }
+
+    fn has_opcode(func: &ir::Function, opcode: ir::Opcode) -> bool {
+        func.layout.blocks().flat_map(|block| func.layout.block_insts(block)).any(|inst| func.dfg.insts[inst].opcode() == opcode)
+    }
+
+    #[test]
+    fn test_translate_load_heaps() {
+        let instr: Instruction = MemoryInstruction::Load(NumberType::I32, MemoryArgument::default_offset(2)).into();
+
+        // static heaps need no bounds checks for 32-bit memories
+        let func = translate_instr(&instr, &TranslateEnv::default());
+        println!("{}", func.display());
+        assert!(!has_opcode(&func, ir::Opcode::SelectSpectreGuard));
+
+        // dynamic heaps compare the index with the bound, guarded against speculation
+        let dynamic_option = WasmtimeEnvOption { static_memory_maximum_size: 0, ..Default::default() };
+        let func = translate_instr(&instr, &TranslateEnv::Wasmtime(dynamic_option.clone()));
+        println!("{}", func.display());
+        assert!(has_opcode(&func, ir::Opcode::SelectSpectreGuard));
+
+        let no_spectre_option = WasmtimeEnvOption { spectre_mitigation: false, ..dynamic_option };
+        let func = translate_instr(&instr, &TranslateEnv::Wasmtime(no_spectre_option));
+        println!("{}", func.display());
+        assert!(!has_opcode(&func, ir::Opcode::SelectSpectreGuard));
+        assert!(has_opcode(&func, ir::Opcode::Trapnz) || has_opcode(&func, ir::Opcode::Trapz));
+    }
+
+    #[test]
+    fn test_translate_memory_size() {
+        let instr: Instruction = MemoryInstruction::Size.into();
+        let func = translate_instr(&instr, &TranslateEnv::default());
+        println!("{}", func.display());
+        assert!(has_opcode(&func, ir::Opcode::UdivImm));
+        assert!(has_opcode(&func, ir::Opcode::Ireduce));
+
+        let func = translate_instr(&instr, &TranslateEnv::Dummy);
+        assert!(!has_opcode(&func, ir::Opcode::UdivImm));
+    }
+
+    #[test]
+    fn test_translate_table_get() {
+        // funcref table entries are masked and lazily initialized
+        let instr: Instruction = TableInstruction::Get(0).into();
+        let func = translate_instr(&instr, &TranslateEnv::default());
+        println!("{}", func.display());
+        assert!(has_opcode(&func, ir::Opcode::TableAddr));
+        assert!(has_opcode(&func, ir::Opcode::BandImm));
+        assert!(has_opcode(&func, ir::Opcode::CallIndirect));
+
+        let func = translate_instr(&instr, &TranslateEnv::Dummy);
+        assert!(!has_opcode(&func, ir::Opcode::BandImm));
+    }
+}
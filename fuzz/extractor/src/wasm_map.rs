@@ -3,38 +3,104 @@
 use std::collections::HashMap;
 
 use anyhow::{Error, anyhow};
-use cranelift_wasm::{DummyEnvironment, DefinedFuncIndex, translate_module};
-use cranelift_codegen::{ir::{function::Function, Block, InstructionData}, isa::{TargetFrontendConfig, CallConv}, ir::{entities::Inst, instructions::Opcode, types::Type, condcodes::{IntCC, FloatCC}}};
-use target_lexicon::PointerWidth;
+use cranelift_codegen::{Context, settings, isa::{self, OwnedTargetIsa, TargetIsa}, entity::EntityRef, ir::{function::Function, Block, InstructionData, MemFlags, TrapCode, Value, ValueDef, ArgumentPurpose}, ir::{entities::Inst, instructions::Opcode, types::Type, condcodes::{IntCC, FloatCC}}};
+use serde::{Deserialize, Serialize};
+use target_lexicon::Triple;
 use wasm_ast::{Instruction, emitter};
 
-use crate::wasm_comp::{wrap_instruction_to_module, get_instr_iterator_no_control};
+use crate::wasm_env::{translate_module_functions, TranslateEnv};
+use crate::wasm_comp::{wrap_instruction_to_module, get_instr_iterator_no_control, get_branch_instrs, get_entity_instrs, get_instruction_type, get_dummy_module};
 
-/// Translates single instruction WASM module into CLIR (Cranelift IR)
-fn translate_wasm_module(data: &[u8]) -> Result<Function, Error> {
-    let mut dummy_environment = DummyEnvironment::new(TargetFrontendConfig {
-        default_call_conv: CallConv::SystemV,
-        pointer_width: PointerWidth::U64,
-    });
+/// Backend that WASM instructions are translated for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetArch {
+    X64,
+    Aarch64,
+    S390x,
+    Riscv64,
+}
+
+impl TargetArch {
+    pub const ALL: [TargetArch; 4] = [TargetArch::X64, TargetArch::Aarch64, TargetArch::S390x, TargetArch::Riscv64];
+
+    pub(crate) fn get_triple(&self) -> Triple {
+        let name = match self {
+            TargetArch::X64 => "x86_64-unknown-linux-gnu",
+            TargetArch::Aarch64 => "aarch64-unknown-linux-gnu",
+            TargetArch::S390x => "s390x-unknown-linux-gnu",
+            TargetArch::Riscv64 => "riscv64gc-unknown-linux-gnu",
+        };
+        name.parse().unwrap()
+    }
+}
+
+/// How WASM instructions are translated and mapped to Cranelift IR
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WasmMapOption {
+    pub strict_candidates: bool, // keep only the instructions with the smallest translation for each IR
+    pub nan_canonicalization: bool, // as the engine runs (cranelift_nan_canonicalization)
+    pub env: TranslateEnv, // environment that heaps, tables, and globals are translated with
+    pub target: Option<TargetArch>, // ISA that NaN canonicalization runs for; the host if None (e.g., rules of all backends)
+}
+
+impl Default for WasmMapOption {
+    fn default() -> Self {
+        WasmMapOption {
+            strict_candidates: false,
+            nan_canonicalization: true,
+            env: TranslateEnv::default(),
+            target: None,
+        }
+    }
+}
+
+// ISA to canonicalize NaNs for, built once per map; None without NaN canonicalization
+fn get_nan_canon_isa(option: &WasmMapOption) -> Result<Option<OwnedTargetIsa>, Error> {
+    if !option.nan_canonicalization {
+        return Ok(None);
+    }
+    let triple = option.target.map_or_else(Triple::host, |x| x.get_triple());
+    let builder = isa::lookup(triple.clone()).map_err(|e| anyhow!("No Cranelift backend for {}: {}", triple, e))?;
+    Ok(Some(builder.finish(settings::Flags::new(settings::builder()))?))
+}
 
-    match translate_module(data, &mut dummy_environment) {
-        Ok(_) => Ok(dummy_environment.info.function_bodies.get(DefinedFuncIndex::from_u32(0)).unwrap().clone()),
-        Err(error) => Err(anyhow!("translate_data failed with {:?}", error)),
+// Translated function; instructions from canon_start on are inserted by NaN canonicalization
+#[derive(Clone, Debug)]
+struct TranslatedFunction {
+    func: Function,
+    canon_start: usize,
+}
+
+/// Translates single instruction WASM module into CLIR (Cranelift IR), canonicalizing NaNs for the ISA if any
+fn translate_wasm_module(data: &[u8], option: &WasmMapOption, isa: Option<&dyn TargetIsa>) -> Result<TranslatedFunction, Error> {
+    let func = translate_module_functions(data, &option.env)?.remove(0);
+    let canon_start = func.dfg.num_insts();
+    let isa = match isa {
+        Some(x) => x,
+        None => return Ok(TranslatedFunction { func, canon_start }),
+    };
+
+    // the pass runs first in the optimizer, so it is a part of the IR that the rules see
+    let mut context = Context::for_function(func);
+    if let Err(error) = context.canonicalize_nans(isa) {
+        return Err(anyhow!("canonicalize_nans failed with {:?}", error));
     }
+    Ok(TranslatedFunction { func: context.func, canon_start })
 }
 
-fn translate_single_instr_module(instr: &Instruction) -> Result<Vec<Function>, Error> {
+fn translate_single_instr_module(instr: &Instruction, option: &WasmMapOption, isa: Option<&dyn TargetIsa>) -> Result<Vec<TranslatedFunction>, Error> {
     let module_vec = match wrap_instruction_to_module(instr) {
         Ok(modules) => modules,
         Err(error) => return Err(error),
     };
 
-    let mut function_vec: Vec<Function> = Vec::new();
+    let mut function_vec: Vec<TranslatedFunction> = Vec::new();
     for module in module_vec {
         let mut data: Vec<u8> = Vec::new();
         let _bin_size = emitter::emit_binary(&module, &mut data).unwrap();
         
-        match translate_wasm_module(data.as_slice()) {
+        match translate_wasm_module(data.as_slice(), option, isa) {
             Ok(function) => function_vec.push(function),
             Err(error) => return Err(error),
         }
@@ -59,27 +125,160 @@ pub struct IRData {
     pub opcode: Opcode,
     pub intcc: Option<IntCC>,
     pub floatcc: Option<FloatCC>,
+    pub memflags: Option<MemFlags>,
+    pub trap_code: Option<TrapCode>,
+    pub branch_dests: usize, // block calls and jump tables, not values
     pub operand_type: Option<IROperandType>,
     pub param_types: Vec<Type>,
     pub ret_type: Option<Type>,
+    pub env_params: Vec<usize>, // params computed from vmctx (e.g., global and table addresses), not from WASM values
 }
 
-fn postprocess_function(func: &Function) -> Vec<IRData> {
+impl IRData {
+    // loads take the address first, stores take the stored value first
+    pub fn get_addr_param_idx(&self) -> Option<usize> {
+        match self.memflags {
+            Some(_) if self.opcode.can_store() => Some(1),
+            Some(_) if self.opcode.can_load() => Some(0),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum IRDagInput {
+    Param(usize), // idx-th parameter of the WASM instruction
+    Node(usize), // result of an earlier node
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IRDagNode {
+    pub data: IRData,
+    pub inputs: Vec<IRDagInput>,
+    pub imm: Option<i128>, // operand fixed by the translation
+}
+
+// WASM instruction translated to multiple IRs; nodes are in topological order and the last one is the root
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IRDag {
+    pub nodes: Vec<IRDagNode>,
+    pub param_types: Vec<Type>,
+}
+
+impl IRDag {
+    pub fn get_root(&self) -> &IRDagNode {
+        self.nodes.last().unwrap()
+    }
+
+    // e.g., i32.eqz -> uextend(icmp_imm(p0))
+    pub fn get_name(&self) -> String {
+        fn node_name(dag: &IRDag, idx: usize) -> String {
+            let node = &dag.nodes[idx];
+            let inputs = node.inputs.iter().map(|input| match input {
+                IRDagInput::Param(param_idx) => format!("p{}", param_idx),
+                IRDagInput::Node(node_idx) => node_name(dag, *node_idx),
+            }).collect::<Vec<_>>();
+            format!("{}({})", node.data.opcode, inputs.join(","))
+        }
+        node_name(self, self.nodes.len() - 1)
+    }
+
+    // IRData of the whole DAG seen as a single instruction
+    pub fn get_summary(&self) -> IRData {
+        IRData {
+            opcode: self.get_root().data.opcode,
+            intcc: None,
+            floatcc: None,
+            memflags: None,
+            trap_code: None,
+            branch_dests: 0,
+            operand_type: None,
+            param_types: self.param_types.clone(),
+            ret_type: self.get_root().data.ret_type,
+            env_params: Vec::new(),
+        }
+    }
+}
+
+// WASM instruction mapped to an IR; rank is the number of extra IRs in its translation,
+// compared to the smallest translation mapped to the same IR (0 for the preferred ones)
+#[derive(Clone, Debug, PartialEq)]
+pub struct WasmCandidate {
+    pub instr: Instruction,
+    pub rank: usize,
+}
+
+impl WasmCandidate {
+    pub fn new(instr: Instruction) -> Self {
+        WasmCandidate { instr, rank: 0 }
+    }
+
+    // weight of the productions using this instruction
+    pub fn get_weight(&self) -> f64 {
+        1.0 / (1.0 + self.rank as f64)
+    }
+}
+
+fn get_imm_value(inst_data: &InstructionData) -> Option<i128> {
+    match inst_data {
+        InstructionData::BinaryImm8 { imm, .. } |
+        InstructionData::TernaryImm8 { imm, .. } => Some(*imm as i128),
+        InstructionData::BinaryImm64 { imm, .. } |
+        InstructionData::IntCompareImm { imm, .. } |
+        InstructionData::UnaryImm { imm, .. } => Some(imm.bits() as i128),
+        InstructionData::UnaryIeee32 { imm, .. } => Some(imm.bits() as i128),
+        InstructionData::UnaryIeee64 { imm, .. } => Some(imm.bits() as i128),
+        _ => None,
+    }
+}
+
+fn get_function_insts(func: &Function) -> Vec<Inst> {
     let mut insts: Vec<Inst> = Vec::new();
     for block in &func.layout {
         let mut block_insts = postprocess_block(func, &block);
         insts.append(&mut block_insts);
     };
-    
+    insts
+}
+
+fn postprocess_function(func: &Function) -> Vec<IRData> {
+    postprocess_insts(func, get_function_insts(func))
+}
+
+// Whether a value is derived from vmctx only, such as the address of a global or a builtin function
+fn is_env_value(func: &Function, value: Value) -> bool {
+    let value = func.dfg.resolve_aliases(value);
+    if Some(value) == func.special_param(ArgumentPurpose::VMContext) {
+        return true;
+    }
+    match func.dfg.value_def(value) {
+        ValueDef::Result(inst, _) => {
+            let inst_data = func.dfg.insts[inst];
+            let args = inst_data.arguments(&func.dfg.value_lists);
+            inst_data.opcode() == Opcode::GlobalValue || (!args.is_empty() && args.iter().all(|arg| is_env_value(func, *arg)))
+        },
+        _ => false,
+    }
+}
+
+fn postprocess_insts(func: &Function, insts: Vec<Inst>) -> Vec<IRData> {
     let mut irdata_vec:Vec<IRData> = Vec::new();
     for inst in insts {
         let inst_data = func.dfg.insts[inst.clone()];
         let opcode = inst_data.opcode();
         let intcc = inst_data.cond_code();
         let floatcc = inst_data.fp_cond_code();
+        let memflags = inst_data.memflags();
+        let trap_code = inst_data.trap_code();
+        let branch_dests = match inst_data {
+            InstructionData::Brif { .. } => 2,
+            InstructionData::BranchTable { .. } => 1,
+            _ => 0,
+        };
         let value_pool_ref = &func.dfg.value_lists;
         let inst_args = inst_data.arguments(value_pool_ref);
         let param_types = inst_args.into_iter().map(|arg| func.dfg.value_type(arg.clone())).collect();
+        let env_params = inst_args.iter().enumerate().filter(|(_, arg)| is_env_value(func, **arg)).map(|(idx, _)| idx).collect();
         let ret_type = func.dfg.inst_result_types(inst, func.dfg.ctrl_typevar(inst)).next();
         let operand_type = match inst_data {
             InstructionData::BinaryImm8 { .. } |
@@ -98,8 +297,8 @@ fn postprocess_function(func: &Function) -> Vec<IRData> {
             InstructionData::StackLoad { .. } |
             InstructionData::StackStore { .. } |
             InstructionData::Store { .. } |
-            InstructionData::StoreNoOffset { .. } |
-            // InstructionData::TableAddr { .. } => Some(IROperandType::Offset32), // remove this case for latest version (20.0.0)
+            InstructionData::StoreNoOffset { .. } => Some(IROperandType::Offset32),
+            InstructionData::TableAddr { .. } => Some(IROperandType::Offset32), // remove this case for latest version (20.0.0)
             
             InstructionData::UnaryConst {.. } => Some(IROperandType::Uimm32),
 
@@ -109,38 +308,106 @@ fn postprocess_function(func: &Function) -> Vec<IRData> {
             opcode,
             intcc,
             floatcc,
+            memflags,
+            trap_code,
+            branch_dests,
             operand_type,
             param_types,
             ret_type,
+            env_params,
         });
     }
     irdata_vec
 }
 
+// Build a DAG from a function translated to multiple IRs, following the def-use chains of the dfg
+// Returns None if the translation is not a single-rooted DAG over the WASM parameters
+fn postprocess_function_dag(func: &Function) -> Option<IRDag> {
+    let insts = get_function_insts(func);
+    if insts.len() < 2 {
+        return None;
+    }
+    let irdata_vec = postprocess_function(func);
+
+    // wasm parameters are the entry block parameters except vmctx
+    let entry_block = func.layout.entry_block()?;
+    let vmctx = func.special_param(ArgumentPurpose::VMContext);
+    let params = func.dfg.block_params(entry_block).iter().filter(|x| Some(**x) != vmctx).cloned().collect::<Vec<_>>();
+    let param_types = params.iter().map(|x| func.dfg.value_type(*x)).collect::<Vec<_>>();
+
+    let mut nodes: Vec<IRDagNode> = Vec::new();
+    let mut used_nodes = vec![false; insts.len()];
+    let mut used_params = vec![false; params.len()];
+    for (idx, (inst, data)) in insts.iter().zip(irdata_vec.into_iter()).enumerate() {
+        // memory accesses, traps, and branches are not pure nodes
+        if data.opcode.can_load() || data.opcode.can_store() || data.trap_code.is_some() || data.branch_dests > 0 {
+            return None;
+        }
+        let inst_data = func.dfg.insts[*inst];
+        let imm = get_imm_value(&inst_data);
+        if data.operand_type.is_some() && imm.is_none() {
+            return None;
+        }
+
+        let mut inputs = Vec::new();
+        for arg in inst_data.arguments(&func.dfg.value_lists) {
+            let arg = func.dfg.resolve_aliases(*arg);
+            let input = match func.dfg.value_def(arg) {
+                ValueDef::Result(def_inst, _) => {
+                    let def_idx = insts[..idx].iter().position(|x| *x == def_inst)?;
+                    used_nodes[def_idx] = true;
+                    IRDagInput::Node(def_idx)
+                },
+                _ => {
+                    let param_idx = params.iter().position(|x| *x == arg)?;
+                    used_params[param_idx] = true;
+                    IRDagInput::Param(param_idx)
+                },
+            };
+            inputs.push(input);
+        }
+        nodes.push(IRDagNode { data, inputs, imm });
+    }
+
+    // every node except the root feeds another node, and every parameter is consumed
+    let root_idx = nodes.len() - 1;
+    if used_nodes.iter().enumerate().any(|(idx, used)| idx != root_idx && !used) || used_nodes[root_idx] || used_params.contains(&false) {
+        return None;
+    }
+    Some(IRDag { nodes, param_types })
+}
+
 fn postprocess_block(func: &Function, block: &Block) -> Vec<Inst> {
     let mut insts: Vec<Inst> = Vec::new();
     for inst in func.layout.block_insts(block.clone()) {
         let inst_data = func.dfg.insts[inst];
-        if !inst_data.opcode().is_branch() && !inst_data.opcode().is_return() {
+        // global_value is the heap base of memory accesses, not a wasm value
+        // jumps only glue the blocks of structured control flow
+        let opcode = inst_data.opcode();
+        let is_cond_branch = opcode == Opcode::Brif || opcode == Opcode::BrTable;
+        if (is_cond_branch || !opcode.is_branch()) && !opcode.is_return() && opcode != Opcode::GlobalValue {
             insts.push(inst);
         }
     };
     insts
 }
 
-pub fn get_clir_wasm_map() -> HashMap<IRData, Vec<Instruction>> {
+pub fn get_clir_wasm_map(option: &WasmMapOption) -> HashMap<IRData, Vec<WasmCandidate>> {
     let mut clir_wasm_idx_map: HashMap<IRData, Vec<usize>> = HashMap::new();
     let mut wasm_idx_clir_map: HashMap<usize, Vec<IRData>> = HashMap::new();
     let mut idx_wasm_map: HashMap<usize, Instruction> = HashMap::new();
+    let isa = get_nan_canon_isa(option).unwrap();
 
     // construct maps
-    let instr_iter = get_instr_iterator_no_control();
+    let instr_iter = get_instr_iterator_no_control().chain(get_branch_instrs()).chain(get_entity_instrs());
     for (idx, instr) in instr_iter.enumerate() {
         idx_wasm_map.insert(idx, instr.clone());
 
-        let function_vec = translate_single_instr_module(&instr).unwrap();
+        let function_vec = translate_single_instr_module(&instr, option, isa.as_deref()).unwrap();
         for function in function_vec {
-            let irdata_vec = postprocess_function(&function);
+            // IRs inserted by NaN canonicalization are not mapped alone, but as a part of the DAG
+            let insts = get_function_insts(&function.func).into_iter().filter(|x| x.index() < function.canon_start).collect();
+            let irdata_vec = postprocess_insts(&function.func, insts);
             wasm_idx_clir_map.insert(idx, irdata_vec.clone());
             for irdata in irdata_vec {
                 let idx_vec = clir_wasm_idx_map.get_mut(&irdata);
@@ -155,46 +422,53 @@ pub fn get_clir_wasm_map() -> HashMap<IRData, Vec<Instruction>> {
         }
     }
     
-    // If an IRData corresponds to multiple instructions, prioritize instructions that translate
-    // to smaller number of IRs. (Heuristic)
-    // Remove instructions with low priority from the CLIR -> WASM map
-    let mut clir_wasm_map: HashMap<IRData, Vec<Instruction>> = HashMap::new();
+    // If an IRData corresponds to multiple instructions, rank them by the number of IRs they translate to:
+    // instructions translating to smaller number of IRs are preferred (Heuristic)
+    // With option.strict_candidates, low-priority instructions are removed from the CLIR -> WASM map
+    let mut clir_wasm_map: HashMap<IRData, Vec<WasmCandidate>> = HashMap::new();
     for (irdata, idx_vec) in clir_wasm_idx_map {
         assert!(idx_vec.len() > 0);
-        let instr_vec = idx_vec.clone().into_iter().map(|idx| idx_wasm_map.get(&idx).unwrap().clone()).collect();
-        if idx_vec.clone().len() == 1 {
-            clir_wasm_map.insert(irdata, instr_vec);
-            continue;
-        }
-
-        let ir_len_vec = idx_vec.clone().into_iter().map(|idx| wasm_idx_clir_map.get(&idx).unwrap().len());
-        let min_size = ir_len_vec.clone().min().unwrap();
-        let mut filtered_instr_vec = Vec::new();
-        for (idx, len) in ir_len_vec.enumerate() {
-            if len == min_size {
-                filtered_instr_vec.push(instr_vec.get(idx).unwrap().clone());
-            }
-        }
-        clir_wasm_map.insert(irdata, filtered_instr_vec);
+        let ir_len_vec = idx_vec.iter().map(|idx| wasm_idx_clir_map.get(idx).unwrap().len()).collect::<Vec<_>>();
+        let min_size = *ir_len_vec.iter().min().unwrap();
+        let mut candidates = idx_vec.iter().zip(ir_len_vec).map(|(idx, len)| WasmCandidate {
+            instr: idx_wasm_map.get(idx).unwrap().clone(),
+            rank: len - min_size,
+        }).filter(|x| !option.strict_candidates || x.rank == 0).collect::<Vec<_>>();
+        candidates.sort_by_key(|x| x.rank);
+        clir_wasm_map.insert(irdata, candidates);
     }
 
-    // Testing code for non-removal of low-priority instructions
-    /*
-    let mut clir_wasm_map: HashMap<IRData, Vec<Instruction>> = HashMap::new();
-    for (irdata, idx_vec) in clir_wasm_idx_map {
-        assert!(idx_vec.len() > 0);
-        let instr_vec = idx_vec.clone().into_iter().map(|idx| idx_wasm_map.get(&idx).unwrap().clone()).collect();
-        clir_wasm_map.insert(irdata, instr_vec);
-    }
-    */
-
     clir_wasm_map
 }
 
+// Map multi-IR translations as whole DAGs, so that their nodes are not matched one by one
+// Instructions with operands are left out, since their operands do not survive in the DAG
+pub fn get_clir_wasm_dag_map(option: &WasmMapOption) -> HashMap<IRDag, Vec<Instruction>> {
+    let dummy_module = get_dummy_module();
+    let mut clir_wasm_dag_map: HashMap<IRDag, Vec<Instruction>> = HashMap::new();
+    let isa = get_nan_canon_isa(option).unwrap();
+    for instr in get_instr_iterator_no_control().chain(get_entity_instrs()) {
+        match get_instruction_type(&dummy_module, 0, &instr) {
+            Ok(instr_types) if instr_types.iter().all(|x| !x.has_operand) => (),
+            _ => continue,
+        }
+        let function_vec = match translate_single_instr_module(&instr, option, isa.as_deref()) {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+        for function in function_vec {
+            if let Some(dag) = postprocess_function_dag(&function.func) {
+                clir_wasm_dag_map.entry(dag).or_default().push(instr.clone());
+            }
+        }
+    }
+    clir_wasm_dag_map
+}
+
 #[cfg(test)]
 mod test {
-    use wasm_ast::{NumericInstruction, Module, ValueType, Function, Expression, sections::emit_module, FunctionType, ResultType};
-
+    use wasm_ast::{NumericInstruction, Module, ValueType, Function, Expression, sections::emit_module, FunctionType, ResultType, VariableInstruction, ReferenceInstruction};
+    use cranelift_codegen::ir::types;
     use crate::wasm_comp::get_instr_iterator_no_control;
 
     use super::*;
@@ -219,33 +493,127 @@ mod test {
         let mut output = Vec::new();
         let _ = emit_module(&module, &mut output);
         println!("{:?}", output);
-        println!("{:#?}", translate_wasm_module(&output));
+        let option = WasmMapOption::default();
+        let isa = get_nan_canon_isa(&option).unwrap();
+        println!("{:#?}", translate_wasm_module(&output, &option, isa.as_deref()));
     }
 
     #[test]
     fn test_translate_single_instr_module() {
         let instr: Instruction = NumericInstruction::Multiply(wasm_ast::NumberType::I32).into();
-        println!("{:?}", translate_single_instr_module(&instr));
+        let option = WasmMapOption::default();
+        let isa = get_nan_canon_isa(&option).unwrap();
+        println!("{:?}", translate_single_instr_module(&instr, &option, isa.as_deref()));
     }
 
     #[test]
     fn test_translate_single_instr_module_enumerative() {
+        let option = WasmMapOption::default();
+        let isa = get_nan_canon_isa(&option).unwrap();
         let instr_iter = get_instr_iterator_no_control();
         for instr in instr_iter {
             println!("{}", "=".repeat(30));
             println!("{:?}", instr.clone());
-            let function_vec = translate_single_instr_module(&instr);
+            let function_vec = translate_single_instr_module(&instr, &option, isa.as_deref());
             println!("{:?}", &function_vec);
             for function in function_vec.unwrap() {
-                let insts = postprocess_function(&function);
+                let insts = postprocess_function(&function.func);
                 println!("{:#?}", insts);
             }
         }
     }
 
+    #[test]
+    fn test_get_clir_wasm_map_memory() {
+        let map = get_clir_wasm_map(&WasmMapOption::default());
+        let loads = map.iter().filter(|(irdata, _)| irdata.opcode == Opcode::Load).collect::<Vec<_>>();
+        println!("{:#?}", loads);
+        assert!(!loads.is_empty());
+        for (irdata, _) in loads {
+            assert_eq!(irdata.operand_type, Some(IROperandType::Offset32));
+            assert_eq!(irdata.get_addr_param_idx(), Some(0));
+        }
+    }
+
+    #[test]
+    fn test_get_clir_wasm_map_branch() {
+        let map = get_clir_wasm_map(&WasmMapOption::default());
+        let branches = map.iter().filter(|(irdata, _)| irdata.branch_dests > 0).collect::<Vec<_>>();
+        println!("{:#?}", branches);
+        assert!(branches.iter().any(|(irdata, _)| irdata.opcode == Opcode::Brif && irdata.param_types == vec![types::I32]));
+        assert!(branches.iter().any(|(irdata, _)| irdata.opcode == Opcode::BrTable));
+        assert!(!map.keys().any(|irdata| irdata.opcode == Opcode::Jump));
+    }
+
+    #[test]
+    fn test_get_clir_wasm_map_entity() {
+        let map = get_clir_wasm_map(&WasmMapOption::default());
+        let global_get: Instruction = VariableInstruction::GlobalGet(0).into();
+        let global_loads = map.iter().filter(|(_, candidates)| candidates.iter().any(|x| x.instr == global_get)).collect::<Vec<_>>();
+        println!("{:#?}", global_loads);
+        // the address of a global comes from vmctx, not from a wasm value
+        assert!(global_loads.iter().any(|(irdata, _)| irdata.opcode == Opcode::Load && irdata.env_params == vec![0]));
+
+        let is_null: Instruction = ReferenceInstruction::IsNull.into();
+        assert!(map.iter().any(|(irdata, candidates)| irdata.opcode == Opcode::IsNull && candidates.iter().any(|x| x.instr == is_null)));
+    }
+
     #[test]
     fn test_get_clir_wasm_map() {
-        let map = get_clir_wasm_map();
+        let map = get_clir_wasm_map(&WasmMapOption::default());
         println!("{:#?}", map);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_get_clir_wasm_map_candidates() {
+        let map = get_clir_wasm_map(&WasmMapOption::default());
+        let strict_map = get_clir_wasm_map(&WasmMapOption { strict_candidates: true, ..Default::default() });
+        assert_eq!(map.len(), strict_map.len());
+        for (irdata, candidates) in &map {
+            // preferred ones first, and they are exactly the strict ones
+            assert_eq!(candidates[0].rank, 0);
+            assert!(candidates.windows(2).all(|x| x[0].rank <= x[1].rank));
+            let preferred = candidates.iter().filter(|x| x.rank == 0).cloned().collect::<Vec<_>>();
+            assert_eq!(strict_map.get(irdata).unwrap(), &preferred);
+        }
+        println!("{:#?}", map.iter().filter(|(_, x)| x.iter().any(|y| y.rank > 0)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_get_clir_wasm_dag_map() {
+        let map = get_clir_wasm_dag_map(&WasmMapOption::default());
+        for (dag, instrs) in &map {
+            println!("{} -> {:?}", dag.get_name(), instrs);
+        }
+        let eqz: Instruction = NumericInstruction::EqualToZero(wasm_ast::IntegerType::I32).into();
+        let (dag, _) = map.iter().find(|(_, instrs)| instrs.contains(&eqz)).unwrap();
+        assert_eq!(dag.get_name(), "uextend(icmp_imm(p0))");
+        assert_eq!(dag.get_root().data.opcode, Opcode::Uextend);
+        assert_eq!(dag.nodes[0].imm, Some(0));
+    }
+
+    #[test]
+    fn test_translate_nan_canonicalization() {
+        let instr: Instruction = NumericInstruction::Add(wasm_ast::NumberType::F32).into();
+        let no_canon_option = WasmMapOption { nan_canonicalization: false, ..Default::default() };
+        assert!(get_nan_canon_isa(&no_canon_option).unwrap().is_none());
+        let function = translate_single_instr_module(&instr, &no_canon_option, None).unwrap().remove(0);
+        assert!(postprocess_function_dag(&function.func).is_none());
+
+        // per-ISA modes canonicalize for their backend, whatever the host is
+        let s390x_option = WasmMapOption { target: Some(TargetArch::S390x), ..Default::default() };
+        let isa = get_nan_canon_isa(&s390x_option).unwrap().unwrap();
+        assert_eq!(isa.triple().architecture, target_lexicon::Architecture::S390x);
+        let function = translate_single_instr_module(&instr, &s390x_option, Some(isa.as_ref())).unwrap().remove(0);
+        println!("{}", function.func.display());
+        let dag = postprocess_function_dag(&function.func).unwrap();
+        println!("{}", dag.get_name());
+        assert_eq!(dag.get_root().data.opcode, Opcode::Select);
+        assert!(dag.nodes.iter().any(|x| x.data.opcode == Opcode::Fadd));
+
+        // the canonicalization sequence is only mapped as a part of the DAG
+        let map = get_clir_wasm_map(&WasmMapOption::default());
+        assert!(map.iter().any(|(irdata, candidates)| irdata.opcode == Opcode::Fadd && candidates.iter().any(|x| x.instr == instr)));
+        assert!(!map.iter().any(|(irdata, candidates)| irdata.opcode == Opcode::Select && candidates.iter().any(|x| x.instr == instr)));
+    }
+}
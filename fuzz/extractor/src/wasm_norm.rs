@@ -2,18 +2,18 @@
 
 use wasm_ast::Instruction;
 
-use crate::{wasm_map::IRData, norm::{UnifiedExprIdx, LinVec, MatchResult, MatchStmt, UnifiedRule, UnifiedStmt}};
+use crate::{wasm_map::{IRData, IRDag, IRDagInput, WasmCandidate}, norm::{get_all_types, UnifiedExprIdx, LinVec, MatchResult, MatchStmt, UnifiedRule, UnifiedStmt}};
 
 // returns UnifiedRule
 // e.g., bxor: ty x y bxor -> bxor
 fn irdata_to_unifiedexpr(irdata: &IRData) -> Option<UnifiedRule> {
-    if irdata.ret_type.is_none() {
-        return None // WARNING: may need to change this if no-return is supported
-    }
-
     let name = irdata.opcode.to_string();
     let mut lhs_stmts = LinVec::new();
-    lhs_stmts.push(UnifiedStmt::TypeVar(vec![irdata.ret_type?]));
+    match irdata.ret_type {
+        Some(ret_type) => lhs_stmts.push(UnifiedStmt::TypeVar(vec![ret_type])),
+        // no-result instructions (e.g., stores, trapz) still take a typevar in lowering rules
+        None => lhs_stmts.push(UnifiedStmt::TypeVar(get_all_types().to_vec())),
+    }
 
     // IntCC or FloatCC -> second argument is CC
     let intcc_stmt = match irdata.intcc {
@@ -33,11 +33,26 @@ fn irdata_to_unifiedexpr(irdata: &IRData) -> Option<UnifiedRule> {
         None => None,
     };
 
+    // loads and stores take MemFlags first, e.g., load: ty flags addr offset
+    if irdata.memflags.is_some() {
+        lhs_stmts.push(UnifiedStmt::Var(Vec::new()));
+    }
+
     // operand takes a variable
     for _ in 0..((irdata.operand_type.is_some() as usize) + irdata.param_types.len()) {
         lhs_stmts.push(UnifiedStmt::Var(Vec::new()));
     }
 
+    // branch destinations come last, e.g., brif: ty c block_then block_else
+    for _ in 0..irdata.branch_dests {
+        lhs_stmts.push(UnifiedStmt::Var(Vec::new()));
+    }
+
+    // trap code comes last, e.g., trapz: ty c code
+    if irdata.trap_code.is_some() {
+        lhs_stmts.push(UnifiedStmt::Var(Vec::new()));
+    }
+
     let mut params = (0..lhs_stmts.len()).map(|x| UnifiedExprIdx::LHS(lhs_stmts.get_idx_ref(x).unwrap().clone())).collect::<Vec<_>>();
     lhs_stmts.push(UnifiedStmt::Expr { name: name.clone(), params: params.clone() });
     
@@ -58,7 +73,7 @@ fn irdata_to_unifiedexpr(irdata: &IRData) -> Option<UnifiedRule> {
     Some(UnifiedRule::new(lhs_stmts, rhs_stmts, false))
 }
 
-pub fn wasm_pair_to_matched_result(irdata: &IRData, instrs: &Vec<Instruction>) -> Option<MatchResult> {
+pub fn wasm_pair_to_matched_result(irdata: &IRData, instrs: &Vec<WasmCandidate>) -> Option<MatchResult> {
     let rule = irdata_to_unifiedexpr(irdata)?;
     let match_stmts = if rule.rhs.len() == 1 {
         vec![MatchStmt::Expr { data: irdata.clone(), instrs: instrs.clone() }]
@@ -70,15 +85,75 @@ pub fn wasm_pair_to_matched_result(irdata: &IRData, instrs: &Vec<Instruction>) -
     Some(matched_result)
 }
 
+// returns UnifiedRule whose LHS is the whole DAG and RHS is a single composite expr
+// e.g., i32.eqz: (uextend ty (icmp_imm ty IntCC.Equal x 0)) -> uextend(icmp_imm(p0)) ty x
+fn irdag_to_unifiedexpr(dag: &IRDag) -> Option<UnifiedRule> {
+    let mut lhs_stmts = LinVec::new();
+    let param_idxs = dag.param_types.iter()
+        .map(|_| UnifiedExprIdx::LHS(lhs_stmts.push(UnifiedStmt::Var(Vec::new()))))
+        .collect::<Vec<_>>();
+
+    // nodes follow the parameter layout of irdata_to_unifiedexpr
+    let mut node_idxs: Vec<UnifiedExprIdx> = Vec::new();
+    let mut root_typevar_idx = None;
+    for node in &dag.nodes {
+        let data = &node.data;
+        let mut params = Vec::new();
+        let typevar_stmt = match data.ret_type {
+            Some(ret_type) => UnifiedStmt::TypeVar(vec![ret_type]),
+            None => UnifiedStmt::TypeVar(get_all_types().to_vec()),
+        };
+        params.push(UnifiedExprIdx::LHS(lhs_stmts.push(typevar_stmt)));
+        root_typevar_idx = Some(params[0].clone());
+        if let Some(intcc) = data.intcc {
+            let cc_stmt = UnifiedStmt::Expr { name: format!("IntCC.{:?}", intcc), params: Vec::new() };
+            params.push(UnifiedExprIdx::LHS(lhs_stmts.push(cc_stmt)));
+        }
+        if let Some(floatcc) = data.floatcc {
+            let cc_stmt = UnifiedStmt::Expr { name: format!("FloatCC.{:?}", floatcc), params: Vec::new() };
+            params.push(UnifiedExprIdx::LHS(lhs_stmts.push(cc_stmt)));
+        }
+        if data.memflags.is_some() {
+            params.push(UnifiedExprIdx::LHS(lhs_stmts.push(UnifiedStmt::Var(Vec::new()))));
+        }
+        for input in &node.inputs {
+            params.push(match input {
+                IRDagInput::Param(idx) => param_idxs.get(*idx)?.clone(),
+                IRDagInput::Node(idx) => node_idxs.get(*idx)?.clone(),
+            });
+        }
+        // operands are fixed by the translation
+        if data.operand_type.is_some() {
+            params.push(UnifiedExprIdx::LHS(lhs_stmts.push(UnifiedStmt::Const(node.imm?))));
+        }
+        node_idxs.push(UnifiedExprIdx::LHS(lhs_stmts.push(UnifiedStmt::Expr { name: data.opcode.to_string(), params })));
+    }
+
+    let mut rhs_params = vec![root_typevar_idx?];
+    rhs_params.extend(param_idxs);
+    let mut rhs_stmts = LinVec::new();
+    rhs_stmts.push(UnifiedStmt::Expr { name: dag.get_name(), params: rhs_params });
+
+    Some(UnifiedRule::new(lhs_stmts, rhs_stmts, false))
+}
+
+pub fn wasm_dag_pair_to_matched_result(dag: &IRDag, instrs: &Vec<Instruction>) -> Option<MatchResult> {
+    let rule = irdag_to_unifiedexpr(dag)?;
+    let candidates = instrs.iter().map(|x| WasmCandidate::new(x.clone())).collect();
+    let match_stmts = vec![MatchStmt::Expr { data: dag.get_summary(), instrs: candidates }];
+    let matched_result = MatchResult::new_rev(rule, match_stmts);
+    Some(matched_result)
+}
+
 #[cfg(test)]
 mod test {
-    use crate::wasm_map::get_clir_wasm_map;
+    use crate::wasm_map::{get_clir_wasm_map, get_clir_wasm_dag_map, WasmMapOption};
 
     use super::*;
     
     #[test]
     fn test_irdata_to_unifiedexpr() {
-        let map = get_clir_wasm_map();
+        let map = get_clir_wasm_map(&WasmMapOption::default());
         for irdata in map.keys() {
             println!("{}", "=".repeat(30));
             println!("{:#?}", irdata);
@@ -88,10 +163,34 @@ mod test {
 
     #[test]
     fn test_wasm_pair_to_matched_result() {
-        let map = get_clir_wasm_map();
+        let map = get_clir_wasm_map(&WasmMapOption::default());
         for (irdata, instrs) in map {
             println!("{}", "=".repeat(30));
             println!("{:#?}", wasm_pair_to_matched_result(&irdata, &instrs));
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_irdata_to_unifiedexpr_noresult() {
+        let map = get_clir_wasm_map(&WasmMapOption::default());
+        for irdata in map.keys().filter(|x| x.ret_type.is_none()) {
+            println!("{}", "=".repeat(30));
+            println!("{:#?}", irdata);
+            let rule = irdata_to_unifiedexpr(irdata);
+            println!("{:#?}", rule);
+            assert!(rule.is_some());
+        }
+    }
+
+    #[test]
+    fn test_irdag_to_unifiedexpr() {
+        let map = get_clir_wasm_dag_map(&WasmMapOption::default());
+        for (dag, instrs) in map {
+            println!("{}", "=".repeat(30));
+            println!("{} -> {:?}", dag.get_name(), instrs);
+            let rule = irdag_to_unifiedexpr(&dag);
+            println!("{:#?}", rule);
+            assert!(rule.is_some());
+        }
+    }
+}